@@ -0,0 +1,138 @@
+// Adaptive supersampling: only the pixels on edges, where the escape iteration count changes
+// between neighboring pixels, are supersampled. Flat areas look the same with a single sample,
+// so this gives most of the quality of full supersampling for a fraction of the work.
+// With a sample budget instead of a threshold, the samples are spread over the pixels by how much
+// the escape count changes around them, so the boundary of the set gets the densest sampling.
+
+use crate::{build_mandelbrot_cpu, progress, render_iterations, render_rows, MandelbrotCpu};
+
+// Most sub-samples along each axis a pixel gets from a sample budget, so a few pixels can't use
+// up the whole budget
+pub const MAX_BUDGET_SAMPLES: usize = 16;
+
+// Returns for every pixel of a {width} pixels wide image whether it is on an edge: whether its
+// escape iteration count in {counts} differs by more than {threshold} from one of its four
+// neighbors
+pub fn edge_pixels(counts: &[u32], width: usize, threshold: u32) -> Vec<bool> {
+    let height = counts.len().checked_div(width).unwrap_or(0);
+    let mut edges = vec![false; counts.len()];
+    for row in 0..height {
+        for column in 0..width {
+            let index = row * width + column;
+            let right = (column + 1 < width).then_some(index + 1);
+            let below = (row + 1 < height).then_some(index + width);
+            // Mark both sides of every edge, so edges are refined the same on either side
+            for neighbor in [right, below].into_iter().flatten() {
+                if counts[index].abs_diff(counts[neighbor]) > threshold {
+                    edges[index] = true;
+                    edges[neighbor] = true;
+                }
+            }
+        }
+    }
+    edges
+}
+
+// Returns for every pixel of a {width} pixels wide image how complex the image is around it: the
+// log of one plus the largest difference between its escape iteration count in {counts} and
+// those of its four neighbors. The log keeps the huge jumps into the set from outweighing the
+// bands outside of it.
+pub fn complexity(counts: &[u32], width: usize) -> Vec<f64> {
+    let height = counts.len().checked_div(width).unwrap_or(0);
+    let mut largest = vec![0; counts.len()];
+    for row in 0..height {
+        for column in 0..width {
+            let index = row * width + column;
+            let right = (column + 1 < width).then_some(index + 1);
+            let below = (row + 1 < height).then_some(index + width);
+            for neighbor in [right, below].into_iter().flatten() {
+                let difference = counts[index].abs_diff(counts[neighbor]);
+                largest[index] = largest[index].max(difference);
+                largest[neighbor] = largest[neighbor].max(difference);
+            }
+        }
+    }
+    largest.iter().map(|&difference| (difference as f64).ln_1p()).collect()
+}
+
+// Spreads a {budget} of samples over the pixels by their {complexity}, returning the number of
+// sub-samples along each axis every pixel gets. Every pixel gets at least one sample, the rest
+// of the budget goes to the pixels in proportion to their complexity, and the samples of all
+// pixels (the squares of the returned numbers) never add up to more than the budget, unless the
+// budget is less than one sample per pixel.
+pub fn allocate_samples(complexity: &[f64], budget: usize) -> Vec<usize> {
+    let extra = budget.saturating_sub(complexity.len()) as f64;
+    let total: f64 = complexity.iter().sum();
+    complexity
+        .iter()
+        .map(|&complexity| {
+            if total == 0.0 {
+                return 1;
+            }
+            let samples = 1.0 + extra * complexity / total;
+            (samples.sqrt().floor() as usize).clamp(1, MAX_BUDGET_SAMPLES)
+        })
+        .collect()
+}
+
+// Returns the number of sub-samples along each axis every pixel gets: from the sample budget
+// (see allocate_samples) if there is one, otherwise options.samples for the pixels on edges (see
+// edge_pixels with options.supersample_threshold) and 1 for the others
+fn pixel_samples(options: &MandelbrotCpu) -> Vec<usize> {
+    let counts = render_iterations(options);
+    if let Some(budget) = options.sample_budget {
+        return allocate_samples(&complexity(&counts, options.image_width), budget);
+    }
+    let threshold = options.supersample_threshold.unwrap_or(u32::MAX);
+    edge_pixels(&counts, options.image_width, threshold)
+        .iter()
+        .map(|&edge| if edge { options.samples.max(1) } else { 1 })
+        .collect()
+}
+
+// Renders the image with a single sample per pixel, then supersamples the pixels on edges
+// (see edge_pixels with options.supersample_threshold), or spends the sample budget of
+// {options} on the pixels (see allocate_samples)
+pub fn build_mandelbrot_adaptive(options: &MandelbrotCpu) -> Vec<u8> {
+    let single_sample = MandelbrotCpu {
+        samples: 1,
+        ..options.clone()
+    };
+    let mut final_image = build_mandelbrot_cpu(&single_sample);
+    if options.sample_budget.is_none() && (options.supersample_threshold.is_none() || options.samples <= 1) {
+        return final_image;
+    }
+    let samples = pixel_samples(options);
+
+    let supersampled = samples.iter().filter(|&&samples| samples > 1).count();
+    progress::status(options.progress, &format!("\nSupersampling {} pixels...", supersampled));
+    let bytes_per_pixel = options.bytes_per_pixel();
+    render_rows(&mut final_image, options.image_width * bytes_per_pixel, options.threads, options.progress, |row, pixels| {
+        let mut pixel_options = options.clone();
+        for (j, pixel) in pixels.chunks_mut(bytes_per_pixel).enumerate() {
+            let samples = samples[row * options.image_width + j];
+            if samples > 1 {
+                let point = options.pixel_point(row, j);
+                pixel_options.iterations = options.iterations_at(row, j);
+                pixel_options.samples = samples;
+                pixel_options.render_pixel(point.real, point.imaginary, pixel);
+            }
+        }
+    });
+    final_image
+}
+
+// Returns the number of samples every pixel gets from build_mandelbrot_adaptive
+pub fn sample_counts(options: &MandelbrotCpu) -> Vec<u32> {
+    pixel_samples(options).iter().map(|&samples| (samples * samples) as u32).collect()
+}
+
+// Returns a grayscale image of the sample {counts} from sample_counts, where a pixel gets brighter
+// with more samples and full brightness is {samples} x {samples}
+pub fn sample_count_map(counts: &[u32], samples: usize) -> Vec<u8> {
+    let most = (samples * samples).max(1) as f64;
+    counts
+        .iter()
+        .map(|&count| ((count as f64 / most).min(1.0) * 255.0).round() as u8)
+        .collect()
+}
@@ -0,0 +1,31 @@
+// Transparency for compositing the set over other images. One side of the boundary of the set is
+// made transparent and the other opaque, which pixel is on which side is worked out again from
+// the view after the image is rendered, so it works with every engine and shading.
+
+// The part of the image --output-alpha makes transparent
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlphaRegion {
+    // The pixels in the set
+    Interior,
+    // The pixels outside of the set
+    Exterior,
+}
+
+// Returns the pixels of {image}, with {bytes_per_pixel} bytes per pixel, as RGBA pixels that are
+// transparent in {region} and opaque elsewhere. {inside} says for every pixel whether it's in the
+// set.
+pub fn with_alpha(image: &[u8], bytes_per_pixel: usize, inside: &[bool], region: AlphaRegion) -> Vec<[u8; 4]> {
+    image
+        .chunks_exact(bytes_per_pixel)
+        .zip(inside)
+        .map(|(pixel, &inside)| {
+            let transparent = inside == (region == AlphaRegion::Interior);
+            let alpha = if transparent { 0 } else { u8::MAX };
+            match pixel {
+                &[gray] => [gray, gray, gray, alpha],
+                _ => [pixel[0], pixel[1], pixel[2], alpha],
+            }
+        })
+        .collect()
+}
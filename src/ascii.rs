@@ -0,0 +1,132 @@
+// A live preview of the view as ASCII art in the terminal, for watching a render resolve over SSH.
+// The view is fitted to the terminal, one character per pixel, and rendered in passes of
+// increasing resolution and iteration count like --time-budget. Every pass redraws the previous
+// one in place.
+
+use crate::{build_mandelbrot_time_budget, pixel_offset, MandelbrotCpu};
+
+// Characters from white (outside of the set) to black (in the set)
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+// How many times as tall as wide a character in a terminal is
+const CELL_ASPECT: f64 = 2.0;
+
+// The terminal size when it can't be found out
+const DEFAULT_COLUMNS: usize = 80;
+const DEFAULT_ROWS: usize = 24;
+
+// Moves the cursor to the top left corner of the terminal
+const HOME: &str = "\x1b[H";
+// Clears the whole terminal
+const CLEAR: &str = "\x1b[2J";
+
+#[cfg(unix)]
+#[repr(C)]
+struct WindowSize {
+    rows: std::ffi::c_ushort,
+    columns: std::ffi::c_ushort,
+    _width: std::ffi::c_ushort,
+    _height: std::ffi::c_ushort,
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn ioctl(fd: std::ffi::c_int, request: std::ffi::c_ulong, ...) -> std::ffi::c_int;
+}
+
+// Returns the size of the terminal on stdout in columns and rows. COLUMNS and LINES override it,
+// and without a terminal it's DEFAULT_COLUMNS x DEFAULT_ROWS.
+pub fn terminal_size() -> (usize, usize) {
+    let from_env = |name: &str| std::env::var(name).ok().and_then(|value| value.parse().ok()).filter(|&n: &usize| n > 0);
+    if let (Some(columns), Some(rows)) = (from_env("COLUMNS"), from_env("LINES")) {
+        return (columns, rows);
+    }
+    #[cfg(unix)]
+    {
+        #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+        const TIOCGWINSZ: std::ffi::c_ulong = 0x40087468;
+        #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd")))]
+        const TIOCGWINSZ: std::ffi::c_ulong = 0x5413;
+        let mut size = WindowSize { rows: 0, columns: 0, _width: 0, _height: 0 };
+        if unsafe { ioctl(1, TIOCGWINSZ, &mut size as *mut WindowSize) } == 0 && size.rows > 0 && size.columns > 0 {
+            return (size.columns as usize, size.rows as usize);
+        }
+    }
+    (DEFAULT_COLUMNS, DEFAULT_ROWS)
+}
+
+// Returns the options for the view of {options} fitted in {columns} x {rows} characters, keeping
+// its aspect ratio with characters CELL_ASPECT times as tall as wide
+pub fn terminal_view(options: &MandelbrotCpu, columns: usize, rows: usize) -> MandelbrotCpu {
+    let offset = options.sample_offset();
+    let corner = pixel_offset(options, -offset, -offset);
+    let view_width = options.real_step * options.image_width as f64;
+    let view_height = options.i_step * options.image_height as f64;
+    let mut width = columns.max(1);
+    let mut height = ((view_height / view_width * width as f64 / CELL_ASPECT).round() as usize).max(1);
+    if height > rows.max(1) {
+        height = rows.max(1);
+        width = ((view_width / view_height * height as f64 * CELL_ASPECT).round() as usize).clamp(1, columns.max(1));
+    }
+    let fitted = MandelbrotCpu {
+        image_width: width,
+        image_height: height,
+        real_step: view_width / width as f64,
+        i_step: view_height / height as f64,
+        // The iteration map only fits the image size it was made for
+        iterations_map: None,
+        ..corner
+    };
+    pixel_offset(&fitted, offset, offset)
+}
+
+// Returns the lines of ASCII art for an {image} {width} pixels wide, with darker pixels as
+// denser characters
+pub fn to_ascii(image: &[u8], width: usize, bytes_per_pixel: usize) -> String {
+    image
+        .chunks(width * bytes_per_pixel)
+        .map(|row| {
+            row.chunks(bytes_per_pixel)
+                .map(|pixel| {
+                    let brightness = pixel.iter().map(|&channel| channel as usize).sum::<usize>() / bytes_per_pixel;
+                    RAMP[(255 - brightness) * (RAMP.len() - 1) / 255] as char
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Renders the view of {options} fitted in {columns} x {rows} characters with {render} in passes
+// of increasing resolution and iteration count, and calls {on_frame} with the ASCII art, the
+// scale and the iteration count of every pass. The last pass is at the full resolution of the
+// terminal with the full iteration count.
+pub fn build_ascii_frames(
+    options: &MandelbrotCpu,
+    render: fn(&MandelbrotCpu) -> Vec<u8>,
+    columns: usize,
+    rows: usize,
+    mut on_frame: impl FnMut(&str, usize, u32),
+) {
+    let view = terminal_view(options, columns, rows);
+    build_mandelbrot_time_budget(&view, render, std::time::Duration::MAX, |image, scale, iterations| {
+        on_frame(&to_ascii(image, view.image_width, view.bytes_per_pixel()), scale, iterations);
+    });
+}
+
+// Draws the passes of build_ascii_frames in place in the terminal, with a status line below
+pub fn preview_live(options: &MandelbrotCpu, render: fn(&MandelbrotCpu) -> Vec<u8>) {
+    // The renders print nothing without the progress, which would scroll the frames off
+    let options = MandelbrotCpu {
+        progress: false,
+        ..options.clone()
+    };
+    let (columns, rows) = terminal_size();
+    print!("{}", CLEAR);
+    // One row is kept for the status line
+    build_ascii_frames(&options, render, columns, rows.saturating_sub(1), |frame, scale, iterations| {
+        print!("{}{}\n1/{} resolution, {} iterations ", HOME, frame, scale, iterations);
+        std::io::Write::flush(&mut std::io::stdout()).unwrap();
+    });
+    println!();
+}
@@ -0,0 +1,34 @@
+// 1-bit output of the set, for renders that only need to know which pixels are in it. Every pixel
+// takes a single bit, which makes the image 8 times smaller than a grayscale one. The packed bits
+// are written as a binary PBM (portable bitmap) file.
+
+use std::io::Write;
+
+// Packs a grayscale {image} that is {width} pixels wide into bits, 8 pixels per byte with the
+// leftmost pixel in the highest bit, every row starting at a new byte. Dark pixels (the set) are
+// 1 and light ones 0, like PBM does.
+pub fn pack_mask(image: &[u8], width: usize) -> Vec<u8> {
+    if width == 0 {
+        return vec![];
+    }
+    let mut packed = vec![];
+    for row in image.chunks(width) {
+        for pixels in row.chunks(8) {
+            let mut byte = 0;
+            for (bit, &pixel) in pixels.iter().enumerate() {
+                if pixel < 128 {
+                    byte |= 0x80 >> bit;
+                }
+            }
+            packed.push(byte);
+        }
+    }
+    packed
+}
+
+// Writes a grayscale {image} to {out} as a binary PBM file. Gray pixels are rounded to black or
+// white.
+pub fn write_pbm(out: &mut impl Write, image: &[u8], width: usize, height: usize) -> std::io::Result<()> {
+    write!(out, "P4\n{} {}\n", width, height)?;
+    out.write_all(&pack_mask(image, width))
+}
@@ -0,0 +1,94 @@
+// The buddhabrot: instead of coloring every pixel by its own escape count, the orbits of points
+// that escape are traced, and every pixel counts how many orbits pass through it. The counts are
+// then scaled to a grayscale image. Only orbits that escape within a band of iteration counts are
+// traced, and rendering the bands of three renders as the channels of one image gives the
+// nebulabrot.
+
+use crate::{Complex, MandelbrotCpu};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// The square of the plane the starting points are taken from, which holds the whole set
+pub const SAMPLE_RADIUS: f64 = 2.0;
+
+// Returns the number of orbits passing through every pixel of {options}, row by row. The starting
+// points are the centers of a grid over the square of SAMPLE_RADIUS around the origin, with
+// {options.samples} x {options.samples} points for every pixel of the image. Only the orbits of
+// points that escape after at least {min_iterations} and less than options.iterations
+// iterations are traced.
+pub fn density(options: &MandelbrotCpu, min_iterations: u32) -> Vec<u32> {
+    let (width, height) = (options.image_width, options.image_height);
+    let (columns, rows) = (width * options.samples.max(1), height * options.samples.max(1));
+    let step = (2.0 * SAMPLE_RADIUS / columns as f64, 2.0 * SAMPLE_RADIUS / rows as f64);
+    let next_row = AtomicUsize::new(0);
+    let total = Mutex::new(vec![0u32; width * height]);
+
+    thread::scope(|scope| {
+        for _i in 0..options.threads.clamp(1, rows.max(1)) {
+            scope.spawn(|| {
+                let mut counts = vec![0u32; width * height];
+                // Grows to the longest orbit traced, --iterations can be far more than fits in memory
+                let mut orbit = Vec::new();
+                loop {
+                    let row = next_row.fetch_add(1, Ordering::Relaxed);
+                    if row >= rows {
+                        break;
+                    }
+                    let imaginary = SAMPLE_RADIUS - (row as f64 + 0.5) * step.1;
+                    for column in 0..columns {
+                        let point = Complex::new(-SAMPLE_RADIUS + (column as f64 + 0.5) * step.0, imaginary);
+                        trace_orbit(options, &point, min_iterations, &mut orbit, &mut counts);
+                    }
+                }
+                let mut total = total.lock().unwrap();
+                for (total, count) in total.iter_mut().zip(counts) {
+                    *total += count;
+                }
+            });
+        }
+    });
+
+    total.into_inner().unwrap()
+}
+
+// Adds the orbit of {point} to {counts} if it escapes within the band, using {orbit} to hold it
+// until it's known whether it does
+fn trace_orbit(options: &MandelbrotCpu, point: &Complex, min_iterations: u32, orbit: &mut Vec<Complex>, counts: &mut [u32]) {
+    orbit.clear();
+    let mut z = point.clone();
+    for _i in 0..options.iterations {
+        if z.has_escaped_radius(options.bailout_sq) {
+            break;
+        }
+        orbit.push(z.clone());
+        z.iterate(point);
+    }
+    let escaped = z.has_escaped_radius(options.bailout_sq);
+    if !escaped || (orbit.len() as u32) < min_iterations {
+        return;
+    }
+    for z in orbit.iter() {
+        let column = ((z.real - options.real_start) / options.real_step + options.sample_offset()).floor();
+        let row = ((options.i_start - z.imaginary) / options.i_step + options.sample_offset()).floor();
+        if column >= 0.0 && row >= 0.0 && (column as usize) < options.image_width && (row as usize) < options.image_height {
+            counts[row as usize * options.image_width + column as usize] += 1;
+        }
+    }
+}
+
+// Scales the {counts} from density to a grayscale image, with the square root of the count so
+// the faint orbits far from the set stay visible next to the bright ones
+pub fn density_image(counts: &[u32]) -> Vec<u8> {
+    let most = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+    counts
+        .iter()
+        .map(|&count| ((count as f64 / most).sqrt() * 255.0).round() as u8)
+        .collect()
+}
+
+// Renders the buddhabrot of {options} (see density) as a grayscale image
+pub fn build_buddhabrot(options: &MandelbrotCpu, min_iterations: u32) -> Vec<u8> {
+    println!("Tracing orbits...");
+    density_image(&density(options, min_iterations))
+}
@@ -0,0 +1,47 @@
+// Raw escape data for compositing: instead of colors, every pixel stores how and where it
+// escaped in the three float channels of an EXR file, so the coloring can be done afterwards in a
+// compositor.
+//   R: the iteration it escaped at, divided by the iteration count
+//   G: the modulus of the number when it escaped
+//   B: the angle of the number when it escaped, in turns from 0 up to 1 (see color::escape_angle)
+// Pixels in the set have the channels of INTERIOR.
+
+use crate::{color, progress, render_rows, MandelbrotCpu};
+use image::{DynamicImage, ImageBuffer, Rgb};
+use std::path::Path;
+
+// The channels of pixels in the set, an escape count no escaping pixel has
+pub const INTERIOR: [f32; 3] = [1.0, 0.0, 0.0];
+
+// Returns the escape channels of every pixel, row by row, 3 floats per pixel
+pub fn render_channels(options: &MandelbrotCpu) -> Vec<f32> {
+    let mut channels = vec![0.0; options.image_width * options.image_height * 3];
+
+    progress::status(options.progress, "Generating Image...");
+    render_rows(&mut channels, options.image_width * 3, options.threads, options.progress, |row, pixels| {
+        for (j, pixel) in pixels.chunks_mut(3).enumerate() {
+            let iterations = options.iterations_at(row, j);
+            let (start, constant) = options.orbit_start(&options.plane_point(&options.pixel_point(row, j)));
+            let values = match start.escape_point(constant.as_ref(), iterations, options.bailout_sq) {
+                Some((i, escaped)) => [
+                    i as f32 / iterations as f32,
+                    escaped.abs() as f32,
+                    (color::escape_angle(escaped.real, escaped.imaginary) / 360.0) as f32,
+                ],
+                None => INTERIOR,
+            };
+            pixel.copy_from_slice(&values);
+        }
+    });
+
+    channels
+}
+
+// Writes the {width} x {height} {channels} from render_channels to an EXR file at {path}
+pub fn write_exr(path: &Path, channels: &[f32], width: usize, height: usize) -> Result<(), String> {
+    let image: ImageBuffer<Rgb<f32>, Vec<f32>> = ImageBuffer::from_raw(width as u32, height as u32, channels.to_vec())
+        .ok_or_else(|| format!("{} channels don't make a {} x {} image", channels.len(), width, height))?;
+    DynamicImage::ImageRgb32F(image)
+        .save_with_format(path, image::ImageFormat::OpenExr)
+        .map_err(|error| format!("Couldn't write {:?}: {}", path, error))
+}
@@ -0,0 +1,271 @@
+// Helpers for working with pixel colors. Pixel values in the outputted image are sRGB
+// (gamma) encoded, so anything that mixes several of them together, like averaging the
+// sub-samples of a pixel, has to convert them to linear light first. Averaging the encoded
+// values directly makes edges come out too dark.
+
+// Converts an sRGB encoded channel value to linear light in the range [0, 1]
+pub fn srgb_to_linear(value: u8) -> f64 {
+    let c = value as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Converts a linear light value back to an sRGB encoded channel value. Values outside
+// of [0, 1] are clamped, so accumulated values can never wrap around.
+pub fn linear_to_srgb(value: f64) -> u8 {
+    let c = value.clamp(0.0, 1.0);
+    let s = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (s * 255.0).round() as u8
+}
+
+// The color spaces palette colors can be interpolated in
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorSpace {
+    // Straight between the encoded sRGB values, which makes the colors between two very
+    // different stops muddy and dark
+    Srgb,
+    // Through the perceptual OKLab space, where equal steps look like equal changes in color
+    Oklab,
+}
+
+// Converts an sRGB encoded {color} to OKLab: the lightness L from 0 to 1, and the a and b axes
+// (green to red and blue to yellow) around 0. See https://bottosson.github.io/posts/oklab/
+pub fn srgb_to_oklab(color: [u8; 3]) -> [f64; 3] {
+    let [r, g, b] = color.map(srgb_to_linear);
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+// Converts an OKLab color back to sRGB. Colors outside of sRGB are clamped channel by channel.
+pub fn oklab_to_srgb(lab: [f64; 3]) -> [u8; 3] {
+    let [lightness, a, b] = lab;
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+    .map(linear_to_srgb)
+}
+
+// Returns the color {fraction} of the way from {from} to {to}, interpolated in {space}
+pub fn interpolate(from: [u8; 3], to: [u8; 3], fraction: f64, space: ColorSpace) -> [u8; 3] {
+    let mix = |from: f64, to: f64| from + (to - from) * fraction;
+    match space {
+        ColorSpace::Srgb => {
+            let mut color = [0; 3];
+            for channel in 0..3 {
+                color[channel] = mix(from[channel] as f64, to[channel] as f64).round() as u8;
+            }
+            color
+        }
+        ColorSpace::Oklab => {
+            let (from, to) = (srgb_to_oklab(from), srgb_to_oklab(to));
+            oklab_to_srgb([mix(from[0], to[0]), mix(from[1], to[1]), mix(from[2], to[2])])
+        }
+    }
+}
+
+// Gamma corrects {color} by raising every channel, scaled to [0, 1], to the power 1/{gamma}.
+// A gamma of 1 leaves the color as it is, higher ones brighten the midtones.
+pub fn apply_gamma(color: [u8; 3], gamma: f64) -> [u8; 3] {
+    if gamma == 1.0 {
+        return color;
+    }
+    color.map(|c| ((c as f64 / 255.0).powf(1.0 / gamma) * 255.0).round() as u8)
+}
+
+// Averages a set of sRGB encoded channel values in linear light
+pub fn average_linear(samples: &[u8]) -> u8 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let total: f64 = samples.iter().map(|&s| srgb_to_linear(s)).sum();
+    linear_to_srgb(total / samples.len() as f64)
+}
+
+// How the pixels of the image are shaded
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shading {
+    // Black inside the mandelbrot set, white outside of it
+    Flat,
+    // A smooth escape gradient outside of the set, darkened with the distance to the set
+    Hybrid,
+    // A continuous rainbow outside of the set, see hsv_cycle
+    HsvCycle,
+    // Two shades outside of the set, depending on where the escaping number ended up,
+    // see binary_decomp_shade
+    BinaryDecomp,
+    // A smooth escape gradient outside of the set through the colors of a palette, see palette.rs
+    Palette,
+    // A hue outside of the set depending on the angle of the escaping number, see escape_angle
+    Angle,
+    // Gray inside and outside of the set depending on the average size of the number over the
+    // orbit, see orbit_average_shade
+    OrbitAverage,
+}
+
+impl Shading {
+    // Whether the shading only produces shades of gray
+    pub fn is_grayscale(&self) -> bool {
+        match self {
+            Shading::Flat | Shading::Hybrid | Shading::BinaryDecomp | Shading::OrbitAverage => true,
+            Shading::HsvCycle | Shading::Palette | Shading::Angle => false,
+        }
+    }
+}
+
+// Distance to the set, in pixels, at which hybrid shading has faded to half brightness
+const GLOW_WIDTH: f64 = 4.0;
+
+// Returns the brightness of an escaped pixel for hybrid shading. {smooth} is the smooth
+// iteration count at which the pixel escaped, {distance} the estimated distance to the set
+// and {pixel_size} the width of a pixel, both in mandelbrot space.
+pub fn hybrid_shade(smooth: f64, iterations: u32, distance: f64, pixel_size: f64) -> u8 {
+    let gradient = (smooth / iterations as f64).clamp(0.0, 1.0).sqrt();
+    let glow = 1.0 / (1.0 + distance / (GLOW_WIDTH * pixel_size));
+    (gradient * glow * 255.0).round() as u8
+}
+
+// Returns the brightness of a pixel for orbit average shading. {average} is the average absolute
+// value of the number over its orbit, which stays within the bailout radius sqrt({bailout_sq}).
+pub fn orbit_average_shade(average: f64, bailout_sq: f64) -> u8 {
+    ((average / bailout_sq.sqrt()).clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Fades {color} of a pixel that escaped at the smooth iteration count {smooth} towards the
+// black of the set, if it escaped within the last {clamp} of {iterations}. Without this, the
+// pixels that only just escape are drawn at full brightness right next to the set, which
+// leaves a halo where the iteration budget runs out.
+pub fn fade_to_interior(color: [u8; 3], smooth: f64, iterations: u32, clamp: u32) -> [u8; 3] {
+    if clamp == 0 {
+        return color;
+    }
+    let fade = ((smooth - (iterations as f64 - clamp as f64)) / clamp as f64).clamp(0.0, 1.0);
+    color.map(|c| (c as f64 * (1.0 - fade)).round() as u8)
+}
+
+// The shades binary decomposition gives numbers that escape into the upper and lower half plane
+pub const BINARY_DECOMP_SHADES: [u8; 2] = [u8::MAX, 128];
+
+// Returns the shade of an escaped pixel for binary decomposition. {imaginary} is the imaginary
+// part of the number when it escaped.
+pub fn binary_decomp_shade(imaginary: f64) -> u8 {
+    if imaginary >= 0.0 {
+        BINARY_DECOMP_SHADES[0]
+    } else {
+        BINARY_DECOMP_SHADES[1]
+    }
+}
+
+// Returns the angle of the number {real} + {imaginary}i at which a pixel escaped, in degrees
+// from 0 up to 360, to use as its hue
+pub fn escape_angle(real: f64, imaginary: f64) -> f64 {
+    // rem_euclid can round tiny negative angles up to 360 itself
+    let angle = imaginary.atan2(real).to_degrees().rem_euclid(360.0);
+    if angle >= 360.0 {
+        0.0
+    } else {
+        angle
+    }
+}
+
+// Returns the shade of a pixel in the set whose orbit reached at most {modulus}, from black for
+// orbits that stay at the origin to white for ones that reach the escape radius sqrt({bailout_sq})
+pub fn interior_modulus_shade(modulus: f64, bailout_sq: f64) -> u8 {
+    ((modulus / bailout_sq.sqrt()).clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Hue step between the colors of consecutive periods, the golden angle, so neighboring bulbs
+// never get similar colors
+const ATOM_HUE_STEP: f64 = 137.508;
+
+// Returns the color of a point in the set whose orbit ends up in a cycle of {period}, so
+// every period gets its own hue
+pub fn atom_domain_color(period: u32) -> [u8; 3] {
+    hsv_to_rgb((period.saturating_sub(1)) as f64 * ATOM_HUE_STEP, 0.8, 0.9)
+}
+
+// Converts a color in HSV to RGB. {hue} is in degrees, {saturation} and {value} range from 0 to 1
+pub fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let saturation = saturation.clamp(0.0, 1.0);
+    let value = value.clamp(0.0, 1.0);
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [
+        ((r + m) * 255.0).round() as u8,
+        ((g + m) * 255.0).round() as u8,
+        ((b + m) * 255.0).round() as u8,
+    ]
+}
+
+// Returns the color at {t} of a rainbow that goes through every hue {repeat} times as {t}
+// goes from 0 to 1. The rainbow wraps around seamlessly, so there is no banding.
+pub fn hsv_cycle(t: f64, repeat: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let hue = (t * repeat).rem_euclid(1.0) * 360.0;
+    hsv_to_rgb(hue, saturation, value)
+}
+
+// A color given on the command line as a hex code like "ff8000" or "#ff8000"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb(pub [u8; 3]);
+
+impl Rgb {
+    // The brightness of the color, for drawing it into grayscale images
+    pub fn luma(&self) -> u8 {
+        let [r, g, b] = self.0;
+        (0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64).round() as u8
+    }
+}
+
+impl std::str::FromStr for Rgb {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Rgb, String> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid color {:?}, expected a hex code like ff8000", text));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        Ok(Rgb([channel(0), channel(1), channel(2)]))
+    }
+}
+
+impl std::fmt::Display for Rgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+impl serde::Serialize for Rgb {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
@@ -0,0 +1,88 @@
+// Automatic framing: a coarse pass finds where the set is in the view, and the view is then
+// tightened around it, so the set fills the image without zooming in by hand.
+
+use crate::{Complex, MandelbrotCpu};
+
+// Number of samples along the longest axis of the coarse pass
+pub const CROP_GRID: usize = 128;
+
+// Fraction of the bounding box of the set added on every side, so the set doesn't touch the edges
+const CROP_MARGIN: f64 = 0.05;
+
+// Most iterations the coarse pass runs for, so it stays quick for huge budgets
+const MAX_CROP_ITERATIONS: u32 = 1000;
+
+// The part of mandelbrot space the set was found in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub real_min: f64,
+    pub real_max: f64,
+    pub imaginary_min: f64,
+    pub imaginary_max: f64,
+}
+
+// Returns the bounding box of the cells of a coarse grid over the view of {options} whose centers
+// are in the set, with {grid} cells along the longest axis. Returns None if none of them are.
+pub fn set_bounds(options: &MandelbrotCpu, grid: usize) -> Option<Bounds> {
+    let scale = options.image_width.max(options.image_height) as f64 / grid as f64;
+    let coarse = MandelbrotCpu {
+        image_width: ((options.image_width as f64 / scale).round() as usize).max(1),
+        image_height: ((options.image_height as f64 / scale).round() as usize).max(1),
+        iterations: options.iterations.min(MAX_CROP_ITERATIONS),
+        ..options.clone()
+    };
+    let real_step = options.real_step * options.image_width as f64 / coarse.image_width as f64;
+    let i_step = options.i_step * options.image_height as f64 / coarse.image_height as f64;
+
+    let mut bounds: Option<Bounds> = None;
+    for row in 0..coarse.image_height {
+        for column in 0..coarse.image_width {
+            let real = options.real_start + column as f64 * real_step;
+            let imaginary = options.i_start - row as f64 * i_step;
+            let center = Complex::new(real + real_step / 2.0, imaginary - i_step / 2.0);
+            if !coarse.contains(&center) {
+                continue;
+            }
+            let cell = Bounds {
+                real_min: real,
+                real_max: real + real_step,
+                imaginary_min: imaginary - i_step,
+                imaginary_max: imaginary,
+            };
+            bounds = Some(match bounds {
+                None => cell,
+                Some(bounds) => Bounds {
+                    real_min: bounds.real_min.min(cell.real_min),
+                    real_max: bounds.real_max.max(cell.real_max),
+                    imaginary_min: bounds.imaginary_min.min(cell.imaginary_min),
+                    imaginary_max: bounds.imaginary_max.max(cell.imaginary_max),
+                },
+            });
+        }
+    }
+    bounds
+}
+
+// Returns the view of {options} tightened around the set, keeping the shape of the pixels.
+// Returns None if the set isn't in the view, so there is nothing to tighten it around.
+pub fn crop_to_set(options: &MandelbrotCpu) -> Option<MandelbrotCpu> {
+    let bounds = set_bounds(options, CROP_GRID)?;
+    let width = (bounds.real_max - bounds.real_min) * (1.0 + 2.0 * CROP_MARGIN);
+    let height = (bounds.imaginary_max - bounds.imaginary_min) * (1.0 + 2.0 * CROP_MARGIN);
+    // Scale both axes the same, so the box fits the image along its tightest axis
+    let scale = (width / (options.real_step * options.image_width as f64))
+        .max(height / (options.i_step * options.image_height as f64));
+    let real_step = options.real_step * scale;
+    let i_step = options.i_step * scale;
+    let real_center = (bounds.real_min + bounds.real_max) / 2.0;
+    let imaginary_center = (bounds.imaginary_min + bounds.imaginary_max) / 2.0;
+    Some(MandelbrotCpu {
+        real_step,
+        i_step,
+        real_start: real_center - real_step * options.image_width as f64 / 2.0,
+        i_start: imaginary_center + i_step * options.image_height as f64 / 2.0,
+        real_start_lo: 0.0,
+        i_start_lo: 0.0,
+        ..options.clone()
+    })
+}
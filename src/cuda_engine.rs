@@ -0,0 +1,343 @@
+// Rendering on NVIDIA GPUs through CUDA, only built with the gpu feature. The kernels are compiled
+// for the GPU by the Rust GPU hybrid compiler, through the engine attribute at the top of
+// main.rs.
+
+use crate::device::DeviceInfo;
+use crate::launch::{LaunchConfig, THREADS_PER_BLOCK};
+use crate::{build_mandelbrot_cpu, hybrid, progress, Complex, MandelbrotCpu};
+use cuda::dmem::{Buffer, DSend};
+use cuda::gpu;
+use std::thread;
+
+// How long to wait before retrying a failed GPU launch, doubled for every next retry
+const GPU_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(50);
+
+// Errors from the GPU, with the operation (and the buffer or argument it was working on) that
+// failed. The cause is the error reported by cuda.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpuError {
+    Alloc { buffer: &'static str, cause: String },
+    ToDevice { argument: &'static str, cause: String },
+    Launch { cause: String },
+    Sync { cause: String },
+    Retrieve { buffer: &'static str, cause: String },
+}
+
+impl std::fmt::Display for GpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GpuError::Alloc { buffer, cause } => write!(f, "couldn't allocate the {} buffer on the GPU: {}", buffer, cause),
+            GpuError::ToDevice { argument, cause } => write!(f, "couldn't copy {} to the GPU: {}", argument, cause),
+            GpuError::Launch { cause } => write!(f, "couldn't launch the mandelbrot kernel: {}", cause),
+            GpuError::Sync { cause } => write!(f, "the GPU failed while rendering: {}", cause),
+            GpuError::Retrieve { buffer, cause } => write!(f, "couldn't retrieve the {} buffer from the GPU: {}", buffer, cause),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+// Whether there is a CUDA device to render on, checked by allocating a tiny buffer on it
+pub fn gpu_available() -> bool {
+    Buffer::<u8>::alloc(1).is_ok() && cuda::device_sync().is_ok()
+}
+
+// The CUDA driver API, for the properties of the device the context of cuda renders on
+#[link(name = "cuda")]
+extern "C" {
+    fn cuCtxGetDevice(device: *mut std::ffi::c_int) -> std::ffi::c_int;
+    fn cuDeviceGetName(name: *mut std::ffi::c_char, length: std::ffi::c_int, device: std::ffi::c_int) -> std::ffi::c_int;
+    fn cuDeviceGetAttribute(value: *mut std::ffi::c_int, attribute: std::ffi::c_int, device: std::ffi::c_int) -> std::ffi::c_int;
+    #[link_name = "cuDeviceTotalMem_v2"]
+    fn cuDeviceTotalMem(bytes: *mut usize, device: std::ffi::c_int) -> std::ffi::c_int;
+}
+
+// The CUdevice_attribute values of the properties in DeviceInfo
+const CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK: std::ffi::c_int = 1;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR: std::ffi::c_int = 75;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR: std::ffi::c_int = 76;
+
+// Returns the properties of the device the renders run on: the one of the context cuda sets up,
+// which allocating a buffer makes sure of
+pub fn device_info() -> Result<DeviceInfo, String> {
+    Buffer::<u8>::alloc(1).map_err(|error| format!("there is no usable CUDA device: {}", cause(error)))?;
+    let mut device = 0;
+    let result = unsafe { cuCtxGetDevice(&mut device) };
+    if result != 0 {
+        return Err(format!("couldn't find the device of the CUDA context (error {})", result));
+    }
+
+    let mut name = [0 as std::ffi::c_char; 256];
+    let result = unsafe { cuDeviceGetName(name.as_mut_ptr(), name.len() as std::ffi::c_int, device) };
+    if result != 0 {
+        return Err(format!("couldn't read the name of CUDA device {} (error {})", device, result));
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned();
+
+    let attribute = |attribute| {
+        let mut value = 0;
+        (unsafe { cuDeviceGetAttribute(&mut value, attribute, device) } == 0).then_some(value)
+    };
+    let compute_capability = attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)
+        .zip(attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR));
+    let mut memory_bytes = 0;
+    let memory_bytes = (unsafe { cuDeviceTotalMem(&mut memory_bytes, device) } == 0).then_some(memory_bytes);
+    Ok(DeviceInfo {
+        name,
+        compute_capability,
+        memory_bytes,
+        max_threads_per_block: attribute(CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK),
+    })
+}
+
+// Renders on the GPU, exiting with a clean message if the GPU fails
+pub fn build_mandelbrot_gpu_or_exit(options: &MandelbrotCpu) -> Vec<u8> {
+    build_mandelbrot_gpu(options).unwrap_or_else(|error| {
+        eprintln!("\nRendering on the GPU failed, {}", error);
+        std::process::exit(1);
+    })
+}
+
+// Renders the top rows on the GPU and the rest on the CPU at the same time, exiting with a
+// clean message if the GPU fails
+pub fn build_mandelbrot_hybrid_or_exit(options: &MandelbrotCpu) -> Vec<u8> {
+    hybrid::build_mandelbrot_hybrid(options, build_mandelbrot_gpu_or_exit, build_mandelbrot_cpu)
+}
+
+// Formats an error from cuda as the cause of a GpuError
+fn cause(error: impl std::fmt::Debug) -> String {
+    format!("{:?}", error)
+}
+
+// Calls {launch} until it succeeds, at most {retries} + 1 times. Before every retry it waits
+// {backoff}, which doubles every time. Returns the last error if every launch failed.
+pub fn launch_with_retries<E: std::fmt::Debug>(
+    retries: u32,
+    backoff: std::time::Duration,
+    mut launch: impl FnMut() -> Result<(), E>,
+) -> Result<(), GpuError> {
+    let mut wait = backoff;
+    let mut attempt = 0;
+    loop {
+        match launch() {
+            Ok(()) => return Ok(()),
+            Err(error) if attempt < retries => {
+                attempt += 1;
+                eprintln!("\nLaunching the kernel failed ({:?}), retry {} of {}", error, attempt, retries);
+                thread::sleep(wait);
+                wait *= 2;
+            }
+            Err(error) => return Err(GpuError::Launch { cause: cause(error) }),
+        }
+    }
+}
+
+// Returns a function that turns an error from copying {argument} to the GPU into a GpuError
+fn to_device_error<E: std::fmt::Debug>(argument: &'static str) -> impl FnOnce(E) -> GpuError {
+    move |error| GpuError::ToDevice { argument, cause: cause(error) }
+}
+
+
+#[kernel]
+fn mandelbrot_kernel(
+    mut image: Buffer<u8>,
+    offset: usize,
+    image_width: usize,
+    image_height: usize,
+    real_start: f64,
+    i_start: f64,
+    real_step: f64,
+    i_step: f64,
+    iterations: u32,
+    bailout_sq: f64,
+) {
+    let pos = offset + gpu::global_tid_x() as usize;
+    let i = pos / image_width;
+    let j = pos % image_width;
+
+    if i >= image_height {
+        return; // Out of bounds
+    }
+    
+    // compute x and y coordinates in mandelbrot space
+    let x = real_start + (j as f64 * real_step);
+    let y = i_start - (i as f64 * i_step);
+
+    // Create a complex number from the x and y coordinates
+    let point = Complex::new(x, y);
+    //If the point is stable, set the pixel to black (1), otherwise leave it white (0)
+    if point.is_stable_radius(iterations, bailout_sq) {
+        image.set(i * image_width + j, 0); // Set pixel to black
+    } else {
+        image.set(i * image_width + j, u8::MAX); // Leave pixel white
+    }
+   
+
+    // report progress TODO
+}
+
+pub fn build_mandelbrot_gpu_simple(options: &MandelbrotCpu) -> Result<Vec<u8>, GpuError> {
+    let MandelbrotCpu {
+        image_width,
+        image_height,
+        real_start,
+        i_start,
+        real_step,
+        i_step,
+        iterations,
+        bailout_sq,
+        ..
+    } = *options;
+    // prepare arguments for the kernel
+    let image_buffer: Buffer<u8> = Buffer::alloc(image_width * image_height)
+        .map_err(|error| GpuError::Alloc { buffer: "image", cause: cause(error) })?;
+    let blocks = (image_width * image_height).div_ceil(THREADS_PER_BLOCK);
+    launch_with_retries(options.gpu_retries, GPU_RETRY_BACKOFF, || {
+        mandelbrot_kernel
+            .launch(
+                THREADS_PER_BLOCK,
+                blocks,
+                image_buffer,
+                0,
+                image_width,
+                image_height,
+                real_start,
+                i_start,
+                real_step,
+                i_step,
+                iterations,
+                bailout_sq,
+            )
+            .map(|_| ())
+    })?;
+
+    progress::status(options.progress, "Waiting for GPU to finish...");
+    image_buffer
+        .retrieve()
+        .map_err(|error| GpuError::Retrieve { buffer: "image", cause: cause(error) })
+}
+
+
+pub fn build_mandelbrot_gpu(options: &MandelbrotCpu) -> Result<Vec<u8>, GpuError> {
+    build_mandelbrot_gpu_injecting(options, |_| Ok(()))
+}
+
+// Calculates the {pixels} of a flat image, counted row by row from the top left, the same way
+// mandelbrot_kernel does
+fn build_mandelbrot_cpu_pixels(options: &MandelbrotCpu, pixels: std::ops::Range<usize>) -> Vec<u8> {
+    pixels
+        .map(|pos| {
+            let (i, j) = (pos / options.image_width, pos % options.image_width);
+            let x = options.real_start + (j as f64 * options.real_step);
+            let y = options.i_start - (i as f64 * options.i_step);
+            if Complex::new(x, y).is_stable_radius(options.iterations, options.bailout_sq) {
+                0
+            } else {
+                u8::MAX
+            }
+        })
+        .collect()
+}
+
+// Same as build_mandelbrot_gpu, but calls {inject} with the offset of every chunk before
+// launching it, and treats an error it returns like a failed launch. Tests use this to make
+// chunks fail on purpose.
+pub fn build_mandelbrot_gpu_injecting(
+    options: &MandelbrotCpu,
+    inject: impl Fn(usize) -> Result<(), GpuError>,
+) -> Result<Vec<u8>, GpuError> {
+    let MandelbrotCpu {
+        image_width,
+        image_height,
+        real_step,
+        i_step,
+        real_start,
+        i_start,
+        iterations,
+        bailout_sq,
+        ..
+    } = *options;
+
+    let total = image_width * image_height;
+
+    let image_buffer: Buffer<u8> = Buffer::alloc(image_width * image_height)
+        .map_err(|error| GpuError::Alloc { buffer: "image", cause: cause(error) })?;
+    let launch = LaunchConfig::new(total, THREADS_PER_BLOCK);
+    let threads_per_block = launch.threads_per_block;
+    if options.verbose {
+        println!("{}", launch);
+    }
+
+    // convert arguments to dptr
+    let mut image_buffer_d = image_buffer.to_device().map_err(to_device_error("the image buffer"))?;
+    let mut image_width_d = image_width.to_device().map_err(to_device_error("image_width"))?;
+    let mut image_height_d = image_height.to_device().map_err(to_device_error("image_height"))?;
+    let mut real_start_d = real_start.to_device().map_err(to_device_error("real_start"))?;
+    let mut i_start_d = i_start.to_device().map_err(to_device_error("i_start"))?;
+    let mut real_step_d = real_step.to_device().map_err(to_device_error("real_step"))?;
+    let mut i_step_d = i_step.to_device().map_err(to_device_error("i_step"))?;
+    let mut iterations = iterations.to_device().map_err(to_device_error("iterations"))?;
+    let mut bailout_sq_d = bailout_sq.to_device().map_err(to_device_error("bailout_sq"))?;
+
+    // The chunks the GPU failed on, calculated on the CPU, see --gpu-cpu-fallback
+    let mut cpu_chunks = vec![];
+    let mut offset = 0;
+    while offset < total as usize {
+        // generate chucks of the mandelbrot set. The last chunk only gets the blocks it needs
+        // for the pixels that are left, the kernel skips the threads past the end of the image
+        let blocks = launch.blocks_at(offset);
+        let mut offset_d = offset.to_device().map_err(to_device_error("offset"))?;
+        
+        let launched = inject(offset).and_then(|_| launch_with_retries(options.gpu_retries, GPU_RETRY_BACKOFF, || {
+            mandelbrot_kernel
+                .launch_with_dptr(
+                    threads_per_block as usize,
+                    blocks,
+                    &mut image_buffer_d,
+                    &mut offset_d,
+                    &mut image_width_d,
+                    &mut image_height_d,
+                    &mut real_start_d,
+                    &mut i_start_d,
+                    &mut real_step_d,
+                    &mut i_step_d,
+                    &mut iterations,
+                    &mut bailout_sq_d,
+                )
+                .map(|_| ())
+        }));
+        let chunk = offset..(offset + blocks * threads_per_block).min(total);
+        offset += blocks * threads_per_block;
+        
+        progress::report(options.progress, offset.min(total), total);
+
+        if offset <= launch.offset_step {
+            // Sleep for a short time to allow the GPU to catch up
+            thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        // sync the device to ensure the kernel has finished executing
+        let finished = launched.and_then(|_| cuda::device_sync().map_err(|error| GpuError::Sync { cause: cause(error) }));
+        match finished {
+            Ok(()) => {}
+            Err(error) if options.gpu_cpu_fallback => {
+                eprintln!("\nThe GPU failed on pixels {} to {} ({}), calculating them on the CPU", chunk.start, chunk.end, error);
+                let pixels = build_mandelbrot_cpu_pixels(options, chunk.clone());
+                cpu_chunks.push((chunk, pixels));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    // calculate how much data needs to be retrieved in mb
+    let data_size = image_width * image_height;
+    progress::status(options.progress, &format!("\nRetrieving image of {:.2} MB", data_size as f64 / (1024.0 * 1024.0)));
+
+
+    let mut image = image_buffer
+        .retrieve()
+        .map_err(|error| GpuError::Retrieve { buffer: "image", cause: cause(error) })?;
+    for (chunk, pixels) in cpu_chunks {
+        image[chunk].copy_from_slice(&pixels);
+    }
+    Ok(image)
@@ -0,0 +1,91 @@
+// Estimates how many iterations a view actually needs. Far out of the set almost every point
+// escapes within a few dozen iterations, so a huge iteration budget only makes the points inside
+// the set take longer, without adding any detail.
+
+use crate::{render_rows, Complex, MandelbrotCpu};
+
+// Number of points along each axis of the grid of samples
+pub const DETAIL_GRID: usize = 32;
+
+// The fraction of escaping samples that have to have escaped within the estimate
+const ESCAPED_FRACTION: f64 = 0.99;
+
+// Most iterations a sample is run for, so the estimate stays quick for huge budgets
+const MAX_PROBE_ITERATIONS: u32 = 100_000;
+
+// The estimate is multiplied by this, so the points that escape just after it are kept too
+pub const SAFETY_FACTOR: u32 = 2;
+
+// The last part of the iteration budget budget_ring looks at
+const RING_FRACTION: f64 = 0.01;
+
+// Returns the iteration count within which 99% of the escaping points of a {grid} x {grid} grid
+// of samples over the view of {options} have escaped. Returns None if none of the samples escape,
+// in which case there's nothing to go on.
+pub fn estimate_sufficient_iterations(options: &MandelbrotCpu, grid: usize) -> Option<u32> {
+    let probe = MandelbrotCpu {
+        iterations: options.iterations.min(MAX_PROBE_ITERATIONS),
+        ..options.clone()
+    };
+    let mut escapes = vec![];
+    for point in sample_points(options, grid) {
+        if let Some(iteration) = probe.escape_iteration(&point, probe.iterations) {
+            escapes.push(iteration);
+        }
+    }
+    if escapes.is_empty() {
+        return None;
+    }
+    escapes.sort_unstable();
+    let index = ((escapes.len() as f64 * ESCAPED_FRACTION).ceil() as usize).clamp(1, escapes.len()) - 1;
+    Some(escapes[index].max(1))
+}
+
+// Returns the fraction of a {grid} x {grid} grid of samples over the view of {options} that is
+// in the set
+pub fn in_set_fraction(options: &MandelbrotCpu, grid: usize) -> f64 {
+    let inside = sample_points(options, grid).filter(|point| options.contains(point)).count();
+    inside as f64 / (grid * grid).max(1) as f64
+}
+
+// Returns a hint for views that are entirely outside or entirely inside of the set, which render
+// as a plain white or black image and leave beginners wondering what went wrong
+pub fn degenerate_view_hint(options: &MandelbrotCpu) -> Option<&'static str> {
+    let fraction = in_set_fraction(options, DETAIL_GRID);
+    if fraction == 0.0 {
+        Some("The entire view is outside of the set, so there is nothing to see. Try the default view, --center -0.5 0 --size 3 3")
+    } else if fraction == 1.0 {
+        Some("The entire view is inside of the set, so there is nothing to see. Try the default view, --center -0.5 0 --size 3 3, or increase --iterations if this view should be on the edge of the set")
+    } else {
+        None
+    }
+}
+
+// Returns for every pixel of the view of {options} whether it escaped within the last
+// RING_FRACTION of its iteration budget, at least the last iteration. A higher budget would show
+// more detail there, and the more of these pixels there are the more it's needed.
+pub fn budget_ring(options: &MandelbrotCpu) -> Vec<bool> {
+    let mut ring = vec![false; options.image_width * options.image_height];
+    render_rows(&mut ring, options.image_width, options.threads, options.progress, |row, pixels| {
+        for (column, pixel) in pixels.iter_mut().enumerate() {
+            let iterations = options.iterations_at(row, column);
+            let last = ((iterations as f64 * RING_FRACTION).round() as u32).max(1);
+            let point = options.plane_point(&options.pixel_point(row, column));
+            let escape = options.escape_iteration(&point, iterations);
+            *pixel = escape.is_some_and(|iteration| iteration + last >= iterations);
+        }
+    });
+    ring
+}
+
+// Returns the points in the plane at the centers of the cells of a {grid} x {grid} grid over the
+// view of {options}
+fn sample_points(options: &MandelbrotCpu, grid: usize) -> impl Iterator<Item = Complex> + '_ {
+    (0..grid).flat_map(move |sy| {
+        (0..grid).map(move |sx| {
+            let row = ((sy as f64 + 0.5) / grid as f64 * options.image_height as f64) as usize;
+            let column = ((sx as f64 + 0.5) / grid as f64 * options.image_width as f64) as usize;
+            options.plane_point(&options.pixel_point(row, column))
+        })
+    })
+}
@@ -0,0 +1,32 @@
+// The properties of the CUDA device, for --report-device-info. They're queried by
+// cuda_engine::device_info, this only formats them, so it's built and tested without the gpu
+// feature too.
+
+use crate::launch::THREADS_PER_BLOCK;
+
+// The properties of the device the renders run on. The ones the driver couldn't report are None
+// and left out of the report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    pub name: String,
+    // Major and minor version
+    pub compute_capability: Option<(i32, i32)>,
+    pub memory_bytes: Option<usize>,
+    pub max_threads_per_block: Option<i32>,
+}
+
+impl std::fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "CUDA device: {}", self.name)?;
+        if let Some((major, minor)) = self.compute_capability {
+            write!(f, "\nCompute capability: {}.{}", major, minor)?;
+        }
+        if let Some(bytes) = self.memory_bytes {
+            write!(f, "\nMemory: {} MiB", bytes / (1 << 20))?;
+        }
+        match self.max_threads_per_block {
+            Some(max) => write!(f, "\nMax threads per block: {} (rendering with {})", max, THREADS_PER_BLOCK),
+            None => write!(f, "\nRendering with {} threads per block", THREADS_PER_BLOCK),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// Double-double arithmetic: numbers stored as the unevaluated sum of two f64s, which gives
+// roughly 32 significant decimal digits instead of the 16 of a plain f64. This lets the CPU
+// zoom in to around 1e-30 without pulling in an arbitrary precision library, at the cost of
+// every operation taking a handful of f64 operations.
+// The algorithms are the classic error-free transformations by Dekker and Knuth, see
+// "Library for Double-Double and Quad-Double Arithmetic" by Hida, Li and Bailey.
+
+use crate::{progress, render_rows, MandelbrotCpu};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+// Returns a + b along with the rounding error of that sum
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+// Same as two_sum, but only correct if |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+// Returns a * b along with the rounding error of that product
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> DoubleDouble {
+        let (hi, lo) = quick_two_sum(hi, lo);
+        DoubleDouble { hi, lo }
+    }
+
+    // Multiplies by a plain f64, which is cheaper than a full double-double multiplication
+    pub fn mul_f64(self, other: f64) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other);
+        DoubleDouble::new(p, e + self.lo * other)
+    }
+
+    // Returns 10 to the power {exponent}
+    fn pow10(exponent: i32) -> DoubleDouble {
+        let mut result = DoubleDouble::from(1.0);
+        for _i in 0..exponent.unsigned_abs() {
+            result = result.mul_f64(10.0);
+        }
+        if exponent < 0 {
+            DoubleDouble::from(1.0) / result
+        } else {
+            result
+        }
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> DoubleDouble {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (s, e) = two_sum(self.hi, other.hi);
+        let (t, f) = two_sum(self.lo, other.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        DoubleDouble::new(s, e + f)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other.hi);
+        DoubleDouble::new(p, e + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = DoubleDouble;
+    fn div(self, other: DoubleDouble) -> DoubleDouble {
+        // Long division, one f64 worth of quotient at a time
+        let q1 = self.hi / other.hi;
+        let r = self - other.mul_f64(q1);
+        let q2 = r.hi / other.hi;
+        let r = r - other.mul_f64(q2);
+        let q3 = r.hi / other.hi;
+        DoubleDouble::new(q1, q2) + DoubleDouble::from(q3)
+    }
+}
+
+impl FromStr for DoubleDouble {
+    type Err = String;
+
+    // Parses a decimal number like "-0.7436438870371587047521915", optionally with an exponent
+    // like "1.5e-20", keeping all digits that fit in double-double precision
+    fn from_str(text: &str) -> Result<DoubleDouble, String> {
+        let invalid = || format!("invalid number: {}", text);
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(index) => (
+                &text[..index],
+                text[index + 1..].parse::<i32>().map_err(|_| invalid())?,
+            ),
+            None => (text, 0),
+        };
+        let (negative, digits) = match mantissa.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        if !digits.chars().any(|c| c.is_ascii_digit()) {
+            // Leave the things f64 understands but we don't, like "inf" and "NaN", to it
+            return text.parse::<f64>().map(DoubleDouble::from).map_err(|_| invalid());
+        }
+
+        let mut value = DoubleDouble::default();
+        let mut exponent = exponent;
+        let mut seen_point = false;
+        for c in digits.chars() {
+            match c.to_digit(10) {
+                Some(digit) => {
+                    value = value.mul_f64(10.0) + DoubleDouble::from(digit as f64);
+                    if seen_point {
+                        exponent -= 1;
+                    }
+                }
+                None if c == '.' && !seen_point => seen_point = true,
+                None => return Err(invalid()),
+            }
+        }
+
+        if exponent != 0 {
+            value = value * DoubleDouble::pow10(exponent);
+        }
+        Ok(if negative { -value } else { value })
+    }
+}
+
+// A coordinate given on the command line. Keeps the text it was parsed from next to its
+// double-double value, so it can be shown again exactly as it was given.
+#[derive(Debug, Clone)]
+pub struct Coordinate {
+    pub value: DoubleDouble,
+    text: String,
+}
+
+impl From<f64> for Coordinate {
+    fn from(value: f64) -> Coordinate {
+        Coordinate {
+            value: DoubleDouble::from(value),
+            text: value.to_string(),
+        }
+    }
+}
+
+impl FromStr for Coordinate {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Coordinate, String> {
+        let value: DoubleDouble = text.parse()?;
+        if !value.hi.is_finite() {
+            return Err(format!("the coordinate must be a finite number, not {}", text));
+        }
+        Ok(Coordinate {
+            value,
+            text: text.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Serialize for Coordinate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.value.hi)
+    }
+}
+
+// Runs the mandelbrot algorithm (see Complex::is_stable) in double-double precision for the
+// complex number {real} + {imaginary}i
+pub fn is_stable(real: DoubleDouble, imaginary: DoubleDouble, stable_iterations: u32, bailout_sq: f64) -> bool {
+    let mut z_real = real;
+    let mut z_imaginary = imaginary;
+    for _i in 0..stable_iterations {
+        let real_sq = z_real * z_real;
+        let imaginary_sq = z_imaginary * z_imaginary;
+        // Compare in full precision, the high part alone rounds points just inside the
+        // bailout radius onto it
+        if (real_sq + imaginary_sq - DoubleDouble::from(bailout_sq)).hi >= 0.0 {
+            return false;
+        }
+        let next_real = real_sq - imaginary_sq + real;
+        z_imaginary = (z_real * z_imaginary).mul_f64(2.0) + imaginary;
+        z_real = next_real;
+    }
+    true
+}
+
+// Renders the image with flat shading, doing all calculations in double-double precision
+pub fn build_mandelbrot_double_double(options: &MandelbrotCpu) -> Vec<u8> {
+    let real_start = DoubleDouble::new(options.real_start, options.real_start_lo);
+    let i_start = DoubleDouble::new(options.i_start, options.i_start_lo);
+    let real_step = DoubleDouble::from(options.real_step);
+    let i_step = DoubleDouble::from(options.i_step);
+
+    let mut final_image = vec![u8::MAX; options.image_width * options.image_height];
+
+    progress::status(options.progress, "Generating Image...");
+    render_rows(&mut final_image, options.image_width, options.threads, options.progress, |row, pixels| {
+        // Calculate every coordinate from the start, adding up the steps would throw away the
+        // precision we're after
+        let y = i_start - i_step.mul_f64(row as f64);
+        for (j, pixel) in pixels.iter_mut().enumerate() {
+            let x = real_start + real_step.mul_f64(j as f64);
+            *pixel = if is_stable(x, y, options.iterations, options.bailout_sq) {
+                0
+            } else {
+                u8::MAX
+            };
+        }
+    });
+
+    final_image
+}
@@ -0,0 +1,102 @@
+// The encoders that write the outputted image, picked by the extension of the file. The formats
+// of the image crate and the 1-bit PBM files of bitmap.rs are registered by default.
+
+use crate::renderer::RenderError;
+use crate::{bitmap, has_extension};
+use image::ColorType;
+use std::io::Write;
+use std::path::Path;
+
+// The extensions of the formats the image crate writes
+const IMAGE_EXTENSIONS: [&str; 17] = [
+    "png", "jpg", "jpeg", "gif", "ico", "bmp", "pnm", "pgm", "ppm", "pam", "tif", "tiff", "tga", "exr", "webp", "qoi", "ff",
+];
+
+pub trait OutputEncoder {
+    // The extensions of the files the encoder writes, without the dot
+    fn extensions(&self) -> &[&str];
+
+    // Writes the {width} x {height} {pixels}, with the channels of {color_type}, to {path}
+    fn encode(&self, path: &Path, pixels: &[u8], width: usize, height: usize, color_type: ColorType) -> Result<(), RenderError>;
+}
+
+// Writes every format of the image crate
+pub struct ImageEncoder;
+
+impl OutputEncoder for ImageEncoder {
+    fn extensions(&self) -> &[&str] {
+        &IMAGE_EXTENSIONS
+    }
+
+    fn encode(&self, path: &Path, pixels: &[u8], width: usize, height: usize, color_type: ColorType) -> Result<(), RenderError> {
+        image::save_buffer(path, pixels, width as u32, height as u32, color_type).map_err(|error| match error {
+            image::ImageError::IoError(cause) => RenderError::Io { path: path.to_path_buf(), cause: cause.to_string() },
+            error => RenderError::Encode { path: path.to_path_buf(), cause: error.to_string() },
+        })
+    }
+}
+
+// Writes grayscale images with one bit per pixel, see bitmap.rs
+pub struct PbmEncoder;
+
+impl OutputEncoder for PbmEncoder {
+    fn extensions(&self) -> &[&str] {
+        &["pbm"]
+    }
+
+    fn encode(&self, path: &Path, pixels: &[u8], width: usize, height: usize, _color_type: ColorType) -> Result<(), RenderError> {
+        let io_error = |cause: std::io::Error| RenderError::Io { path: path.to_path_buf(), cause: cause.to_string() };
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path).map_err(io_error)?);
+        bitmap::write_pbm(&mut file, pixels, width, height)
+            .and_then(|_| file.flush())
+            .map_err(io_error)
+    }
+}
+
+pub struct EncoderRegistry {
+    encoders: Vec<Box<dyn OutputEncoder>>,
+}
+
+impl EncoderRegistry {
+    // Creates a registry without any encoders
+    pub fn new() -> EncoderRegistry {
+        EncoderRegistry { encoders: vec![] }
+    }
+
+    // Adds {encoder} to the registry. If several encoders write the same extension, the one
+    // registered last is used, so registering an encoder replaces the default one.
+    pub fn register(&mut self, encoder: impl OutputEncoder + 'static) {
+        self.encoders.push(Box::new(encoder));
+    }
+
+    // Returns the encoder for the extension of {path}, in any case
+    pub fn encoder_for(&self, path: &Path) -> Option<&dyn OutputEncoder> {
+        self.encoders
+            .iter()
+            .rev()
+            .find(|encoder| encoder.extensions().iter().any(|extension| has_extension(path, extension)))
+            .map(|encoder| encoder.as_ref())
+    }
+
+    // Writes {pixels} to {path} with the encoder for its extension
+    pub fn encode(&self, path: &Path, pixels: &[u8], width: usize, height: usize, color_type: ColorType) -> Result<(), RenderError> {
+        let encoder = self.encoder_for(path).ok_or_else(|| RenderError::Encode {
+            path: path.to_path_buf(),
+            cause: format!(
+                "there's no encoder for its extension, the extensions are {}",
+                self.encoders.iter().flat_map(|encoder| encoder.extensions()).copied().collect::<Vec<&str>>().join(", ")
+            ),
+        })?;
+        encoder.encode(path, pixels, width, height, color_type)
+    }
+}
+
+impl Default for EncoderRegistry {
+    // The image crate's formats and PBM files
+    fn default() -> EncoderRegistry {
+        let mut registry = EncoderRegistry::new();
+        registry.register(ImageEncoder);
+        registry.register(PbmEncoder);
+        registry
+    }
+}
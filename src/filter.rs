@@ -0,0 +1,69 @@
+// Downsample filters for supersampling, which decide how the sub-samples of a pixel are weighed
+// when they're combined into the final pixel. Every filter has a radius in pixels around the
+// center of the pixel, and the sub-samples are spread evenly over that radius, so wider filters
+// also take the surroundings of the pixel into account.
+
+use std::f64::consts::PI;
+
+// The downsample filters --downsample-filter accepts
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DownsampleFilter {
+    // A plain average of the sub-samples inside the pixel
+    Box,
+    // Weighs sub-samples less the further they are from the center, slightly smoother than box
+    Tent,
+    // A windowed sinc, which keeps edges sharp without aliasing
+    Lanczos,
+}
+
+// Number of lobes of the lanczos filter
+const LANCZOS_LOBES: f64 = 2.0;
+
+// The normalized sinc function, sin(pi x) / (pi x)
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+impl DownsampleFilter {
+    // Distance in pixels from the center of a pixel to the edge of the filter
+    pub fn radius(&self) -> f64 {
+        match self {
+            DownsampleFilter::Box => 0.5,
+            DownsampleFilter::Tent => 1.0,
+            DownsampleFilter::Lanczos => LANCZOS_LOBES,
+        }
+    }
+
+    // The weight of a sub-sample {x} pixels away from the center, before normalizing
+    pub fn weight(&self, x: f64) -> f64 {
+        if x.abs() >= self.radius() && *self != DownsampleFilter::Box {
+            return 0.0;
+        }
+        match self {
+            DownsampleFilter::Box => 1.0,
+            DownsampleFilter::Tent => 1.0 - x.abs() / self.radius(),
+            DownsampleFilter::Lanczos => sinc(x) * sinc(x / LANCZOS_LOBES),
+        }
+    }
+
+    // Returns {samples} sub-samples along one axis, as their position relative to the top left
+    // of the pixel (in pixels) together with their weight. The weights add up to 1.
+    pub fn samples(&self, samples: usize) -> Vec<(f64, f64)> {
+        let radius = self.radius();
+        let positions: Vec<f64> = (0..samples)
+            .map(|s| (s as f64 + 0.5) / samples as f64 * (2.0 * radius) + (0.5 - radius))
+            .collect();
+        let weights: Vec<f64> = positions.iter().map(|&position| self.weight(position - 0.5)).collect();
+        let total: f64 = weights.iter().sum();
+        positions
+            .into_iter()
+            .zip(weights)
+            .map(|(position, weight)| (position, weight / total))
+            .collect()
+    }
+}
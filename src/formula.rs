@@ -0,0 +1,395 @@
+// Iteration formulas given on the command line, like "z^2 + c" or "z*z + sin(c)". The formula is
+// parsed into a tree once and compiled into nested closures, which the escape time loop calls on
+// every iteration. That is several times slower than the built-in iteration, which the compiler
+// can optimize as a whole, so this is an experimental mode for trying out formulas.
+//
+// A formula is made of the variables z and c, the imaginary unit i, real numbers, + - * / and ^,
+// parentheses and the functions in FUNCTIONS. Whole powers up to MAX_WHOLE_POWER are multiplied
+// out, so "z^2 + c" gives exactly the same numbers as the built-in iteration.
+
+use crate::{progress, render_rows, Complex, MandelbrotCpu};
+
+// The functions a formula can call
+pub const FUNCTIONS: [&str; 8] = ["sin", "cos", "exp", "log", "sqrt", "abs", "conj", "sqr"];
+
+// Highest whole power that is multiplied out, higher ones go through exp and log
+const MAX_WHOLE_POWER: f64 = 64.0;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+// The parsed formula
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Z,
+    C,
+    I,
+    Negate(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Subtract(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    Divide(Box<Expr>, Box<Expr>),
+    Power(Box<Expr>, Box<Expr>),
+    Call(&'static str, Box<Expr>),
+}
+
+type Compiled = Box<dyn Fn(&Complex, &Complex) -> Complex + Send + Sync>;
+
+// A compiled formula, called with z and c to get the next z
+pub struct Formula {
+    source: String,
+    function: Compiled,
+}
+
+impl std::fmt::Debug for Formula {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Formula({:?})", self.source)
+    }
+}
+
+impl Formula {
+    // Parses and compiles {source}. Returns an error saying what's wrong with it if it isn't a
+    // valid formula.
+    pub fn parse(source: &str) -> Result<Formula, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expr = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {} in the formula {:?}", describe(token), source));
+        }
+        Ok(Formula {
+            source: source.to_string(),
+            function: compile(expr),
+        })
+    }
+
+    // Returns the formula as it was given
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Returns the next number of the orbit after {z}, for the point {c}
+    pub fn apply(&self, z: &Complex, c: &Complex) -> Complex {
+        (self.function)(z, c)
+    }
+
+    // Runs the escape time loop with the formula like escape_info does, and returns the iteration
+    // at which the orbit escaped, or None if it stayed within the bailout for {max} iterations.
+    // For the mandelbrot set the orbit of {point} starts at 0, for a julia set with the constant
+    // {julia} at the point itself. Orbits that turn into NaN count as escaped.
+    pub fn escape(&self, point: &Complex, julia: Option<&Complex>, max: u32, bailout_sq: f64) -> Option<u32> {
+        let (mut z, c) = match julia {
+            Some(constant) => (point.clone(), constant.clone()),
+            // The first iteration is done here, so the iterations line up with escape_info, which
+            // starts from the point itself
+            None => (self.apply(&Complex::new(0.0, 0.0), point), point.clone()),
+        };
+        for i in 0..max {
+            let modulus_sq = z.real * z.real + z.imaginary * z.imaginary;
+            if modulus_sq >= bailout_sq || modulus_sq.is_nan() {
+                return Some(i);
+            }
+            z = self.apply(&z, &c);
+        }
+        None
+    }
+}
+
+// Splits {source} into numbers, names and symbols
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, character)) = chars.peek() {
+        if character.is_whitespace() {
+            chars.next();
+        } else if character.is_ascii_digit() || character == '.' {
+            let mut end = start;
+            while let Some(&(index, digit)) = chars.peek() {
+                if !digit.is_ascii_digit() && digit != '.' {
+                    break;
+                }
+                end = index + digit.len_utf8();
+                chars.next();
+            }
+            let text = &source[start..end];
+            let number = text.parse().map_err(|_| format!("{:?} in the formula {:?} is not a number", text, source))?;
+            tokens.push(Token::Number(number));
+        } else if character.is_ascii_alphabetic() {
+            let mut name = String::new();
+            while let Some(&(_, letter)) = chars.peek() {
+                if !letter.is_ascii_alphanumeric() {
+                    break;
+                }
+                name.push(letter);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else if "+-*/^()".contains(character) {
+            tokens.push(Token::Symbol(character));
+            chars.next();
+        } else {
+            return Err(format!("unexpected {:?} in the formula {:?}", character, source));
+        }
+    }
+    Ok(tokens)
+}
+
+// Describes {token} for error messages
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(number) => format!("number {}", number),
+        Token::Name(name) => format!("name {:?}", name),
+        Token::Symbol(symbol) => format!("{:?}", symbol),
+    }
+}
+
+// A recursive descent parser over the tokens of a formula, with the usual precedence: ^ binds
+// tightest and to the right, then unary minus, then * and /, then + and -
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // Skips the next token if it's {symbol}
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        loop {
+            if self.eat('+') {
+                expr = Expr::Add(Box::new(expr), Box::new(self.term()?));
+            } else if self.eat('-') {
+                expr = Expr::Subtract(Box::new(expr), Box::new(self.term()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            if self.eat('*') {
+                expr = Expr::Multiply(Box::new(expr), Box::new(self.unary()?));
+            } else if self.eat('/') {
+                expr = Expr::Divide(Box::new(expr), Box::new(self.unary()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(Expr::Power(Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Name(name)) => match name.as_str() {
+                "z" => Ok(Expr::Z),
+                "c" => Ok(Expr::C),
+                "i" => Ok(Expr::I),
+                _ => {
+                    let function = FUNCTIONS.into_iter().find(|&function| function == name).ok_or_else(|| {
+                        format!(
+                            "unknown name {:?} in the formula, the variables are z, c and i and the functions {}",
+                            name,
+                            FUNCTIONS.join(", ")
+                        )
+                    })?;
+                    if !self.eat('(') {
+                        return Err(format!("{} has to be followed by its argument in parentheses", function));
+                    }
+                    let argument = self.expression()?;
+                    if !self.eat(')') {
+                        return Err(format!("the argument of {} is missing its closing parenthesis", function));
+                    }
+                    Ok(Expr::Call(function, Box::new(argument)))
+                }
+            },
+            Some(Token::Symbol('(')) => {
+                let expr = self.expression()?;
+                if !self.eat(')') {
+                    return Err("the formula is missing a closing parenthesis".to_string());
+                }
+                Ok(expr)
+            }
+            Some(token) => Err(format!("unexpected {} in the formula", describe(&token))),
+            None => Err("the formula ends too early".to_string()),
+        }
+    }
+}
+
+fn add(a: &Complex, b: &Complex) -> Complex {
+    Complex::new(a.real + b.real, a.imaginary + b.imaginary)
+}
+
+fn subtract(a: &Complex, b: &Complex) -> Complex {
+    Complex::new(a.real - b.real, a.imaginary - b.imaginary)
+}
+
+fn multiply(a: &Complex, b: &Complex) -> Complex {
+    Complex::new(
+        a.real * b.real - a.imaginary * b.imaginary,
+        a.real * b.imaginary + a.imaginary * b.real,
+    )
+}
+
+fn divide(a: &Complex, b: &Complex) -> Complex {
+    let modulus_sq = b.real * b.real + b.imaginary * b.imaginary;
+    Complex::new(
+        (a.real * b.real + a.imaginary * b.imaginary) / modulus_sq,
+        (a.imaginary * b.real - a.real * b.imaginary) / modulus_sq,
+    )
+}
+
+fn exp(z: &Complex) -> Complex {
+    let scale = z.real.exp();
+    Complex::new(scale * z.imaginary.cos(), scale * z.imaginary.sin())
+}
+
+fn log(z: &Complex) -> Complex {
+    Complex::new(z.abs().ln(), z.imaginary.atan2(z.real))
+}
+
+// Raises {base} to the whole power {exponent} by multiplying it out
+fn whole_power(base: &Complex, exponent: i32) -> Complex {
+    let mut result = base.clone();
+    for _i in 1..exponent.unsigned_abs() {
+        result = multiply(&result, base);
+    }
+    match exponent {
+        0 => Complex::new(1.0, 0.0),
+        exponent if exponent < 0 => divide(&Complex::new(1.0, 0.0), &result),
+        _ => result,
+    }
+}
+
+// Raises {base} to any complex power {exponent}, through exp and log
+fn power(base: &Complex, exponent: &Complex) -> Complex {
+    if base.real == 0.0 && base.imaginary == 0.0 {
+        return if exponent.real == 0.0 && exponent.imaginary == 0.0 { Complex::new(1.0, 0.0) } else { base.clone() };
+    }
+    exp(&multiply(exponent, &log(base)))
+}
+
+fn call(function: &str, z: &Complex) -> Complex {
+    match function {
+        "sin" => Complex::new(z.real.sin() * z.imaginary.cosh(), z.real.cos() * z.imaginary.sinh()),
+        "cos" => Complex::new(z.real.cos() * z.imaginary.cosh(), -z.real.sin() * z.imaginary.sinh()),
+        "exp" => exp(z),
+        "log" => log(z),
+        "sqrt" => power(z, &Complex::new(0.5, 0.0)),
+        "abs" => Complex::new(z.abs(), 0.0),
+        "conj" => Complex::new(z.real, -z.imaginary),
+        "sqr" => multiply(z, z),
+        _ => unreachable!("the parser only accepts the functions in FUNCTIONS"),
+    }
+}
+
+// Compiles {expr} into a closure taking z and c
+fn compile(expr: Expr) -> Compiled {
+    match expr {
+        Expr::Number(number) => Box::new(move |_, _| Complex::new(number, 0.0)),
+        Expr::Z => Box::new(|z, _| z.clone()),
+        Expr::C => Box::new(|_, c| c.clone()),
+        Expr::I => Box::new(|_, _| Complex::new(0.0, 1.0)),
+        Expr::Negate(operand) => {
+            let operand = compile(*operand);
+            Box::new(move |z, c| {
+                let value = operand(z, c);
+                Complex::new(-value.real, -value.imaginary)
+            })
+        }
+        Expr::Add(left, right) => binary(*left, *right, add),
+        Expr::Subtract(left, right) => binary(*left, *right, subtract),
+        Expr::Multiply(left, right) => binary(*left, *right, multiply),
+        Expr::Divide(left, right) => binary(*left, *right, divide),
+        Expr::Power(base, exponent) => match whole_exponent(&exponent) {
+            Some(exponent) => {
+                let base = compile(*base);
+                Box::new(move |z, c| whole_power(&base(z, c), exponent))
+            }
+            None => binary(*base, *exponent, power),
+        },
+        Expr::Call(function, argument) => {
+            let argument = compile(*argument);
+            Box::new(move |z, c| call(function, &argument(z, c)))
+        }
+    }
+}
+
+// Returns {exponent} if it's a whole number that's multiplied out, like the 2 of z^2 or the -1
+// of z^-1
+fn whole_exponent(exponent: &Expr) -> Option<i32> {
+    let number = match exponent {
+        Expr::Number(number) => *number,
+        Expr::Negate(operand) => match **operand {
+            Expr::Number(number) => -number,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    (number.fract() == 0.0 && number.abs() <= MAX_WHOLE_POWER).then_some(number as i32)
+}
+
+fn binary(left: Expr, right: Expr, operation: fn(&Complex, &Complex) -> Complex) -> Compiled {
+    let (left, right) = (compile(left), compile(right));
+    Box::new(move |z, c| operation(&left(z, c), &right(z, c)))
+}
+
+// Renders the image with flat shading, iterating with options.formula
+pub fn build_mandelbrot_formula(options: &MandelbrotCpu) -> Vec<u8> {
+    let formula = options.formula.as_ref().expect("rendering with a formula without one");
+    let julia = options.julia_constant();
+
+    let mut final_image = vec![u8::MAX; options.image_width * options.image_height];
+
+    progress::status(options.progress, "Generating Image...");
+    render_rows(&mut final_image, options.image_width, options.threads, options.progress, |row, pixels| {
+        for (column, pixel) in pixels.iter_mut().enumerate() {
+            let point = options.plane_point(&options.pixel_point(row, column));
+            let escape = formula.escape(&point, julia.as_ref(), options.iterations_at(row, column), options.bailout_sq);
+            *pixel = if escape.is_none() { 0 } else { u8::MAX };
+        }
+    });
+
+    final_image
+}
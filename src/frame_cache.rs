@@ -0,0 +1,89 @@
+// A cache of rendered frames, keyed by a hash of the parameters they were rendered with. The
+// frames of an animation are rendered by separate runs, and a zoom path that pauses renders the
+// same frame several times. With a cache, only the first of those is rendered and the others
+// are copied from it.
+
+use crate::params::SKIPPED_ARGS;
+use crate::renderer::RenderError;
+use std::path::{Path, PathBuf};
+
+// Arguments that change how a frame is rendered or what is reported about it, but not the
+// outputted image, so frames that only differ in these are the same frame
+const UNHASHED_ARGS: [&str; 13] = [
+    "threads",
+    "nice",
+    "row_order",
+    "max_pixels",
+    "gpu_fraction",
+    "gpu_retries",
+    "gpu_cpu_fallback",
+    "verbose",
+    "report_device_info",
+    "no_progress",
+    "validate_output",
+    "report_precision_exhaustion",
+    "dump_timings_json",
+];
+
+// Returns the hash of the JSON {params} of --dump-args, leaving out the arguments that don't
+// change the image (see params::SKIPPED_ARGS and UNHASHED_ARGS). Uses FNV-1a, which unlike the hasher of the
+// standard library gives the same hash in every build, so a cache can be shared between builds.
+pub fn params_hash(params: &str) -> Result<u64, String> {
+    let mut params = match serde_json::from_str(params) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => return Err(format!("the parameters {:?} aren't a JSON object", params)),
+    };
+    params.retain(|key, _| !SKIPPED_ARGS.contains(&key.as_str()) && !UNHASHED_ARGS.contains(&key.as_str()));
+    // The keys of a JSON object are sorted, so the same parameters always give the same text
+    let text = serde_json::Value::Object(params).to_string();
+    Ok(text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3)))
+}
+
+// A directory of rendered frames, named after the hash of their parameters
+pub struct FrameCache {
+    directory: PathBuf,
+}
+
+impl FrameCache {
+    // Opens the cache in {directory}, creating it if it doesn't exist yet
+    pub fn new(directory: &Path) -> Result<FrameCache, RenderError> {
+        std::fs::create_dir_all(directory).map_err(|error| RenderError::Io {
+            path: directory.to_path_buf(),
+            cause: error.to_string(),
+        })?;
+        Ok(FrameCache {
+            directory: directory.to_path_buf(),
+        })
+    }
+
+    // The file in the cache for the frame with the parameters {hash} outputted to {path}. The
+    // extension of {path} is kept, so the same frame in different formats is cached separately.
+    fn entry(&self, hash: u64, path: &Path) -> PathBuf {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        self.directory.join(format!("{:016x}.{}", hash, extension.to_ascii_lowercase()))
+    }
+
+    // Copies the cached frame with the parameters {hash} to {path}. Returns false if the frame
+    // isn't in the cache, in which case it has to be rendered.
+    pub fn restore(&self, hash: u64, path: &Path) -> Result<bool, RenderError> {
+        let entry = self.entry(hash, path);
+        if !entry.is_file() {
+            return Ok(false);
+        }
+        std::fs::copy(&entry, path).map_err(|error| RenderError::Io {
+            path: path.to_path_buf(),
+            cause: error.to_string(),
+        })?;
+        Ok(true)
+    }
+
+    // Copies the frame with the parameters {hash} at {path} into the cache
+    pub fn store(&self, hash: u64, path: &Path) -> Result<(), RenderError> {
+        let entry = self.entry(hash, path);
+        std::fs::copy(path, &entry).map_err(|error| RenderError::Io {
+            path: entry,
+            cause: error.to_string(),
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+// Rendering on the GPU and the CPU at the same time, for machines with a modest GPU and many
+// cores. The top rows of the image go to the GPU and the rest to the CPU, and the two parts are
+// joined afterwards. Both calculate in f64 and every part is positioned with row_range, so the
+// rows on either side of the seam are the same as in a render on one engine.
+
+use crate::{row_range, MandelbrotCpu};
+use std::thread;
+
+// Default share of the rows the GPU renders
+pub const GPU_FRACTION: f64 = 0.5;
+
+// Returns the number of rows at the top of an image {height} rows high the GPU renders for
+// {fraction} of the rows
+pub fn gpu_rows(height: usize, fraction: f64) -> usize {
+    ((height as f64 * fraction.clamp(0.0, 1.0)).round() as usize).min(height)
+}
+
+// Renders the top options.gpu_fraction of the rows of {options} with {gpu} and the rest with
+// {cpu} at the same time, and returns the joined image
+pub fn build_mandelbrot_hybrid(
+    options: &MandelbrotCpu,
+    gpu: impl FnOnce(&MandelbrotCpu) -> Vec<u8>,
+    cpu: impl FnOnce(&MandelbrotCpu) -> Vec<u8> + Send,
+) -> Vec<u8> {
+    let split = gpu_rows(options.image_height, options.gpu_fraction);
+    let top = row_range(options, 0, split);
+    let bottom = row_range(options, split, options.image_height);
+    // The GPU part stays on this thread, which the CUDA context was set up on
+    let (mut image, bottom_image) = thread::scope(|scope| {
+        let bottom_image = scope.spawn(|| if split < options.image_height { cpu(&bottom) } else { vec![] });
+        let top_image = if split > 0 { gpu(&top) } else { vec![] };
+        (top_image, bottom_image.join().unwrap())
+    });
+    image.extend_from_slice(&bottom_image);
+    image
+}
@@ -0,0 +1,56 @@
+// Spatially varying iteration budgets. A grayscale image is stretched over the render, and the
+// brightness of every pixel scales the iteration count used for it: white gets the full
+// --iterations, black next to nothing. This lets a single image combine areas that need few
+// iterations with detailed areas that need many.
+
+use std::iter;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IterationsMap {
+    width: usize,
+    // One brightness value per pixel of the render, row by row
+    values: Vec<u8>,
+}
+
+impl IterationsMap {
+    // Creates a map from brightness values that already have the size of the render
+    pub fn new(width: usize, values: Vec<u8>) -> IterationsMap {
+        IterationsMap { width, values }
+    }
+
+    // Loads the image at {path} as a map, resampled to {width} x {height} pixels
+    pub fn load(path: &Path, width: usize, height: usize) -> Result<IterationsMap, String> {
+        let map = image::open(path).map_err(|error| format!("could not open {:?}: {}", path, error))?;
+        let map = map
+            .resize_exact(width as u32, height as u32, image::imageops::FilterType::Triangle)
+            .into_luma8();
+        Ok(IterationsMap::new(width, map.into_raw()))
+    }
+
+    // Returns the iteration count for the pixel at {column} in {row}, scaling {iterations} by the
+    // brightness of the map there. Every pixel gets at least one iteration.
+    pub fn iterations(&self, iterations: u32, row: usize, column: usize) -> u32 {
+        let value = self.values[row * self.width + column] as f64;
+        ((iterations as f64 * value / u8::MAX as f64).round() as u32).max(1)
+    }
+
+    // Returns the map for a render at {factor} times the resolution, every value covering a block
+    // of {factor} x {factor} pixels
+    pub fn scaled(&self, factor: usize) -> IterationsMap {
+        let values = self
+            .values
+            .chunks(self.width)
+            .flat_map(|row| {
+                let row: Vec<u8> = row.iter().flat_map(|&value| iter::repeat_n(value, factor)).collect();
+                iter::repeat_n(row, factor).flatten()
+            })
+            .collect();
+        IterationsMap::new(self.width * factor, values)
+    }
+
+    // Returns the part of the map covering rows {start} up to {end}
+    pub fn rows(&self, start: usize, end: usize) -> IterationsMap {
+        IterationsMap::new(self.width, self.values[start * self.width..end * self.width].to_vec())
+    }
+}
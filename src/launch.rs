@@ -0,0 +1,68 @@
+// How the CUDA engine splits an image into kernel launches. Every launch covers the next
+// blocks_per_step blocks of pixels, so the progress can be shown between launches. This only
+// does the arithmetic, so it's built and tested without the gpu feature too.
+
+// Number of threads in every block of a launch
+pub const THREADS_PER_BLOCK: usize = 256;
+
+// Number of launches the image is split into, about
+const LAUNCH_STEPS: usize = 100;
+
+// The launches that cover an image of {total} pixels, one pixel per thread
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaunchConfig {
+    pub total: usize,
+    pub threads_per_block: usize,
+    // Blocks of every launch, the last one only gets the blocks it needs
+    pub blocks_per_step: usize,
+    // Pixels between the starts of two launches
+    pub offset_step: usize,
+    pub launches: usize,
+}
+
+impl LaunchConfig {
+    pub fn new(total: usize, threads_per_block: usize) -> LaunchConfig {
+        let blocks_per_step = total.div_ceil(threads_per_block).div_ceil(LAUNCH_STEPS);
+        let offset_step = threads_per_block * blocks_per_step;
+        let launches = if total == 0 { 0 } else { total.div_ceil(offset_step) };
+        assert!(
+            blocks_per_step * threads_per_block * launches >= total,
+            "{} launches of {} blocks of {} threads don't cover {} pixels",
+            launches,
+            blocks_per_step,
+            threads_per_block,
+            total
+        );
+        LaunchConfig {
+            total,
+            threads_per_block,
+            blocks_per_step,
+            offset_step,
+            launches,
+        }
+    }
+
+    // Returns the number of blocks of the launch starting at pixel {offset}
+    pub fn blocks_at(&self, offset: usize) -> usize {
+        (self.total - offset).div_ceil(self.threads_per_block).min(self.blocks_per_step)
+    }
+
+    // Number of threads started over all launches, the ones past the end of the image return
+    // right away
+    pub fn threads(&self) -> usize {
+        self.total.div_ceil(self.threads_per_block) * self.threads_per_block
+    }
+}
+
+impl std::fmt::Display for LaunchConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "CUDA launch configuration")?;
+        writeln!(f, "  pixels:            {}", self.total)?;
+        writeln!(f, "  threads_per_block: {}", self.threads_per_block)?;
+        writeln!(f, "  blocks_per_step:   {}", self.blocks_per_step)?;
+        writeln!(f, "  offset_step:       {}", self.offset_step)?;
+        writeln!(f, "  total_launches:    {}", self.launches)?;
+        let coverage = if self.threads() == 0 { 1.0 } else { self.total as f64 / self.threads() as f64 };
+        write!(f, "  grid coverage:     {} threads, {:.2}% of them on a pixel", self.threads(), coverage * 100.0)
+    }
+}
@@ -1,23 +1,85 @@
-#![engine(cuda::engine)]
-
-use clap::{crate_version, Parser};
-use cuda::dmem::{Buffer, DSend};
-use cuda::gpu;
+#![cfg_attr(feature = "gpu", engine(cuda::engine))]
+
+use clap::{crate_version, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use color::{ColorSpace, Rgb, Shading};
+#[cfg(feature = "gpu")]
+use cuda_engine::gpu_available;
+use double_double::{Coordinate, DoubleDouble};
+use encoder::EncoderRegistry;
+use filter::DownsampleFilter;
+use iterations_map::IterationsMap;
+use palette::BuiltinPalette;
+use renderer::{RenderError, RenderOutput};
+use schedule::RowOrder;
 use image::ColorType;
+use serde::Serialize;
 use std::io::Write;
 // use std::fs::File;
 // use std::io::prelude::*;
-use std::path::Path;
-use std::sync::mpsc;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::{iter, thread};
 
+mod adaptive;
+mod alpha;
+mod ascii;
+mod bitmap;
+mod buddhabrot;
+mod channels;
+mod color;
+mod crop;
+#[cfg(feature = "gpu")]
+mod cuda_engine;
+mod detail;
+#[cfg_attr(not(feature = "gpu"), allow(dead_code))]
+mod device;
+mod double_double;
+mod encoder;
+mod filter;
+mod formula;
+mod frame_cache;
+#[cfg_attr(not(feature = "gpu"), allow(dead_code))]
+mod hybrid;
+mod iterations_map;
+#[cfg_attr(not(feature = "gpu"), allow(dead_code))]
+mod launch;
+mod mandel_julia;
+mod minibrots;
+mod naming;
+mod orbits;
+mod overlay;
+mod oversize;
+mod palette;
+mod params;
+mod priority;
+mod progress;
+mod renderer;
+mod reuse;
+mod rle;
+mod scanline;
+mod schedule;
+mod seamless;
+mod single;
+mod snap;
 mod tests;
-
-// Default number of threads to use
+mod tile_index;
+mod timings;
+mod transform;
+mod validate;
+#[cfg(feature = "wgpu")]
+mod wgpu_engine;
+
+// Number of threads the default options render with, the command line uses every logical core
 const THREADS: usize = 1;
 
 // Default number of stable iterations (see Complex::is_stable below)
-const STABLE_ITERATIONS: i32 = 50;
+const STABLE_ITERATIONS: u32 = 50;
+
+// Default squared escape radius (see Complex::has_escaped below)
+const BAILOUT_SQ: f64 = 4.0;
 
 // Default width and height of the image in mandelbrot space
 const RADIUS: f64 = 3.0;
@@ -29,27 +91,65 @@ const I_CENTER: f64 = 0.0;
 // The default width and height of the outputted image in pixels
 const IMAGE_DIM: usize = 1024;
 
+// Default number of sub-samples per pixel along each axis (1 disables supersampling)
+const SAMPLES: usize = 1;
+
+// Default number of times the colors cycle between the outside and the edge of the set
+const REPEAT: f64 = 1.0;
+
+// Number of stable iterations used for the quick first pass of a progressive render
+const PREVIEW_ITERATIONS: u32 = 20;
+
+// Largest image, in pixels, --dump-coords writes the coordinates of
+const MAX_DUMP_PIXELS: usize = 256 * 256;
+
+// Default for --max-pixels, a 16384 x 16384 image, which takes 768 MiB in RGB
+const MAX_PIXELS: usize = 16384 * 16384;
+
+// Number of times a --time-budget render halves the resolution for its first pass
+const BUDGET_LEVELS: u32 = 3;
+
+// Longest limit cycle --atom-domains looks for (see Complex::interior_period below)
+const MAX_ATOM_PERIOD: u32 = 64;
+
+// Squared distance within which an orbit counts as having come back to where it was
+const ATOM_EPSILON_SQ: f64 = 1e-12;
+
+// Degree of the iteration z -> z² + c, which the smooth iteration count depends on (see
+// smooth_iteration below)
+const DEGREE: u32 = 2;
+
+// How many times the machine epsilon the pixel step has to be, relative to the coordinates, for
+// neighboring pixels to still be distinct (see precision_exhausted below)
+const PRECISION_MARGIN: f64 = 4.0;
+
 // The default name and file type of the outputted image file
 const IMAGE_NAME: &str = "mandelbrot.png";
 
 // The command line arguments Gendel accepts
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Serialize)]
 #[command(version = crate_version!(), about = "A small, simplistic mandelbrot image generator.", long_about = None)]
 struct Args {
-    // Number of threads to use
-    #[arg(short, long, help = "The number of threads to calculate with", default_value_t = THREADS)]
-    threads: usize,
+    // Number of threads to use, the number of logical cores by default
+    #[arg(short, long, help = "The number of threads to calculate with",
+        long_help = "The number of threads to calculate with. Defaults to the number of logical cores. More threads than that only makes the threads compete for the cores, so that prints a warning.")]
+    threads: Option<usize>,
+
+    // How much to lower the scheduling priority of the render, see priority.rs
+    #[arg(long, value_name = "n", help = "Lower the priority of the render threads, from 0 to 19",
+        long_help = "Lower the scheduling priority of the render threads by n, like the nice command: the niceness goes up by n from where it is, up to 19 (only use the CPU when nothing else needs it). 0 leaves the priority as it is. Keeps the machine responsive during long renders. On Windows this picks the below normal (1 to 14) or idle (15 to 19) priority class.", value_parser = clap::value_parser!(i32).range(0..=priority::MAX_NICE as i64))]
+    nice: Option<i32>,
 
     // Number of stable iterations (see Complex::is_stable below)
-    #[arg(short, long, help = "Number of stable iterations", default_value_t = STABLE_ITERATIONS)]
-    iterations: i32,
+    #[arg(short, long, visible_alias = "max-iterations", help = "Number of stable iterations", default_value_t = STABLE_ITERATIONS)]
+    iterations: u32,
 
     // The center of the image in mandelbrot space
-    #[arg(short, long, help = "The center of the image in mandelbrot space", default_values_t=[REAL_CENTER, I_CENTER], num_args = 2, value_names=["x","y"])]
-    center: Vec<f64>,
+    #[arg(short, long, help = "The center of the image in mandelbrot space", default_values_t=[Coordinate::from(REAL_CENTER), Coordinate::from(I_CENTER)], num_args = 2, value_names=["x","y"], allow_negative_numbers = true)]
+    center: Vec<Coordinate>,
 
     // The dimensions of the image in mandelbrot space
-    #[arg(short, long, help = "The dimensions of the image in mandelbrot space", default_values_t=[RADIUS, RADIUS], num_args = 2, value_names=["width","height"])]
+    #[arg(short, long, help = "The dimensions of the image in mandelbrot space", default_values_t=[RADIUS, RADIUS], num_args = 2, value_names=["width","height"], value_parser = parse_size, allow_negative_numbers = true)]
     size: Vec<f64>,
 
     // The dimensions of the image
@@ -57,13 +157,450 @@ struct Args {
     image_size: Vec<usize>,
 
     // The name of the image file with the file extension
-    #[arg(short='o', long, help="Name of the outputted image file, must include a file extension.", long_help = "Name of the outputted image file, must include a file extension. (Only jpeg, png, ico, pnm, bmp, exr and tiff files are supported)", default_value = IMAGE_NAME)]
+    #[arg(short='o', long, help="Name of the outputted image file, must include a file extension.", long_help = "Name of the outputted image file, must include a file extension. (Only jpeg, png, ico, pnm, bmp, exr and tiff files are supported. pbm files store one bit per pixel, so they only hold the black and white of flat shading)", default_value = IMAGE_NAME)]
     file: String,
 
-    // whether to use the GPU or not
-    #[arg(long, help = "Use the GPU to calculate the mandelbrot image",
-        long_help = "Use the GPU to calculate the mandelbrot image.")]
+    // Where the outputted files go and what they're called, see naming.rs
+    #[arg(long, value_name = "dir", help = "Directory to write the outputted files to, created if it doesn't exist")]
+    output_directory: Option<String>,
+    #[arg(long, value_name = "template", help = "Name the outputted files after a template like \"zoom_{zoom}_{frame:04}.png\" instead of --file",
+        long_help = "Name the outputted files after a template instead of --file, like \"zoom_{zoom}_{frame:04}.png\". {re} and {im} are the center as it was given, {zoom} how far the view is zoomed in compared to the default size, and {frame} and {index} the number of the frame and output when rendering several images (0 otherwise). {frame} and {index} can be padded with zeros, like {frame:04}. The template must include the file extension.")]
+    name_template: Option<String>,
+
+    // Check whether the iteration count is far more than the view needs
+    #[arg(long, help = "Warn if the iteration count is far more than the view needs",
+        long_help = "Estimate how many iterations the view needs from a sparse grid of samples, and warn if --iterations is far more than that. Far outside of the set, huge iteration counts only slow down the render without adding detail.")]
+    clamp_iterations_to_detail: bool,
+
+    // Lower the iteration count to what the view needs
+    #[arg(long, help = "Lower the iteration count to what the view needs (see --clamp-iterations-to-detail)")]
+    auto_clamp: bool,
+
+    // Tighten the view around the set before rendering, see crop.rs
+    #[arg(long, help = "Tighten the view around the set, so it fills the image",
+        long_help = "Tighten the view around the set before rendering, so it fills the image. A quick coarse pass finds the part of the view the set is in, then the view is centered on it and zoomed in until it fills the image, keeping the pixels square. If the set isn't in the view at all, the view is kept as it is.")]
+    crop_to_set: bool,
+
+    // Move the center onto the nearest boundary of the set before rendering, see snap.rs
+    #[arg(long, alias = "center-on-feature", help = "Move the center onto the nearest boundary of the set",
+        long_help = "Move the center of the view onto the nearest point on the boundary of the set before rendering, where the detail is, so an approximate --center is enough to frame a zoom. A grid around the center, out to a tenth of the smallest side of the view, is checked for membership, and the view is moved to the nearest point of it that's in the set next to one that isn't. If there is no boundary that close, the view is kept as it is.",
+        conflicts_with_all = ["crop_to_set", "log_polar"])]
+    snap_to_boundary: bool,
+
+    // Number of sub-samples per pixel along each axis, used for anti-aliasing
+    #[arg(long, help = "Number of sub-samples per pixel along each axis (anti-aliasing)",
+        long_help = "Number of sub-samples per pixel along each axis. A value of n renders n*n sub-samples per pixel and averages them in linear light to smooth out jagged edges. (Only supported on the CPU)", default_value_t = SAMPLES)]
+    samples: usize,
+
+    // Render at a multiple of the resolution and downsample, see oversize.rs
+    #[arg(long, value_name = "factor", help = "Render at factor times the resolution and average it down (anti-aliasing)",
+        long_help = "Render the image at factor times --image-size, then average every factor x factor block of pixels into one pixel in linear light. Every pixel is smoothed the same way, on every engine, and it works with --row-range. It takes factor² times the memory of the outputted image though, where --samples takes more time instead, so prefer --samples for huge images. 1 renders the image as it is. (not used with --progressive, --mandel-julia, --time-budget or --reuse-previous)", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    oversize_then_crop: u32,
+
+    // Only supersample the pixels on edges, see adaptive.rs
+    #[arg(long, value_name = "n", help = "Only supersample pixels whose escape count differs by more than n from a neighbor",
+        long_help = "Only supersample the pixels on edges, where the escape iteration count differs by more than n from one of the neighboring pixels. Every other pixel gets a single sample. 0 refines every edge between iteration bands, which is almost as slow as supersampling everything. Around 2 to 5 finds the boundary of the set and its filaments while skipping the smooth bands outside of it, higher values start to miss thin filaments. Without this option every pixel is supersampled. (only used with --samples above 1)")]
+    supersample_adaptive_threshold: Option<u32>,
+
+    // Spread a total number of samples over the pixels by how complex the image is around them,
+    // see adaptive::allocate_samples
+    #[arg(long, value_name = "n", help = "Spread n samples in total over the pixels, the most on the boundary of the set",
+        long_help = "Spread a budget of n samples in total over the pixels. A quick pass finds how much the escape iteration count changes around every pixel, then every pixel gets one sample and the rest of the budget goes to the pixels in proportion to that change, so the boundary of the set gets the densest sampling and flat areas a single sample. Every pixel gets at most 16 x 16 samples. The render never takes more samples than the budget, unless it's less than one per pixel. Replaces --samples and --supersample-adaptive-threshold. (only supported on the CPU at f64 precision)", conflicts_with_all = ["samples", "supersample_adaptive_threshold"])]
+    sample_budget: Option<usize>,
+
+    // Write where adaptive supersampling took extra samples to a grayscale image
+    #[arg(long, value_name = "path", help = "Also write an image of how many samples every pixel got from adaptive supersampling",
+        long_help = "Also write a grayscale image of how many samples every pixel got from adaptive supersampling, to check that the extra samples went to the edges and tune --supersample-adaptive-threshold. Pixels with a single sample are nearly black, fully supersampled ones white. (only used with --supersample-adaptive-threshold or --sample-budget)")]
+    sample_count_map: Option<String>,
+
+    // How the sub-samples are combined into a pixel
+    #[arg(long, value_enum, help = "How the sub-samples of a pixel are combined",
+        long_help = "How the sub-samples of a pixel are combined when supersampling. box averages the sub-samples inside the pixel, tent and lanczos also sample around the pixel and weigh the sub-samples by their distance to its center. tent gives a smoother image, lanczos a sharper one.", default_value_t = DownsampleFilter::Box)]
+    downsample_filter: DownsampleFilter,
+
+    // Only render a band of rows of the image, so a large render can be split across machines
+    #[arg(long, help = "Only render the rows from start (inclusive) to end (exclusive)",
+        long_help = "Only render the rows from start (inclusive) to end (exclusive) into the outputted file. The partial images can be combined into the full image with --stitch.", num_args = 2, value_names=["start","end"])]
+    row_range: Option<Vec<usize>>,
+
+    // The order the rows are rendered in, see schedule.rs
+    #[arg(long, value_enum, help = "The order the rows are rendered in",
+        long_help = "The order the rows are handed out to the threads in. top-down renders from the top of the image down, center-out starts at the middle row and works outwards, so the center of the view is finished first in a live preview. The outputted image is the same either way. (only used on the CPU)", default_value_t = RowOrder::TopDown)]
+    row_order: RowOrder,
+
+    // Refuse to allocate images larger than this, see check_pixel_limit
+    #[arg(long, value_name = "n", help = "Largest number of pixels to render at once",
+        long_help = "Largest number of pixels to render at once. Larger images stop with an error before anything is allocated, so a typo in --image-size doesn't run the machine out of memory. This counts the pixels that are actually rendered, so a band of --row-range only counts its rows and --oversize-then-crop counts the oversized image. Raise it for intentionally huge renders, or split them into bands with --row-range and combine those with --stitch.", default_value_t = MAX_PIXELS)]
+    max_pixels: usize,
+
+    // Partial images (see row_range) to combine into the outputted file
+    #[arg(long, help = "Stitch partial images rendered with --row-range together, from top to bottom", num_args = 1.., value_name = "files")]
+    stitch: Vec<String>,
+
+    // Write an index of the partial images instead of stitching them, see tile_index.rs
+    #[arg(long, help = "Write a small labeled index of the partial images given to --stitch instead of stitching them",
+        long_help = "Write a small index of the partial images given to --stitch to the outputted file instead of stitching them, for finding your way around renders too large to open. Every part is downsampled to at most 256 pixels wide and labeled with its number, counting from 0 in the order they are given, and the parts are stacked from top to bottom with a divider in between. Only one part is loaded at a time.", requires = "stitch")]
+    tile_index: bool,
+
+    // Whether to save a quick low iteration preview before the full render
+    #[arg(long, help = "Save a quick low iteration preview before rendering the full image",
+        long_help = "Save a quick low iteration preview to the outputted file first, then overwrite it with the full render once that is done.")]
+    progressive: bool,
+
+    // Watch the view resolve as ASCII art in the terminal instead of rendering, see ascii.rs
+    #[arg(long, help = "Show the view as ASCII art in the terminal, redrawn as it resolves, instead of rendering",
+        long_help = "Show the view as ASCII art in the terminal instead of rendering an image, fitted to the size of the terminal. It's rendered in passes of increasing resolution and iteration count like --time-budget, and every pass is drawn over the previous one, so the fractal can be watched resolving over SSH. The COLUMNS and LINES environment variables override the terminal size.",
+        conflicts_with_all = ["progressive", "time_budget", "buddhabrot", "mandel_julia"])]
+    preview_ascii_live: bool,
+
+    // Render the julia set of a point next to the mandelbrot set
+    #[arg(long, help = "Render the julia set of a point next to the mandelbrot set, with the point marked",
+        long_help = "Render the julia set of a point next to the mandelbrot set. The outputted image has the mandelbrot set on the left, with the point marked in --overlay-color, and the julia set of the point on the right. Both panels are --image-size large.", num_args = 2, value_names = ["re", "im"], allow_negative_numbers = true)]
+    mandel_julia: Option<Vec<f64>>,
+
+    // Render the density of the orbits of escaping points, see buddhabrot.rs
+    #[arg(long, help = "Render the buddhabrot, the density of the orbits of escaping points",
+        long_help = "Render the buddhabrot: the orbits of a grid of starting points over the whole set are traced, and every pixel is as bright as the number of orbits of escaping points that pass through it. There are --samples x --samples starting points for every pixel. Only the orbits of points that escape after --min-iterations up to --iterations (also --max-iterations) iterations are traced, so rendering a few bands and combining them as the channels of an image gives the nebulabrot. The image is grayscale and always rendered on the CPU.", conflicts_with_all = ["mandel_julia", "progressive", "time_budget", "reuse_previous"])]
+    buddhabrot: bool,
+
+    // The lower end of the band of escape counts the buddhabrot traces
+    #[arg(long, value_name = "n", help = "Only trace the orbits of points that escape after at least n iterations",
+        long_help = "Only trace the orbits of points that escape after at least n iterations into the buddhabrot. Together with --iterations this gives the band of escape counts, higher bands show the orbits close to the set. (only used with --buddhabrot)", default_value_t = 0, requires = "buddhabrot")]
+    min_iterations: u32,
+
+    // Render the best image possible within a number of seconds
+    #[arg(long, value_name = "seconds", help = "Render the best image possible within this many seconds",
+        long_help = "Render the best image possible within this many seconds. The image is first rendered at a low resolution and iteration count, then refined until the next refinement wouldn't fit in the time budget anymore. At least the first, coarse pass is always rendered.", conflicts_with = "progressive")]
+    time_budget: Option<f64>,
+
+    // The previous frame of a pan animation, to copy everything that's still in view from
+    #[arg(long, value_name = "path", help = "Reuse the previous frame of a pan animation, only rendering what scrolled into view",
+        long_help = "Reuse the previous frame of a pan animation. If the view moved by a whole number of pixels since the previous frame, which was rendered with the same settings at --previous-center, everything that's still in view is copied from it and only the strips that scrolled into view are rendered. Otherwise the full image is rendered. The previous frame must not have overlays drawn over it.", requires = "previous_center", conflicts_with_all = ["progressive", "time_budget", "mandel_julia"])]
+    reuse_previous: Option<String>,
+    #[arg(long, help = "The center the previous frame (see --reuse-previous) was rendered at", num_args = 2, value_names = ["x", "y"], allow_negative_numbers = true, requires = "reuse_previous")]
+    previous_center: Option<Vec<Coordinate>>,
+
+    // How to shade the pixels of the image
+    #[arg(long, value_enum, visible_alias = "color-mode", help = "How to shade the image",
+        long_help = "How to shade the image. flat colors the mandelbrot set black and everything else white, hybrid shades the outside with a smooth gradient that fades with the distance to the set, so the filaments glow. hsv-cycle colors the outside with a continuous rainbow. binary-decomp shades the outside by the sign of the imaginary part of z when it escapes, which splits the equipotential bands into cells. palette colors the outside with a smooth gradient through the colors of --palette. angle colors the outside by the angle of z when it escapes, going around the hue circle, which shows the external rays of the set. orbit-average shades the inside and the outside by the average size of z over its orbit, which gives a soft, cloudy look. (only flat is supported on the GPU)", default_value_t = Shading::Flat)]
+    shading: Shading,
+
+    // The palette used by palette shading
+    #[arg(long, visible_alias = "color", help = "The palette to color the outside of the set with (implies --shading palette)",
+        long_help = "The palette to color the outside of the set with, one of grayscale, fire, ocean, rainbow and sunset. Implies --shading palette if no other shading is given.")]
+    palette: Option<BuiltinPalette>,
+
+    // Nonlinear spacing of the escape counts over the palette
+    #[arg(long, value_name = "g", help = "Raise the palette position to the power g, to move where the palette colors fall",
+        long_help = "Raise the position in the palette to the power g before looking up the color, to move where the palette colors fall. Below 1 the pixels that escape quickly get more of the palette, above 1 the ones close to the set. 1 leaves the palette as it is. This is independent of --gamma, which corrects the outputted colors. (only used by palette shading)", default_value_t = 1.0, value_parser = parse_gamma)]
+    palette_gamma: f64,
+
+    // The color space the palette is interpolated in, see color::interpolate
+    #[arg(long, value_enum, help = "The color space the colors of the palette are interpolated in",
+        long_help = "The color space the colors between the stops of the palette are interpolated in. srgb mixes the encoded colors, which makes the colors between very different stops muddy. oklab mixes them in a perceptual color space, so the gradient changes evenly. (only used by palette shading)", default_value_t = ColorSpace::Srgb)]
+    color_space: ColorSpace,
+
+    // Shorthand for --shading hsv-cycle
+    #[arg(long, help = "Color the outside of the set with a continuous rainbow (same as --shading hsv-cycle)", conflicts_with = "shading")]
+    hsv_cycle: bool,
+
+    // Shorthand for --shading binary-decomp
+    #[arg(long, help = "Shade the outside of the set by the sign of the imaginary part at escape (same as --shading binary-decomp)", conflicts_with_all = ["shading", "hsv_cycle"])]
+    binary_decomp: bool,
+
+    // Look at the plane through 1/c, which brings infinity to the origin
+    #[arg(long, help = "Render the plane inverted through 1/c, so infinity ends up at the origin",
+        long_help = "Render the plane inverted through 1/c: every pixel shows the number 1/c instead of its own coordinate c. The far outside of the set ends up around the origin, and the set itself wraps around it. The pixel right at the origin is infinity, which is outside of the set. (only supported on the CPU at f64 precision)")]
+    invert_plane: bool,
+
+    // Move the points of the view by an affine transform around its center, see transform.rs
+    #[arg(long, help = "Transform the view by the affine matrix [a b e; c d f] around its center",
+        long_help = "Transform the points of the view by the 2x3 affine matrix [a b e; c d f] before they're iterated, for rotating, shearing, stretching and moving the view in one go. The grid of --center and --size is laid out as usual, then every point p of it shows the number center + [a b; c d] (p - center) + (e, f), so the matrix works around --center, and e and f move the view in mandelbrot space. --size is the size of the view before the transform: a matrix of 2 0 0 2 0 0 shows twice as much of the plane. Rotating the view by t radians counterclockwise, which turns the image clockwise, is cos(t) -sin(t) sin(t) cos(t) 0 0. 1 0 0 1 0 0 leaves the view as it is. Everything calculated from the view is transformed the same way, like --export-iterations, --export-channels and --scanline. (only supported on the CPU at f64 precision)",
+        num_args = 6, value_names = ["a", "b", "c", "d", "e", "f"], allow_negative_numbers = true,
+        conflicts_with_all = ["log_polar", "crop_to_set", "snap_to_boundary"])]
+    transform: Option<Vec<f64>>,
+
+    // Start the orbits of the mandelbrot set somewhere else than 0, see MandelbrotCpu::orbit_start
+    #[arg(long, help = "Start the orbit of every point at z = re + im i instead of 0",
+        long_help = "Start the orbit of every point of the mandelbrot set at z = re + im i instead of 0, which changes the shape of the set. Starting at 0 0 gives the usual set. Julia sets always start at the point itself, so this isn't used with them. (only supported on the CPU at f64 precision)", num_args = 2, value_names = ["re", "im"], allow_negative_numbers = true)]
+    start_z: Option<Vec<f64>>,
+
+    // Iterate a formula given here instead of z² + c, see formula.rs
+    #[arg(long, value_name = "formula", help = "Iterate the formula, like \"z^3 + c\", instead of z² + c (experimental, slower)",
+        long_help = "Iterate the formula instead of z² + c, for trying out other fractals without changing the code. A formula is made of z, c, the imaginary unit i, real numbers, + - * / and ^, parentheses and the functions sin, cos, exp, log, sqrt, abs, conj and sqr, like \"z^3 + c\", \"z*z + sin(c)\" or \"conj(z)^2 + c\". The orbit of every point c starts at z = 0, or at the point itself for julia sets, where c is the julia constant. Points whose orbit reaches the bailout or turns into NaN escape. Whole powers are multiplied out, so \"z^2 + c\" renders the same image as without this. The formula is evaluated on every iteration, which is several times slower than the built-in iteration. (experimental, only supported on the CPU at f64 precision with flat shading)",
+        value_parser = parse_formula)]
+    fractal_formula: Option<String>,
+
+    #[arg(long, help = "Render a zoom strip in log-polar coordinates around a point",
+        long_help = "Render the view in log-polar coordinates around the point re + im i: the rows go once around the point, from an angle of pi at the top to -pi at the bottom, and the columns go from close to the point on the left out to half of the largest --size on the right. Every pixel covers the same step in angle and in log radius, so the strip zooms in on the point at the same rate everywhere, and the strip can be scrolled endlessly for an infinite zoom (the Droste effect). The top and bottom rows are next to each other around the point. --center isn't used. (only supported on the CPU at f64 precision)", num_args = 2, value_names = ["re", "im"], allow_negative_numbers = true)]
+    log_polar: Option<Vec<f64>>,
+
+    // Gamma correction of the outputted colors
+    #[arg(long, help = "Gamma to correct the outputted colors with, 2.2 for sRGB displays",
+        long_help = "Gamma to correct the outputted colors with. Every channel is raised to the power 1/gamma before it's quantized, so 2.2 brightens the midtones of smooth shadings to look right on sRGB displays. 1 leaves the colors as they are. Black and white pixels never change, so this has no effect on flat shading.", default_value_t = 1.0, value_parser = parse_gamma)]
+    gamma: f64,
+
+    // Color the inside of the set by the period of its limit cycle
+    #[arg(long, help = "Color the inside of the set by the period of the cycle its orbits end up in",
+        long_help = "Color the inside of the set by the period of the cycle its orbits end up in, which shows the bulbs of the set and the bulbs on those bulbs. Every period gets its own hue, points whose cycle isn't found within the iteration count stay black. (only supported on the CPU at f64 precision)")]
+    atom_domains: bool,
+
+    // Shade the inside of the set by how close its orbits come to escaping
+    #[arg(long, help = "Shade the inside of the set by how close its orbits come to escaping",
+        long_help = "Shade the inside of the set by the largest modulus its orbits reach, relative to the escape radius. Orbits that stay near the origin are black, the ones that come close to escaping are light, which shows gradients inside the set that are otherwise plain black. --atom-domains takes precedence where it finds a cycle. (only supported on the CPU at f64 precision)")]
+    color_interior_by_iteration_fraction: bool,
+
+    // How many times the colors cycle between the outside and the edge of the set
+    #[arg(long, help = "Number of times the colors cycle between the outside and the edge of the set", default_value_t = REPEAT)]
+    repeat: f64,
+
+    // Saturation and value of the colors used by hsv-cycle shading
+    #[arg(long, help = "Saturation of the colors of hsv-cycle shading, from 0 to 1", default_value_t = 1.0)]
+    hsv_saturation: f64,
+    #[arg(long, help = "Value (brightness) of the colors of hsv-cycle shading, from 0 to 1", default_value_t = 1.0)]
+    hsv_value: f64,
+
+    // How many of the last iterations to fade into the color of the set
+    #[arg(long, value_name = "n", help = "Fade pixels escaping in the last n iterations into the color of the set",
+        long_help = "Fade pixels that escape in the last n iterations into the color of the set. With smooth shadings, pixels escaping right before the iteration limit are drawn at full brightness next to the black set, which shows up as a halo around it. Fading them out smooths that transition. 0 turns this off. (only used by hybrid, hsv-cycle and palette shading)", default_value_t = 0)]
+    smooth_iter_clamp: u32,
+
+    // Whether to check the rendered image for assembly errors before saving it
+    #[arg(long, help = "Check the rendered image for errors before saving it",
+        long_help = "Check the rendered image for errors before saving it. This checks that the image has the right size and only valid pixel values, and recomputes a few pixels to compare them against the image.")]
+    validate_output: bool,
+
+    // Shift the sampling grid by a fraction of a pixel
+    #[arg(long, help = "Shift the image by a fraction of a pixel, to the right and down",
+        long_help = "Shift the image by a fraction of a pixel, to the right and down. Zoom animations can use this to keep the frames aligned to a continuous path instead of jumping a whole pixel at a time.", num_args = 2, value_names=["dx","dy"], allow_negative_numbers = true)]
+    pixel_offset: Option<Vec<f64>>,
+
+    // Sample the pixels at their top left corners instead of their centers, see sample_centers
+    #[arg(long, help = "Sample every pixel at its top left corner instead of its center",
+        long_help = "Sample every pixel at its top left corner instead of its center, like older versions did. Corner sampling shifts the image half a pixel up and to the left of the given center, so it isn't symmetric around it.")]
+    sample_corners: bool,
+
+    // A grayscale image that scales the iteration count over the image
+    #[arg(long, help = "A grayscale image whose brightness scales the iteration count over the image",
+        long_help = "A grayscale image whose brightness scales the iteration count over the image. The image is stretched to the size of the render, white pixels get the full --iterations and darker ones proportionally fewer. (only supported on the CPU at f64 precision)")]
+    iterations_map_file: Option<String>,
+
+    // Composition aids drawn over the finished image
+    #[arg(long, help = "Draw a crosshair over the center of the image")]
+    overlay_crosshair: bool,
+    #[arg(long, help = "Draw lines dividing the image into thirds")]
+    overlay_thirds: bool,
+    #[arg(long, help = "Color of the overlays, as a hex code like ff8000", default_value_t = Rgb([0x80, 0x80, 0x80]))]
+    overlay_color: Rgb,
+
+    // Highlight the pixels that only just escaped, see detail::budget_ring
+    #[arg(long, help = "Draw the pixels that escaped in the last 1% of the iterations in the overlay color",
+        long_help = "Draw the pixels that escaped within the last 1% of the iterations, at least the last iteration, in --overlay-color. A higher --iterations would show more detail at those pixels, so if there are many of them the budget is too low for the view.")]
+    max_escape_iterations_ring: bool,
+
+    // Make one side of the boundary of the set transparent, see alpha.rs
+    #[arg(long, value_enum, value_name = "region", help = "Make the interior or the exterior of the set transparent",
+        long_help = "Output an image with an alpha channel where the pixels in the set (interior) or outside of it (exterior) are transparent and the rest is opaque, for compositing the set over other images. (only supported for PNG files)")]
+    output_alpha: Option<alpha::AlphaRegion>,
+
+    // Make the outputted image tile seamlessly, see seamless.rs
+    #[arg(long, value_enum, value_name = "mode", help = "Make the outputted image tile seamlessly, for use as a texture",
+        long_help = "Make the opposite edges of the outputted image match, so copies of it can be put next to each other without a visible seam, for use as a texture. mirror replaces the right half with the left half mirrored and the bottom half with the top half mirrored. cross-fade keeps the image and only blends a band along every edge with the mirrored image, an eighth of the side wide. The overlays are blended along with the rest of the image.")]
+    seamless: Option<seamless::SeamlessMode>,
+
+    // The floating point precision to calculate with on the CPU
+    #[arg(long, value_enum, help = "The floating point precision to calculate with",
+        long_help = "The floating point precision to calculate with. f64 is fast but runs out of precision at a zoom of around 1e-15, double-double is several times slower but good up to around 1e-30. f32 runs out at around 1e-6 and is there to see what a view looks like at the precision of the wgpu engine without a GPU. (f32 and double-double are only supported on the CPU with flat shading)", default_value_t = Precision::F64)]
+    precision: Precision,
+
+    // What to calculate the mandelbrot image on
+    #[arg(long, value_enum, help = "What to calculate the mandelbrot image on",
+        long_help = "What to calculate the mandelbrot image on. cpu and gpu always use the CPU or the GPU (CUDA, only available when built with the gpu feature), auto uses the GPU if there is a usable CUDA device and falls back to the CPU otherwise. hybrid renders the top --gpu-fraction of the rows on the GPU and the rest on the CPU at the same time (also only with the gpu feature). wgpu renders on any GPU through wgpu, in f32 precision (only available when built with the wgpu feature).", default_value_t = Engine::Cpu)]
+    engine: Engine,
+
+    // Share of the rows --engine hybrid renders on the GPU, see hybrid.rs
+    #[arg(long, value_name = "fraction", help = "Share of the rows --engine hybrid renders on the GPU, from 0 to 1",
+        long_help = "Share of the rows of the image --engine hybrid renders on the GPU, from 0 (all on the CPU) to 1 (all on the GPU). The GPU renders the top rows and the CPU the rest, at the same time. Raise it for faster GPUs, lower it for machines with many cores. (only used by --engine hybrid)", default_value_t = hybrid::GPU_FRACTION, value_parser = parse_fraction)]
+    gpu_fraction: f64,
+
+    // whether to use the GPU or not, from before --engine
+    #[arg(long, help = "Use the GPU to calculate the mandelbrot image (deprecated, same as --engine gpu)",
+        long_help = "Use the GPU to calculate the mandelbrot image. Deprecated, use --engine gpu instead.", conflicts_with = "engine")]
     gpu: bool,
+
+    // How many times to retry a failed GPU launch
+    #[arg(long, help = "Number of times to retry a failed GPU kernel launch",
+        long_help = "Number of times to retry a failed GPU kernel launch before giving up, waiting a little longer before every retry. Useful on shared GPUs, where launches can fail while the device is busy.", default_value_t = 0)]
+    gpu_retries: u32,
+
+    // Calculate chunks the GPU fails on on the CPU instead
+    #[arg(long, help = "Calculate the parts of the image the GPU fails on on the CPU instead of giving up",
+        long_help = "If launching or finishing a chunk of the image on the GPU still fails after --gpu-retries, calculate the pixels of that chunk on the CPU and carry on with the next chunk, instead of giving up on the whole render. For long unattended renders on unreliable GPUs. (only used by --engine gpu)")]
+    gpu_cpu_fallback: bool,
+
+    // Print details of the render, see launch.rs
+    #[arg(long, help = "Print how the GPU render is split into kernel launches",
+        long_help = "Print the launch configuration of the GPU render before it starts: the threads per block, the blocks and pixels of every launch, the number of launches and how many of the threads land on a pixel. (only used by --engine gpu)")]
+    verbose: bool,
+
+    // Print the properties of the CUDA device, see device.rs
+    #[arg(long, help = "Print the name, compute capability, memory and max threads per block of the CUDA device",
+        long_help = "Print the properties of the CUDA device before rendering: its name, compute capability, memory and the most threads a block can have, along with the threads per block the render uses. They're asked from the CUDA driver for the device the render runs on, and the ones it can't report are left out. (only used by --engine gpu and --engine hybrid)")]
+    report_device_info: bool,
+
+    // Leave out the progress, see progress.rs
+    #[arg(long, help = "Don't show the progress while rendering",
+        long_help = "Don't show the progress while rendering. The progress is rewritten in place on the terminal, so it's also left out when the output isn't a terminal, like when it's redirected to a log file. The messages about what the render is doing, like Generating Image..., are left out with it.")]
+    no_progress: bool,
+
+    // Write the coordinates of every pixel to a CSV file instead of rendering, for debugging
+    #[arg(long, value_name = "path", help = "Write the coordinates of every pixel to a CSV file instead of rendering",
+        long_help = "Write the coordinates in mandelbrot space of every pixel to a CSV file instead of rendering, for checking how pixels map to coordinates. Only works for small images.")]
+    dump_coords: Option<String>,
+
+    // Write the orbits of every nth pixel to a CSV file instead of rendering, see orbits.rs
+    #[arg(long, value_name = "stride", help = "Write the orbits of every stride-th pixel to a CSV file instead of rendering",
+        long_help = "Write the orbit of every stride-th pixel, counting row by row, to a CSV file instead of rendering: every z value the pixel goes through until it escapes, one line per point. Plotting the points shows where the orbits go, like a buddhabrot. The file is named after the outputted file, with the extension csv. At most a million points are written, the rest is cut off.", value_parser = clap::value_parser!(u32).range(1..))]
+    orbit_cloud: Option<u32>,
+
+    // Write the escape iteration counts to a run-length encoded file instead of rendering
+    #[arg(long, value_name = "path", help = "Write the escape iteration count of every pixel to a .rle file instead of rendering",
+        long_help = "Write the escape iteration count of every pixel to a run-length encoded .rle file instead of rendering an image, for archiving renders independently of the shading. Pixels in the set get the iteration count. (only supported on the CPU)")]
+    export_iterations: Option<String>,
+
+    // Write how many pixels escaped at every iteration count instead of rendering
+    #[arg(long, value_name = "path", help = "Write how many pixels escaped at every iteration count to a CSV file instead of rendering",
+        long_help = "Write the distribution of the escape iteration counts over the image to a CSV file instead of rendering an image, with one line per iteration count that any pixel escaped at and the number of pixels that escaped at it. Pixels in the set are counted at the iteration count. Useful for choosing the iteration count and the range of a palette. (only supported on the CPU)")]
+    iterations_histogram: Option<String>,
+
+    // Write the escape counts of a single row or column instead of rendering, see scanline.rs
+    #[arg(long, value_name = "row", help = "Write the escape counts of one row of the image to a CSV file instead of rendering",
+        long_help = "Calculate only the given row of the image, counting from 0 at the top, and write the coordinates and the escape iteration count of every pixel in it to a CSV file instead of rendering an image, for plotting how the counts change along a line through a feature. Pixels in the set get the iteration count. The coordinates are those of the pixels in the view, and the counts are of the points they show in the plane after --transform, --log-polar and --invert-plane, the same as in that row of --export-iterations. Takes the time of a single row. The file is named after the outputted file, with the extension csv. (always calculated on the CPU)")]
+    scanline: Option<usize>,
+
+    // Same as --scanline for a column
+    #[arg(long, value_name = "column", help = "Write the escape counts of one column of the image to a CSV file instead of rendering",
+        long_help = "Same as --scanline, but calculates the given column of the image, counting from 0 on the left. (always calculated on the CPU)", conflicts_with = "scanline")]
+    scanline_col: Option<usize>,
+
+    // List the small copies of the set in the view instead of rendering, see minibrots.rs
+    #[arg(long, value_name = "pixels", help = "List the small copies of the set in the view, up to a number of pixels large, instead of rendering",
+        long_help = "List the small copies of the set in the view instead of rendering an image, as candidates to zoom in on. Every blob of connected pixels in the set of at most the given number of pixels that doesn't touch the edges of the image is listed with its center and its size, largest first. (only supported on the CPU)", value_parser = clap::value_parser!(u64).range(1..))]
+    find_minibrots: Option<u64>,
+
+    // Write the raw escape data to an EXR file instead of rendering, see channels.rs
+    #[arg(long, value_name = "path.exr", help = "Write the escape count, modulus and angle of every pixel to an EXR file instead of rendering",
+        long_help = "Write how every pixel escaped to the float channels of an EXR file instead of rendering an image, for coloring in a compositor. R is the iteration the pixel escaped at divided by the iteration count, G the modulus and B the angle in turns (0 to 1) of the number when it escaped. Pixels in the set have an R of 1 and G and B of 0. (only supported on the CPU)")]
+    export_channels: Option<String>,
+
+    // Embed the arguments in the outputted PNG, see params.rs
+    #[arg(long, help = "Embed the render parameters in the outputted PNG",
+        long_help = "Embed the render parameters as JSON in a text chunk of the outputted PNG, so the image describes how to render it again. Read them back with --read-params. (only supported for PNG files)")]
+    save_params_with_image: bool,
+
+    // Leave everything but the pixels out of the outputted file, see embedded_params
+    #[arg(long, help = "Write nothing but the image, so the same render always gives the same file",
+        long_help = "Write nothing but the image to the outputted file, leaving out the parameters of --save-params-with-image. Those hold every argument, including ones that don't change the image like the output path and --threads, so the same image could end up in files with different bytes. With this, renders of the same image give byte-identical files, for archiving and deduplicating them by their hash. Gendel never writes timestamps.")]
+    strip_metadata: bool,
+
+    // Copy frames that were rendered before with the same parameters, see frame_cache.rs
+    #[arg(long, value_name = "directory", help = "Keep the rendered frames in a directory, and copy a frame from it instead of rendering it again",
+        long_help = "Keep a copy of every rendered frame in a directory, named after a hash of the render parameters. If a frame with exactly the same parameters was rendered before, like the frames where a zoom path pauses, it's copied from the directory instead of rendered again. The name of the outputted file isn't part of the parameters, and neither are the arguments that don't change the image, like --threads. With --validate-output or --dump-timings-json the frame is always rendered, as those report on the render, and stored for later runs.", conflicts_with_all = ["iterations_map_file", "stitch", "time_budget"])]
+    frame_cache: Option<String>,
+
+    // Print the parameters embedded in a PNG instead of rendering
+    #[arg(long, value_name = "file.png", help = "Print the render parameters embedded in a PNG (see --save-params-with-image)")]
+    #[serde(skip)]
+    read_params: Option<String>,
+
+    // Start from the parameters embedded in a PNG
+    #[arg(long, value_name = "file.png", help = "Render with the parameters embedded in a PNG (see --save-params-with-image), changed by the other arguments",
+        long_help = "Render with the parameters embedded in a PNG saved with --save-params-with-image, so a render can be tweaked and rendered again. Every other argument overrides the embedded one, like --iterations 5000 to render the same view with more iterations. The output file isn't taken from the image, so it's never overwritten by accident.")]
+    #[serde(skip)]
+    from_image: Option<String>,
+
+    // Warn if the view is too deep for the precision it's calculated at
+    #[arg(long, help = "Warn if the view is zoomed in too far for the precision it's calculated at",
+        long_help = "Warn if the view is zoomed in too far for the precision it's calculated at, where neighboring pixels round to the same or nearly the same coordinates and the image turns blocky. The check is done before rendering and suggests a precision that goes deeper.")]
+    report_precision_exhaustion: bool,
+
+    // Print how many digits the view needs, see precision_report
+    #[arg(long, help = "Print how many significant digits the view needs and which precisions have enough",
+        long_help = "Print how many significant decimal digits the coordinates need for neighboring pixels to be distinct at the current --size and --image-size, and whether f32 (about 7 digits, the wgpu engine), f64 (about 16 digits) and double-double (about 31 digits) have enough. Deeper zooms need more digits, and a view rendered in a precision without enough of them turns blocky.")]
+    probe_precision: bool,
+
+    // Only time the render, see ComputeReport
+    #[arg(long, help = "Render without writing anything and report how long the render took",
+        long_help = "Render the image without encoding or writing it, and report how long the render took and how many pixels per second that is. Encoding a large PNG can take longer than rendering it, so this times the render on its own, for profiling and comparing engines.")]
+    compute_only: bool,
+
+    // Write how long every phase of the run took, see timings.rs
+    #[arg(long, value_name = "path.json", help = "Write how long every phase of the run took to a JSON file",
+        long_help = "Write how long every phase of the run took to a JSON file after writing the image, for tracking performance over time. The phases are arguments (parsing and resolving the arguments), setup (preparing the output and looking up the frame cache), compute (the render, including allocating the image on either engine), postprocess (validation and overlays) and write (encoding the image and writing it, which the encoders do in one go), in seconds, along with the engine and the total. Nothing is written if the run stops before writing an image.")]
+    dump_timings_json: Option<String>,
+
+    // Print the parsed arguments as JSON instead of rendering, for debugging and tooling
+    #[arg(long, hide = true)]
+    dump_args: bool,
+
+    // What to do instead of rendering an image, if anything
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Commands>,
+}
+
+// Parses a dimension of the image in mandelbrot space, which has to be a positive number.
+// Anything else would give zero, negative or NaN steps and silently render garbage.
+fn parse_size(text: &str) -> Result<f64, String> {
+    let size: f64 = text.parse().map_err(|_| format!("{:?} is not a number", text))?;
+    if !size.is_finite() || size <= 0.0 {
+        return Err(format!("the size must be a positive finite number, not {}", text));
+    }
+    Ok(size)
+}
+
+// Parses a fraction, which has to be between 0 and 1
+fn parse_fraction(text: &str) -> Result<f64, String> {
+    let fraction: f64 = text.parse().map_err(|_| format!("{:?} is not a number", text))?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!("the fraction must be between 0 and 1, not {}", text));
+    }
+    Ok(fraction)
+}
+
+// Parses the gamma of --gamma, which has to be a positive number
+fn parse_gamma(text: &str) -> Result<f64, String> {
+    let gamma: f64 = text.parse().map_err(|_| format!("{:?} is not a number", text))?;
+    if !gamma.is_finite() || gamma <= 0.0 {
+        return Err(format!("the gamma must be a positive finite number, not {}", text));
+    }
+    Ok(gamma)
+}
+
+// Checks the formula of --fractal-formula, so a typo is reported before rendering. The formula
+// is kept as text, which is what's embedded with --save-params-with-image.
+fn parse_formula(text: &str) -> Result<String, String> {
+    formula::Formula::parse(text)?;
+    Ok(text.to_string())
+}
+
+// The subcommands Gendel accepts
+#[derive(Subcommand, Debug)]
+enum Commands {
+    // Print a shell completion script
+    #[command(about = "Print the completion script for a shell")]
+    Completions { shell: Shell },
+}
+
+// How the mandelbrot algorithm went for a number, see Complex::escape_info
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EscapeInfo {
+    // The iteration the number escaped at, or the maximum number of iterations if it didn't
+    pub iterations: u32,
+    pub escaped: bool,
+    // Where the number ended up, past the escape radius if it escaped
+    pub final_re: f64,
+    pub final_im: f64,
 }
 
 // Simple struct for complex numbers
@@ -87,7 +624,12 @@ impl Complex {
 
     // Checks to see if the complex number has gone past the escape radius
     fn has_escaped(&self) -> bool {
-        self.real * self.real + self.imaginary * self.imaginary >= 4.0 
+        self.has_escaped_radius(BAILOUT_SQ)
+    }
+
+    // Checks to see if the complex number has gone past an escape radius of sqrt({bailout_sq})
+    fn has_escaped_radius(&self, bailout_sq: f64) -> bool {
+        self.real * self.real + self.imaginary * self.imaginary >= bailout_sq
     }
 
     // Returns a new complex number
@@ -102,18 +644,253 @@ impl Complex {
     // is in the mandelbrot set. The complex number will be iterated a maximum of
     // {stable_iterations} times before the algorithm decides it's in the mandelbrot set,
     // assuming it doesn't escape before then.
-    fn is_stable(&self, stable_iterations: i32) -> bool {
+    fn is_stable(&self, stable_iterations: u32) -> bool {
+        self.is_stable_radius(stable_iterations, BAILOUT_SQ)
+    }
+
+    // Returns 1 divided by the complex number. 0 gives the point at infinity, which escapes
+    // right away.
+    fn inverse(&self) -> Complex {
+        let modulus_sq = self.real * self.real + self.imaginary * self.imaginary;
+        if modulus_sq == 0.0 {
+            return Complex::new(f64::INFINITY, 0.0);
+        }
+        Complex::new(self.real / modulus_sq, -self.imaginary / modulus_sq)
+    }
+
+    // Reads the complex number as the log of a radius (real part) and an angle (imaginary part)
+    // around {center}, and returns the number at that radius and angle
+    fn log_polar_point(&self, center: &Complex) -> Complex {
+        let radius = self.real.exp();
+        Complex::new(
+            center.real + radius * self.imaginary.cos(),
+            center.imaginary + radius * self.imaginary.sin(),
+        )
+    }
+
+    // Returns the absolute value (modulus) of the complex number
+    fn abs(&self) -> f64 {
+        (self.real * self.real + self.imaginary * self.imaginary).sqrt()
+    }
+
+    // Same as is_stable, but with an escape radius of sqrt({bailout_sq})
+    fn is_stable_radius(&self, stable_iterations: u32, bailout_sq: f64) -> bool {
+        !self.escape_info(stable_iterations, bailout_sq).escaped
+    }
+
+    // Runs the mandelbrot algorithm for at most {max} iterations with an escape radius of
+    // sqrt({bailout_sq}), and returns how far it got and where the number ended up. Everything
+    // that iterates to find out if a number escapes, on the CPU and the GPU, goes through this,
+    // so they all agree on every pixel.
+    fn escape_info(&self, max: u32, bailout_sq: f64) -> EscapeInfo {
+        self.escape_info_from(self, max, bailout_sq)
+    }
+
+    // Same as escape_info, but adds {origin} on every iteration instead of this number, like
+    // julia sets do
+    fn escape_info_from(&self, origin: &Complex, max: u32, bailout_sq: f64) -> EscapeInfo {
+        // Same as calling has_escaped_radius and iterate, but the squares of the escape test are
+        // reused for the next iteration, which saves two multiplications per iteration and
+        // gives the exact same numbers
+        let mut real = self.real;
+        let mut imaginary = self.imaginary;
+        for i in 0..max {
+            let real_sq = real * real;
+            let imaginary_sq = imaginary * imaginary;
+            if real_sq + imaginary_sq >= bailout_sq {
+                return EscapeInfo {
+                    iterations: i,
+                    escaped: true,
+                    final_re: real,
+                    final_im: imaginary,
+                };
+            }
+            imaginary = (real + real) * imaginary + origin.imaginary;
+            real = real_sq - imaginary_sq + origin.real;
+        }
+        EscapeInfo {
+            iterations: max,
+            escaped: false,
+            final_re: real,
+            final_im: imaginary,
+        }
+    }
+
+    // Runs the mandelbrot algorithm like is_stable, but returns None if the number is in the
+    // mandelbrot set, otherwise the iteration at which it escaped together with the escaped number.
+    // For julia sets, {julia} is the constant added on every iteration instead of this number.
+    fn escape_point(&self, julia: Option<&Complex>, stable_iterations: u32, bailout_sq: f64) -> Option<(u32, Complex)> {
+        let info = self.escape_info_from(julia.unwrap_or(self), stable_iterations, bailout_sq);
+        info.escaped
+            .then(|| (info.iterations, Complex::new(info.final_re, info.final_im)))
+    }
+
+    // Returns the period of the cycle the orbit of this number ends up in if it's in the set:
+    // after {stable_iterations} iterations to settle onto the cycle, the smallest p up to
+    // {max_period} for which the orbit comes back to within sqrt(ATOM_EPSILON_SQ) of where it
+    // was. Returns None if the number escapes or the orbit doesn't settle on a cycle that short.
+    // {julia} works like it does for escape_point.
+    fn interior_period(&self, julia: Option<&Complex>, stable_iterations: u32, bailout_sq: f64, max_period: u32) -> Option<u32> {
+        let origin = julia.unwrap_or(self);
+        let info = self.escape_info_from(origin, stable_iterations, bailout_sq);
+        if info.escaped {
+            return None;
+        }
+        let settled = Complex::new(info.final_re, info.final_im);
+        let mut copy = settled.clone();
+        for period in 1..=max_period {
+            copy.iterate(origin);
+            let (real, imaginary) = (copy.real - settled.real, copy.imaginary - settled.imaginary);
+            if real * real + imaginary * imaginary < ATOM_EPSILON_SQ {
+                return Some(period);
+            }
+        }
+        None
+    }
+
+    // Returns the largest modulus the orbit of this number reaches in {stable_iterations}
+    // iterations if it's in the set, or None if it escapes. Takes the same steps as
+    // escape_info_from, {julia} works like it does for escape_point.
+    fn interior_max_modulus(&self, julia: Option<&Complex>, stable_iterations: u32, bailout_sq: f64) -> Option<f64> {
+        let origin = julia.unwrap_or(self);
+        let mut real = self.real;
+        let mut imaginary = self.imaginary;
+        let mut max_sq: f64 = 0.0;
+        for _i in 0..stable_iterations {
+            let real_sq = real * real;
+            let imaginary_sq = imaginary * imaginary;
+            if real_sq + imaginary_sq >= bailout_sq {
+                return None;
+            }
+            max_sq = max_sq.max(real_sq + imaginary_sq);
+            imaginary = (real + real) * imaginary + origin.imaginary;
+            real = real_sq - imaginary_sq + origin.real;
+        }
+        Some(max_sq.sqrt())
+    }
+
+    // Runs the mandelbrot algorithm like escape_point, and returns the average absolute value of
+    // the iterated number over the orbit until it escaped, or over all {stable_iterations}
+    // iterations if it didn't. The number that escaped isn't counted, so the average stays within
+    // the bailout radius, which is what numbers that escape right away get.
+    fn orbit_average(&self, julia: Option<&Complex>, stable_iterations: u32, bailout_sq: f64) -> f64 {
+        let origin = julia.unwrap_or(self);
         let mut copy: Complex = self.clone();
+        let mut total = 0.0;
+        let mut count = 0;
         for _i in 0..stable_iterations {
-            if copy.has_escaped() {
-                return false;
+            if copy.has_escaped_radius(bailout_sq) {
+                break;
             }
-            copy.iterate(self);
+            total += copy.abs();
+            count += 1;
+            copy.iterate(origin);
+        }
+        if count == 0 {
+            bailout_sq.sqrt()
+        } else {
+            total / count as f64
         }
-        true
     }
+
+    // Runs the mandelbrot algorithm like is_stable, but also keeps track of the derivative
+    // of the iterated number with respect to this one. Returns None if the number is in the
+    // mandelbrot set, otherwise the smooth (fractional) iteration count at which it escaped
+    // together with an estimate of its distance to the mandelbrot set. {julia} works like it
+    // does for escape_point, the derivative is then taken with respect to the starting number.
+    fn escape_distance(&self, julia: Option<&Complex>, stable_iterations: u32, bailout_sq: f64) -> Option<(f64, f64)> {
+        let origin = julia.unwrap_or(self);
+        // The derivative of the added constant, which is this number for the mandelbrot set
+        let constant = if julia.is_some() { 0.0 } else { 1.0 };
+        let mut copy: Complex = self.clone();
+        let mut derivative = Complex::new(1.0, 0.0);
+        for i in 0..stable_iterations {
+            if copy.has_escaped_radius(bailout_sq) {
+                let abs = copy.abs();
+                let log_abs = abs.ln().max(f64::MIN_POSITIVE);
+                let smooth = smooth_iteration(i, abs, DEGREE);
+                let distance = abs * log_abs / derivative.abs();
+                return Some((smooth, distance));
+            }
+            // dz -> 2 * z * dz + 1 (or + 0 for julia sets)
+            let next_real = 2.0 * (copy.real * derivative.real - copy.imaginary * derivative.imaginary) + constant;
+            derivative.imaginary = 2.0 * (copy.real * derivative.imaginary + copy.imaginary * derivative.real);
+            derivative.real = next_real;
+            copy.iterate(origin);
+        }
+        None
+    }
+}
+
+// Returns the smooth (fractional) iteration count of an orbit that escaped at {iteration} with
+// an absolute value of {abs}, for an iteration of degree {degree}. Far from the set every
+// iteration raises the absolute value to about the power {degree}, so the log in base {degree} of
+// its log only goes up by 1 per iteration, and the count stays continuous where the iteration the
+// orbit escapes at changes. Any other base would leave bands there.
+pub fn smooth_iteration(iteration: u32, abs: f64, degree: u32) -> f64 {
+    let log_abs = abs.ln().max(f64::MIN_POSITIVE);
+    iteration as f64 + 1.0 - log_abs.ln() / (degree as f64).ln()
+}
+
+// Conversions from and to the complex numbers of the num-complex crate, so coordinates and
+// orbits can be passed to and from the rest of the Rust numerics ecosystem
+#[cfg(feature = "num-complex")]
+impl From<num_complex::Complex<f64>> for Complex {
+    fn from(value: num_complex::Complex<f64>) -> Complex {
+        Complex::new(value.re, value.im)
+    }
+}
+
+#[cfg(feature = "num-complex")]
+impl From<num_complex::Complex<f32>> for Complex {
+    fn from(value: num_complex::Complex<f32>) -> Complex {
+        Complex::new(value.re as f64, value.im as f64)
+    }
+}
+
+#[cfg(feature = "num-complex")]
+impl From<Complex> for num_complex::Complex<f64> {
+    fn from(value: Complex) -> num_complex::Complex<f64> {
+        num_complex::Complex::new(value.real, value.imaginary)
+    }
+}
+
+// The floating point precisions the CPU can calculate with
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Precision {
+    // Like the wgpu engine, see single.rs
+    F32,
+    F64,
+    // Pairs of f64s, see double_double.rs
+    DoubleDouble,
+}
+
+// What the image can be calculated on
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    Cpu,
+    // CUDA, see cuda_engine.rs, only in builds with the gpu feature
+    Gpu,
+    // The GPU if there is a usable CUDA device, otherwise the CPU, see resolve_engine
+    Auto,
+    // Any GPU, see wgpu_engine.rs
+    Wgpu,
+    // The GPU and the CPU at the same time, see hybrid.rs, only in builds with the gpu feature
+    Hybrid,
+}
+
+// The kinds of fractals that can be rendered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FractalKind {
+    // z -> z^2 + c, see Complex::is_stable
+    Mandelbrot,
+    // z -> z^2 + c for a fixed c, starting from z = the point
+    Julia { real: f64, imaginary: f64 },
 }
 
+#[derive(Clone)]
 pub struct MandelbrotCpu {
     threads: usize,
     image_width: usize,
@@ -122,10 +899,70 @@ pub struct MandelbrotCpu {
     i_step: f64,
     real_start: f64,
     i_start: f64,
-    iterations: i32,
+    // Low order parts of real_start and i_start, only used at double-double precision
+    real_start_lo: f64,
+    i_start_lo: f64,
+    iterations: u32,
+    // Scales iterations per pixel, see iterations_map.rs
+    iterations_map: Option<Arc<IterationsMap>>,
+    bailout_sq: f64,
+    fractal: FractalKind,
+    samples: usize,
+    // Only supersample pixels on edges, see adaptive.rs
+    supersample_threshold: Option<u32>,
+    // Total number of samples to spread over the pixels, see adaptive.rs
+    sample_budget: Option<usize>,
+    downsample_filter: DownsampleFilter,
+    // Whether the start of the view is the center of the top left pixel instead of its top left
+    // corner, see sample_centers
+    sample_center: bool,
+    // The order the rows are rendered in, see schedule.rs
+    row_order: RowOrder,
+    shading: Shading,
+    repeat: f64,
+    hsv_saturation: f64,
+    hsv_value: f64,
+    palette: BuiltinPalette,
+    // Power the palette position is raised to, see BuiltinPalette::sample_gamma
+    palette_gamma: f64,
+    // The color space the palette is interpolated in
+    color_space: ColorSpace,
+    // Number of final iterations faded into the color of the set, see color::fade_to_interior
+    smooth_iter_clamp: u32,
+    // Color the set by the period of its cycles, see Complex::interior_period
+    atom_domains: bool,
+    // Shade the set by the largest modulus of its orbits, see Complex::interior_max_modulus
+    interior_modulus: bool,
+    // Gamma the colors are corrected with, see color::apply_gamma
+    gamma: f64,
+    // Render the plane inverted through 1/c, see Complex::inverse
+    invert_plane: bool,
+    // Center of a log-polar zoom strip, see log_polar_view
+    log_polar: Option<Complex>,
+    // Affine transform of the points of the view, see transform.rs
+    transform: Option<transform::Affine>,
+    // Where the orbits of the mandelbrot set start instead of z = 0, see orbit_start
+    start_z: Option<Complex>,
+    // Formula iterated instead of z² + c, see formula.rs
+    formula: Option<Arc<formula::Formula>>,
+    precision: Precision,
+    // Only read by the CUDA engine, see cuda_engine.rs
+    #[cfg_attr(not(feature = "gpu"), allow(dead_code))]
+    gpu_retries: u32,
+    // Calculate failed GPU chunks on the CPU, see cuda_engine::build_mandelbrot_gpu
+    #[cfg_attr(not(feature = "gpu"), allow(dead_code))]
+    gpu_cpu_fallback: bool,
+    // Share of the rows the GPU renders with --engine hybrid, see hybrid.rs
+    #[cfg_attr(not(feature = "gpu"), allow(dead_code))]
+    gpu_fraction: f64,
+    // Print how the GPU render is split into launches, see launch.rs
+    #[cfg_attr(not(feature = "gpu"), allow(dead_code))]
+    verbose: bool,
+    // Whether to show the progress while rendering, see progress.rs
+    progress: bool,
 }
 
-impl MandelbrotCpu {
+impl Default for MandelbrotCpu {
     fn default() -> MandelbrotCpu {
         MandelbrotCpu {
             threads: THREADS,
@@ -135,394 +972,1555 @@ impl MandelbrotCpu {
             i_step: RADIUS / (IMAGE_DIM as f64),
             real_start: REAL_CENTER - (RADIUS / 2.0),
             i_start: I_CENTER + (RADIUS / 2.0),
+            real_start_lo: 0.0,
+            i_start_lo: 0.0,
             iterations: STABLE_ITERATIONS,
+            iterations_map: None,
+            bailout_sq: BAILOUT_SQ,
+            fractal: FractalKind::Mandelbrot,
+            samples: SAMPLES,
+            supersample_threshold: None,
+            sample_budget: None,
+            downsample_filter: DownsampleFilter::Box,
+            sample_center: false,
+            row_order: RowOrder::TopDown,
+            shading: Shading::Flat,
+            repeat: REPEAT,
+            hsv_saturation: 1.0,
+            hsv_value: 1.0,
+            palette: BuiltinPalette::Grayscale,
+            palette_gamma: 1.0,
+            color_space: ColorSpace::Srgb,
+            smooth_iter_clamp: 0,
+            atom_domains: false,
+            interior_modulus: false,
+            gamma: 1.0,
+            invert_plane: false,
+            log_polar: None,
+            transform: None,
+            start_z: None,
+            formula: None,
+            precision: Precision::F64,
+            gpu_retries: 0,
+            gpu_cpu_fallback: false,
+            gpu_fraction: hybrid::GPU_FRACTION,
+            verbose: false,
+            progress: true,
         }
     }
 }
 
-fn main() {
-    // Parse the command line arguments and store the most commonly used ones in variables
-    let args = Args::parse();
-    let image_width: usize = args.image_size[0];
-    let image_height: usize = args.image_size[1];
+impl MandelbrotCpu {
+    // Returns whether {point} is in the fractal described by these options
+    fn contains(&self, point: &Complex) -> bool {
+        if let Some(formula) = &self.formula {
+            return formula.escape(point, self.julia_constant().as_ref(), self.iterations, self.bailout_sq).is_none();
+        }
+        match self.orbit_start(point) {
+            (start, None) => start.is_stable_radius(self.iterations, self.bailout_sq),
+            (start, constant) => start.escape_point(constant.as_ref(), self.iterations, self.bailout_sq).is_none(),
+        }
+    }
 
-    let real_step: f64 = args.size[0] / (image_width as f64);
-    let i_step: f64 = args.size[1] / (image_height as f64);
+    // Returns the number the orbit of {point} starts from, and the constant added on every
+    // iteration if that isn't the starting number, like julia_constant. The mandelbrot orbit
+    // normally starts from the point itself, which is z = 0 one iteration in. With a start_z
+    // it starts from start_z² + point instead, adding the point on every iteration. The smooth
+    // shadings then take the distance estimate with respect to that start instead of the point,
+    // which is close enough for shading. A start_z of 0 is the usual orbit.
+    fn orbit_start(&self, point: &Complex) -> (Complex, Option<Complex>) {
+        match (self.fractal, &self.start_z) {
+            (FractalKind::Mandelbrot, Some(start_z)) if start_z.real != 0.0 || start_z.imaginary != 0.0 => {
+                let mut start = start_z.clone();
+                start.iterate(point);
+                (start, Some(point.clone()))
+            }
+            _ => (point.clone(), self.julia_constant()),
+        }
+    }
 
-    let real_start: f64 = -(args.size[0] / 2.0) + args.center[0];
-    let i_start: f64 = args.size[1] / 2.0 + args.center[1];
+    // Returns the constant of the julia set if that is the fractal being rendered
+    fn julia_constant(&self) -> Option<Complex> {
+        match self.fractal {
+            FractalKind::Mandelbrot => None,
+            FractalKind::Julia { real, imaginary } => Some(Complex::new(real, imaginary)),
+        }
+    }
 
-    let threads = args.threads;
+    // Returns the iteration count for the pixel at {column} in {row}
+    fn iterations_at(&self, row: usize, column: usize) -> u32 {
+        match &self.iterations_map {
+            Some(map) => map.iterations(self.iterations, row, column),
+            None => self.iterations,
+        }
+    }
 
-    let options = MandelbrotCpu {
-        threads,
-        image_width: image_width,
-        image_height: image_height,
-        real_step,
-        i_step,
-        real_start,
-        i_start,
-        iterations: args.iterations,
-    };
-    let final_image = if args.gpu {
-        build_mandelbrot_gpu(&options)
-    } else {
-        // If the GPU is not used, use the CPU version
-        build_mandelbrot_cpu(&options)
-    };
+    // Returns the iteration at which the orbit of {point} in the plane escaped, or None if it
+    // stayed within the bailout for {iterations} iterations. Iterates the formula if there is one.
+    fn escape_iteration(&self, point: &Complex, iterations: u32) -> Option<u32> {
+        if let Some(formula) = &self.formula {
+            return formula.escape(point, self.julia_constant().as_ref(), iterations, self.bailout_sq);
+        }
+        let (start, constant) = self.orbit_start(point);
+        start.escape_point(constant.as_ref(), iterations, self.bailout_sq).map(|(i, _)| i)
+    }
 
-    // Create the image file with the given name
-    let image_path = Path::new(&args.file);
+    // Returns the iteration at which the pixel at {column} in {row} escaped, or the iteration
+    // count of the pixel if it's in the set
+    fn escape_count(&self, row: usize, column: usize) -> u32 {
+        let iterations = self.iterations_at(row, column);
+        self.escape_iteration(&self.plane_point(&self.pixel_point(row, column)), iterations).unwrap_or(iterations)
+    }
 
-    // Write the image contents to a file (format automatically deduced from filename)
-    image::save_buffer(
-        image_path,
-        &final_image,
-        image_width as u32,
-        image_height as u32,
-        ColorType::L8,
-    )
-    .expect("Couldn't create or overwrite file!");
+    // Returns the coordinates in mandelbrot space of the point the pixel at {column} in {row} is
+    // sampled at: its top left corner, or its center if the view samples the centers
+    fn pixel_point(&self, row: usize, column: usize) -> Complex {
+        Complex::new(
+            self.real_start + column as f64 * self.real_step,
+            self.i_start - row as f64 * self.i_step,
+        )
+    }
 
-    // Done! (image files close automatically when dropped)
-    println!(
-        "\nDone. File outputted to {:?}",
-        dunce::canonicalize(Path::new(&args.file)).unwrap()
-    );
-}
+    // How far the point pixel_point samples is from the top left corner of the pixel, as a
+    // fraction of a pixel
+    fn sample_offset(&self) -> f64 {
+        if self.sample_center {
+            0.5
+        } else {
+            0.0
+        }
+    }
 
+    // Number of bytes every pixel of the rendered image takes up
+    fn bytes_per_pixel(&self) -> usize {
+        if self.shading.is_grayscale() && !self.atom_domains {
+            1
+        } else {
+            3
+        }
+    }
 
-// ==========================================================================
-//  CPU Mandelbrot Functions
-// ==========================================================================
+    // Returns the color of the pixel at {point}. Grayscale shadings return the same
+    // value for all three channels.
+    fn pixel_color(&self, point: &Complex) -> [u8; 3] {
+        color::apply_gamma(self.shade(&self.plane_point(point)), self.gamma)
+    }
 
-pub fn build_mandelbrot_cpu(options: &MandelbrotCpu) -> Vec<u8> {
-    let MandelbrotCpu {
-        threads,
-        image_width,
-        image_height,
-        real_step,
-        i_step,
-        real_start,
-        i_start,
-        iterations,
-    } = *options;
+    // Returns the number in the plane that {point} of the view shows, which is {point} itself
+    // unless the view is transformed, a log-polar strip or the plane is inverted
+    fn plane_point(&self, point: &Complex) -> Complex {
+        let point = match &self.transform {
+            Some(transform) => transform.apply(point),
+            None => point.clone(),
+        };
+        let point = match &self.log_polar {
+            Some(center) => point.log_polar_point(center),
+            None => point,
+        };
+        if self.invert_plane {
+            return point.inverse();
+        }
+        point
+    }
 
-    // Create two senders and recievers for thread communication,
-    // one for progress reports, and one to receive the completed image
-    // slice from a thread. (This method of completion isn't ideal, but this project
-    // was created before I knew about thread joining, possible TODO)
-    let (tx, rx) = mpsc::channel();
-    let (ptx, prx) = mpsc::channel();
-
-    // Initialize an array to hold a slice of the final image for each thread
-    let mut image_slices = vec![];
-
-    // Split the image up into even vertical slices, accounting for any remaining height
-    let slice_height = image_height / threads;
-    let slice_remainder = image_height % threads;
-
-    // Initalize the progress counter variables
-    let mut progress: f64 = 0.0;
-    let total: f64 = image_height as f64;
-
-    println!("Generating Image...");
-
-    // Start spawning threads
-    for i in 0..threads {
-        // Set the initial x and y values for mandelbrot calculations to the
-        // top right corner of the image slice.
-        let mut x = real_start;
-        let mut y = i_start - ((i * slice_height) as f64) * (i_step);
-
-        // Set the variable for how many rows this thread has of the image, giving
-        // any remaining height to the last thread
-        let mut this_height: usize;
-        if i != threads - 1 {
-            this_height = slice_height;
+    // Returns the color of the pixel at {point} before gamma correction
+    fn shade(&self, point: &Complex) -> [u8; 3] {
+        // Formulas are only rendered with flat shading, see main
+        if self.formula.is_some() {
+            return if self.contains(point) { [0; 3] } else { [u8::MAX; 3] };
+        }
+        let (start, julia) = self.orbit_start(point);
+        let point = &start;
+        if self.atom_domains {
+            if let Some(period) = point.interior_period(julia.as_ref(), self.iterations, self.bailout_sq, MAX_ATOM_PERIOD) {
+                return color::atom_domain_color(period);
+            }
+        }
+        if self.interior_modulus {
+            if let Some(modulus) = point.interior_max_modulus(julia.as_ref(), self.iterations, self.bailout_sq) {
+                return [color::interior_modulus_shade(modulus, self.bailout_sq); 3];
+            }
+        }
+        let fade = |color: [u8; 3], smooth: f64| {
+            color::fade_to_interior(color, smooth, self.iterations, self.smooth_iter_clamp)
+        };
+        match self.shading {
+            Shading::Flat => {
+                if point.escape_point(julia.as_ref(), self.iterations, self.bailout_sq).is_none() {
+                    [0; 3]
+                } else {
+                    [u8::MAX; 3]
+                }
+            }
+            Shading::Hybrid => match point.escape_distance(julia.as_ref(), self.iterations, self.bailout_sq) {
+                None => [0; 3],
+                Some((smooth, distance)) => fade(
+                    [color::hybrid_shade(smooth, self.iterations, distance, self.real_step); 3],
+                    smooth,
+                ),
+            },
+            Shading::HsvCycle => match point.escape_distance(julia.as_ref(), self.iterations, self.bailout_sq) {
+                None => [0; 3],
+                Some((smooth, _)) => fade(
+                    color::hsv_cycle(
+                        smooth / self.iterations as f64,
+                        self.repeat,
+                        self.hsv_saturation,
+                        self.hsv_value,
+                    ),
+                    smooth,
+                ),
+            },
+            Shading::Palette => match point.escape_distance(julia.as_ref(), self.iterations, self.bailout_sq) {
+                None => [0; 3],
+                // Most pixels escape within the first few iterations, a logarithmic scale spreads
+                // them over the palette
+                Some((smooth, _)) => fade(
+                    self.palette
+                        .sample_gamma(smooth.max(0.0).ln_1p() / (self.iterations as f64).ln_1p(), self.palette_gamma, self.color_space),
+                    smooth,
+                ),
+            },
+            Shading::BinaryDecomp => match point.escape_point(julia.as_ref(), self.iterations, self.bailout_sq) {
+                None => [0; 3],
+                Some((_, escaped)) => [color::binary_decomp_shade(escaped.imaginary); 3],
+            },
+            Shading::OrbitAverage => {
+                [color::orbit_average_shade(point.orbit_average(julia.as_ref(), self.iterations, self.bailout_sq), self.bailout_sq); 3]
+            }
+            Shading::Angle => match point.escape_point(julia.as_ref(), self.iterations, self.bailout_sq) {
+                None => [0; 3],
+                Some((_, escaped)) => color::hsv_to_rgb(
+                    color::escape_angle(escaped.real, escaped.imaginary),
+                    self.hsv_saturation,
+                    self.hsv_value,
+                ),
+            },
+        }
+    }
+
+    // Writes the color of the pixel at {point} to {pixel}, which is bytes_per_pixel long
+    fn write_pixel(&self, point: &Complex, pixel: &mut [u8]) {
+        let color = self.pixel_color(point);
+        pixel.copy_from_slice(&color[..pixel.len()]);
+    }
+
+    // Renders the pixel with its top left corner at (x, y) into {pixel}, supersampling it if
+    // that is enabled
+    fn render_pixel(&self, x: f64, y: f64, pixel: &mut [u8]) {
+        if self.samples > 1 {
+            supersample_pixel(self, x, y, pixel);
         } else {
-            this_height = slice_height + slice_remainder;
+            self.write_pixel(&Complex::new(x, y), pixel);
         }
+    }
 
-        // If there are more threads than there are rows, just give it all to one thread.
-        if image_height < threads {
-            this_height = image_height;
+    // The color type of the rendered image
+    fn color_type(&self) -> ColorType {
+        if self.bytes_per_pixel() == 1 {
+            ColorType::L8
+        } else {
+            ColorType::Rgb8
         }
+    }
+}
 
-        // Clone the senders and spawn the thread
-        let ptxc = ptx.clone();
-        let txc = tx.clone();
-        thread::spawn(move || {
-            let thread_num = i;
+fn main() {
+    let mut timings = timings::Timings::start();
+    // Parse the command line arguments and store the most commonly used ones in variables
+    let mut args = Args::parse();
+    if let Some(path) = args.from_image.clone() {
+        args = args_from_image(Path::new(&path), &std::env::args_os().collect::<Vec<_>>()).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+    }
 
-            // Create a buffer to store the image slice in, initializing all pixels to white (0)
-            let mut this_slice = vec![u8::MAX; this_height * image_width];
+    if let Some(Commands::Completions { shell }) = args.command {
+        write_completions(shell, &mut std::io::stdout());
+        return;
+    }
+    if args.dump_args {
+        println!("{}", serde_json::to_string_pretty(&args).unwrap());
+        return;
+    }
+    if let Some(path) = &args.read_params {
+        let params = params::read_params(Path::new(path)).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        println!("{}", params);
+        return;
+    }
 
-            // Iterate over the slice pixel by pixel.
-            for i in 0..this_height {
-                for j in 0..image_width {
-                    let point = Complex::new(x, y);
-                    // If this point is stable, draw a black pixel (1)
-                    if point.is_stable(iterations) {
-                        this_slice[j + (i * image_width)] = 0;
-                    }
-                    x += real_step;
-                }
-                x = real_start;
-                y -= i_step;
-                // Send a progress report for every row.
-                ptxc.send(1.0).unwrap();
-            }
+    let output_values = naming::NameValues {
+        frame: 0,
+        index: 0,
+        zoom: RADIUS / args.size[0],
+        re: args.center[0].to_string(),
+        im: args.center[1].to_string(),
+    };
+    let output_directory = args.output_directory.as_deref().map(Path::new);
+    let output = naming::output_path(output_directory, args.name_template.as_deref(), &args.file, &output_values)
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+    if let Some(directory) = output_directory {
+        std::fs::create_dir_all(directory).unwrap_or_else(|error| {
+            eprintln!("Couldn't create the output directory {:?}: {}", directory, error);
+            std::process::exit(1);
+        });
+    }
+    let image_path = output.as_path();
+    let encoders = EncoderRegistry::default();
+
+    // Stitching partial images together doesn't render anything
+    if !args.stitch.is_empty() {
+        let stitched = if args.tile_index {
+            tile_index::build_tile_index(&args.stitch)
+                .map(|index| (index.image, index.width, index.height, index.color_type))
+        } else {
+            stitch_images(&args.stitch)
+        };
+        let (image, width, height, color_type) = stitched.unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        encoders.encode(image_path, &image, width, height, color_type).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        println!(
+            "Done. File outputted to {:?}",
+            dunce::canonicalize(image_path).unwrap()
+        );
+        return;
+    }
 
-            // Send the completed image slice to the main thread, along with this
-            // thread's number for re-ordering.
-            let message = (thread_num, this_slice);
-            txc.send(message).unwrap();
+    // The pixel indices are usizes, an image with more bytes than that can't be indexed
+    let oversize = args.oversize_then_crop as usize;
+    let (width, height) = (args.image_size[0].checked_mul(oversize), args.image_size[1].checked_mul(oversize));
+    if width.zip(height).and_then(|(width, height)| width.checked_mul(height)).and_then(|pixels| pixels.checked_mul(3)).is_none() {
+        let oversized = if oversize > 1 { format!(" at {} times the resolution", oversize) } else { String::new() };
+        eprintln!("An image of {} x {} pixels{} is too large to render", args.image_size[0], args.image_size[1], oversized);
+        std::process::exit(1);
+    }
+    let mut options = options_from_args(&args);
+    if let Some(warning) = args.threads.and_then(|threads| oversubscription_warning(threads, logical_cores())) {
+        println!("Warning: {}", warning);
+    }
+    let (image_width, image_height) = (options.image_width, options.image_height);
+    if let Some(center) = &args.log_polar {
+        let radius = args.size[0].max(args.size[1]) / 2.0;
+        options = log_polar_view(&options, Complex::new(center[0], center[1]), radius);
+    }
+    if args.snap_to_boundary {
+        match snap::snap_to_boundary(&options) {
+            None => println!("There is no boundary of the set near the center, ignoring --snap-to-boundary"),
+            Some(snapped) => {
+                println!(
+                    "Snapped the center to the boundary of the set, --center {} {}",
+                    snapped.real_start + image_width as f64 * snapped.real_step / 2.0,
+                    snapped.i_start - image_height as f64 * snapped.i_step / 2.0
+                );
+                options = snapped;
+            }
+        }
+    }
+    if args.crop_to_set && args.log_polar.is_some() {
+        println!("A log-polar strip can't be cropped to the set, ignoring --crop-to-set");
+    } else if args.crop_to_set {
+        match crop::crop_to_set(&options) {
+            None => println!("The set isn't in the view, ignoring --crop-to-set"),
+            Some(cropped) => {
+                let (width, height) = (image_width as f64 * cropped.real_step, image_height as f64 * cropped.i_step);
+                println!(
+                    "Cropped the view to the set, --center {} {} --size {} {}",
+                    cropped.real_start + width / 2.0,
+                    cropped.i_start - height / 2.0,
+                    width,
+                    height
+                );
+                options = cropped;
+            }
+        }
+    }
+    if let Some(offset) = &args.pixel_offset {
+        options = pixel_offset(&options, offset[0], offset[1]);
+    }
+    if !args.sample_corners {
+        options = sample_centers(&options);
+    }
+    if let Some(path) = &args.iterations_map_file {
+        let map = IterationsMap::load(Path::new(path), image_width, image_height).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        options.iterations_map = Some(Arc::new(map));
+    }
+    if let Some(rows) = &args.row_range {
+        if rows[0] >= rows[1] || rows[1] > image_height {
+            eprintln!(
+                "Invalid row range {}..{}, it must be a non-empty range within the image height of {}",
+                rows[0], rows[1], image_height
+            );
+            std::process::exit(1);
+        }
+        options = row_range(&options, rows[0], rows[1]);
+    }
+    check_pixel_limit(&options, oversize, args.max_pixels).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    let engine = resolve_engine(if args.gpu { Engine::Gpu } else { args.engine }, gpu_available);
+    if args.engine == Engine::Auto {
+        println!("Using the {}", if engine == Engine::Gpu { "GPU" } else { "CPU, there is no usable CUDA device" });
+    }
+    if let Some(error) = engine_unsupported(engine) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    if args.report_device_info {
+        match engine {
+            #[cfg(feature = "gpu")]
+            Engine::Gpu | Engine::Hybrid => match cuda_engine::device_info() {
+                Ok(info) => println!("{}", info),
+                Err(error) => println!("Couldn't read the properties of the CUDA device: {}", error),
+            },
+            _ => println!("Device info is only reported for CUDA devices, ignoring --report-device-info"),
+        }
+    }
+    let gpu = engine != Engine::Cpu;
+    if (gpu || options.precision != Precision::F64) && options.iterations_map.is_some() {
+        println!("Iteration maps are only supported on the CPU at f64 precision, ignoring --iterations-map-file");
+        options.iterations_map = None;
+    }
+    if args.buddhabrot {
+        if gpu {
+            println!("The buddhabrot is always rendered on the CPU");
+        }
+        if options.shading != Shading::Flat || options.atom_domains {
+            println!("The buddhabrot is grayscale, ignoring --shading and --atom-domains");
+            options.shading = Shading::Flat;
+            options.atom_domains = false;
+        }
+    }
+    if gpu && args.samples > 1 && !args.buddhabrot {
+        println!("Supersampling is not supported on the GPU, ignoring --samples");
+    }
+    if (gpu || options.precision != Precision::F64) && options.sample_budget.is_some() {
+        println!("Sample budgets are only supported on the CPU at f64 precision, ignoring --sample-budget");
+        options.sample_budget = None;
+    }
+    if gpu && options.shading != Shading::Flat {
+        println!("Shading is not supported on the GPU, ignoring --shading");
+        options.shading = Shading::Flat;
+    }
+    if (gpu || options.precision != Precision::F64) && options.atom_domains {
+        println!("Atom domains are only supported on the CPU at f64 precision, ignoring --atom-domains");
+        options.atom_domains = false;
+    }
+    if (gpu || options.precision != Precision::F64) && options.log_polar.is_some() {
+        eprintln!("Log-polar strips are only supported on the CPU at f64 precision");
+        std::process::exit(1);
+    }
+    if (gpu || options.precision != Precision::F64) && options.invert_plane {
+        println!("Inverting the plane is only supported on the CPU at f64 precision, ignoring --invert-plane");
+        options.invert_plane = false;
+    }
+    if (gpu || options.precision != Precision::F64) && options.transform.is_some() {
+        println!("Transforming the view is only supported on the CPU at f64 precision, ignoring --transform");
+        options.transform = None;
+    }
+    if (gpu || options.precision != Precision::F64) && options.start_z.is_some() {
+        println!("Starting the orbits elsewhere is only supported on the CPU at f64 precision, ignoring --start-z");
+        options.start_z = None;
+    }
+    if (gpu || options.precision != Precision::F64) && options.formula.is_some() {
+        println!("Formulas are only supported on the CPU at f64 precision, ignoring --fractal-formula");
+        options.formula = None;
+    }
+    // These follow the orbits of z² + c themselves, not just when they escape
+    let orbit_mode = [
+        (args.buddhabrot, "--buddhabrot"),
+        (args.mandel_julia.is_some(), "--mandel-julia"),
+        (args.orbit_cloud.is_some(), "--orbit-cloud"),
+        (args.export_channels.is_some(), "--export-channels"),
+    ]
+    .into_iter()
+    .find_map(|(given, mode)| given.then_some(mode));
+    if let (Some(mode), Some(_)) = (orbit_mode, &options.formula) {
+        println!("{} doesn't support formulas, ignoring --fractal-formula", mode);
+        options.formula = None;
+    }
+    if let Some(formula) = &options.formula {
+        println!(
+            "Iterating {:?}, formulas are experimental and several times slower than the built-in iteration",
+            formula.source()
+        );
+        if options.start_z.is_some() {
+            println!("Formulas start the orbits at 0, ignoring --start-z");
+            options.start_z = None;
+        }
+        if options.shading != Shading::Flat || options.atom_domains || options.interior_modulus {
+            println!("Formulas only support flat shading, ignoring --shading");
+            options.shading = Shading::Flat;
+            options.atom_domains = false;
+            options.interior_modulus = false;
+        }
+        if options.samples > 1 || options.sample_budget.is_some() {
+            println!("Formulas don't support supersampling, ignoring --samples");
+            options.samples = 1;
+            options.sample_budget = None;
+        }
+    }
+    if (gpu || options.precision != Precision::F64) && options.interior_modulus {
+        println!("Interior shading is only supported on the CPU at f64 precision, ignoring --color-interior-by-iteration-fraction");
+        options.interior_modulus = false;
+    }
+    if gpu && options.precision != Precision::F64 {
+        println!(
+            "The GPU always calculates in {}, ignoring --precision",
+            if engine == Engine::Wgpu { "f32" } else { "f64" }
+        );
+        options.precision = Precision::F64;
+    }
+    if options.precision != Precision::F64 {
+        let name = if options.precision == Precision::F32 { "f32" } else { "Double-double" };
+        if options.shading != Shading::Flat {
+            println!("{} precision only supports flat shading, ignoring --shading", name);
+            options.shading = Shading::Flat;
+        }
+        if options.samples > 1 {
+            println!("{} precision doesn't support supersampling, ignoring --samples", name);
+            options.samples = 1;
+        }
+    }
+    if args.report_precision_exhaustion {
+        let (name, epsilon, advice) = match (engine, options.precision) {
+            (Engine::Wgpu, _) => ("f32", f32::EPSILON as f64, "use --engine cpu or --engine gpu, which calculate in f64"),
+            (_, Precision::F32) => ("f32", f32::EPSILON as f64, "use --precision f64"),
+            (_, Precision::F64) => ("f64", f64::EPSILON, "use --precision double-double on the CPU"),
+            (_, Precision::DoubleDouble) => ("Double-double", f64::EPSILON * f64::EPSILON, "this is as deep as Gendel goes"),
+        };
+        if precision_exhausted(&options, epsilon) {
+            println!(
+                "Warning: {} precision exhausted, neighboring pixels are no longer distinct; {}",
+                name, advice
+            );
+        }
+    }
+    if args.probe_precision {
+        println!("{}", precision_report(&options));
+    }
+    if has_extension(image_path, "pbm") && options.bytes_per_pixel() != 1 {
+        println!("PBM files only hold black and white pixels, ignoring --shading and --atom-domains");
+        options.shading = Shading::Flat;
+        options.atom_domains = false;
+    }
+    // Before anything is calculated, so the modes that write something else than an image run at
+    // the lowered priority too
+    if let Some(nice) = args.nice {
+        if let Err(error) = priority::lower_priority(nice) {
+            println!("Couldn't lower the priority ({}), rendering at normal priority", error);
+        }
+    }
+    if args.clamp_iterations_to_detail || args.auto_clamp {
+        if let Some(estimate) = detail::estimate_sufficient_iterations(&options, detail::DETAIL_GRID) {
+            let sufficient = estimate.saturating_mul(detail::SAFETY_FACTOR).max(PREVIEW_ITERATIONS);
+            if options.iterations > sufficient.saturating_mul(10) && !args.auto_clamp {
+                println!(
+                    "Warning: almost everything in this view escapes within {} iterations, --iterations {} is probably much more than needed",
+                    estimate, options.iterations
+                );
+            } else if options.iterations > sufficient && args.auto_clamp {
+                println!("Lowering the iteration count from {} to {}", options.iterations, sufficient);
+                options.iterations = sufficient;
+            }
+        }
+    }
+    if let Some(path) = &args.dump_coords {
+        let pixels = options.image_width * options.image_height;
+        if pixels > MAX_DUMP_PIXELS {
+            eprintln!(
+                "The image has {} pixels, --dump-coords only supports images of up to {} pixels",
+                pixels, MAX_DUMP_PIXELS
+            );
+            std::process::exit(1);
+        }
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path).expect("Couldn't create or overwrite file!"));
+        write_coordinates(&options, &mut file).expect("Couldn't write the coordinates!");
+        println!("Done. Coordinates outputted to {:?}", dunce::canonicalize(Path::new(path)).unwrap());
+        return;
+    }
+    if let Some(stride) = args.orbit_cloud {
+        let path = image_path.with_extension("csv");
+        let write = |path: &Path| -> std::io::Result<usize> {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            let written = orbits::write_orbit_cloud(&options, stride as usize, orbits::MAX_ORBIT_POINTS, &mut file)?;
+            file.flush()?;
+            Ok(written)
+        };
+        let written = write(&path).unwrap_or_else(|error| {
+            eprintln!("Couldn't write the orbits to {:?}: {}", path, error);
+            std::process::exit(1);
         });
+        if written == orbits::MAX_ORBIT_POINTS {
+            println!("The orbits were cut off at {} points, use a larger stride to get all of them", written);
+        }
+        println!("Done. {} orbit points outputted to {:?}", written, dunce::canonicalize(&path).unwrap());
+        return;
+    }
+    if let Some(path) = &args.export_iterations {
+        let counts = rle::IterationCounts {
+            width: options.image_width,
+            height: options.image_height,
+            iterations: options.iterations,
+            counts: render_iterations(&options),
+        };
+        std::fs::write(path, counts.encode()).expect("Couldn't create or overwrite file!");
+        println!("\nDone. Iteration counts outputted to {:?}", dunce::canonicalize(Path::new(path)).unwrap());
+        return;
+    }
+    if let Some(max_pixels) = args.find_minibrots {
+        let minibrots = minibrots::find_minibrots(&options, max_pixels as usize);
+        println!("\nFound {} candidate copies of the set", minibrots.len());
+        for minibrot in &minibrots {
+            println!(
+                "--center {} {} --size {} {} ({} pixels)",
+                minibrot.re, minibrot.im, minibrot.scale, minibrot.scale, minibrot.pixels
+            );
+        }
+        return;
+    }
+    if let Some(path) = &args.iterations_histogram {
+        let histogram = iteration_histogram(&render_iterations(&options));
+        let write = |path: &Path| -> std::io::Result<()> {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            write_iteration_histogram(&histogram, &mut file)?;
+            file.flush()
+        };
+        write(Path::new(path)).unwrap_or_else(|error| {
+            eprintln!("Couldn't write the histogram to {:?}: {}", path, error);
+            std::process::exit(1);
+        });
+        println!("\nDone. Iteration histogram outputted to {:?}", dunce::canonicalize(Path::new(path)).unwrap());
+        return;
+    }
+    let line = match (args.scanline, args.scanline_col) {
+        (Some(row), _) => Some(scanline::Scanline::Row(row)),
+        (_, Some(column)) => Some(scanline::Scanline::Column(column)),
+        _ => None,
+    };
+    if let Some(line) = line {
+        let pixels = line.pixels(&options).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        let counts = scanline::scanline_counts(&options, &pixels);
+        let path = image_path.with_extension("csv");
+        let write = |path: &Path| -> std::io::Result<()> {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            scanline::write_scanline(&options, &pixels, &counts, &mut file)?;
+            file.flush()
+        };
+        write(&path).unwrap_or_else(|error| {
+            eprintln!("Couldn't write the scanline to {:?}: {}", path, error);
+            std::process::exit(1);
+        });
+        println!("\nDone. Escape counts of {} pixels outputted to {:?}", pixels.len(), dunce::canonicalize(&path).unwrap());
+        return;
+    }
+    if let Some(path) = &args.export_channels {
+        let channels = channels::render_channels(&options);
+        channels::write_exr(Path::new(path), &channels, options.image_width, options.image_height).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        println!("\nDone. Escape channels outputted to {:?}", dunce::canonicalize(Path::new(path)).unwrap());
+        return;
+    }
 
-        // In the case that there are more threads than rows in the image, cease
-        // spawning threads because we're giving it all to 1 thread.
-        if image_height < threads {
-            break;
+    let adaptive = !gpu
+        && options.precision == Precision::F64
+        && ((options.samples > 1 && options.supersample_threshold.is_some()) || options.sample_budget.is_some());
+    if let Some(path) = &args.sample_count_map {
+        if adaptive {
+            let counts = adaptive::sample_counts(&options);
+            let most = if options.sample_budget.is_some() { adaptive::MAX_BUDGET_SAMPLES } else { options.samples };
+            let map = adaptive::sample_count_map(&counts, most);
+            let (width, height) = (options.image_width as u32, options.image_height as u32);
+            image::save_buffer(Path::new(path), &map, width, height, ColorType::L8).expect("Couldn't create or overwrite file!");
+            println!("\nSample count map outputted to {:?}", dunce::canonicalize(Path::new(path)).unwrap());
+        } else {
+            println!("The image isn't adaptively supersampled, ignoring --sample-count-map");
         }
     }
 
-    // Start keeping track of how many threads have completed their task.
-    // In the edge case that only 1 thread was created due to the row issue mentioned above,
-    // set the number of done threads to 1 less than the expected number of threads (which in
-    // reality is only 1)
-    let mut done_threads = 0;
-    if image_height < threads {
-        done_threads = threads - 1;
+    let render: fn(&MandelbrotCpu) -> Vec<u8> = match engine {
+        #[cfg(feature = "gpu")]
+        Engine::Gpu => cuda_engine::build_mandelbrot_gpu_or_exit,
+        #[cfg(feature = "gpu")]
+        Engine::Hybrid => cuda_engine::build_mandelbrot_hybrid_or_exit,
+        #[cfg(feature = "wgpu")]
+        Engine::Wgpu => wgpu_engine::build_mandelbrot_wgpu_or_exit,
+        _ if options.precision == Precision::DoubleDouble => double_double::build_mandelbrot_double_double,
+        _ if options.precision == Precision::F32 => single::build_mandelbrot_f32,
+        _ if options.formula.is_some() => formula::build_mandelbrot_formula,
+        _ if adaptive => adaptive::build_mandelbrot_adaptive,
+        // If the GPU is not used, use the CPU version
+        _ => build_mandelbrot_cpu,
+    };
+
+    timings.finish("arguments");
+
+    if args.preview_ascii_live {
+        ascii::preview_live(&options, render);
+        return;
+    }
+    if args.compute_only {
+        let report = compute_only(&options, |options| oversize::build_mandelbrot_oversized(options, render, oversize));
+        println!("\n{}", report);
+        return;
     }
 
-    // Wait for all threads to be done
-    while done_threads < threads {
-        // Receive messages from completed threads
-        match rx.try_recv() {
-            Ok(image_slice) => {
-                // Store the image slice from the thread and increment the thread counter
-                done_threads += 1;
-                image_slices.push(image_slice);
-            }
-            // Check for any disconnect errors
-            Err(error) => {
-                if error == mpsc::TryRecvError::Disconnected {
-                    println!("Main Disconnected!");
+    let is_png = has_extension(image_path, "png");
+    let params = embedded_params(&args, is_png);
+
+    let frame_cache = args.frame_cache.as_deref().map(|directory| {
+        let cache = frame_cache::FrameCache::new(Path::new(directory)).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        let hash = frame_cache::params_hash(&serde_json::to_string(&args).unwrap()).unwrap();
+        (cache, hash)
+    });
+    // Flags that report on the render itself, which a copied frame doesn't have
+    let render_report = [
+        (args.validate_output, "--validate-output"),
+        (args.dump_timings_json.is_some(), "--dump-timings-json"),
+    ]
+    .into_iter()
+    .find_map(|(given, flag)| given.then_some(flag));
+    if let (Some(_), Some(flag)) = (&frame_cache, render_report) {
+        println!("{} needs the frame to be rendered, not copying it from the frame cache", flag);
+    }
+    if let (Some((cache, hash)), None) = (&frame_cache, render_report) {
+        let restored = cache.restore(*hash, image_path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        if restored {
+            println!("Done. Copied the frame rendered before with the same parameters to {:?}", dunce::canonicalize(image_path).unwrap());
+            return;
+        }
+    }
+
+    let other_mode = [
+        (args.buddhabrot, "--buddhabrot"),
+        (args.progressive, "--progressive"),
+        (args.mandel_julia.is_some(), "--mandel-julia"),
+        (args.time_budget.is_some(), "--time-budget"),
+        (args.reuse_previous.is_some(), "--reuse-previous"),
+    ]
+    .into_iter()
+    .find(|(given, _)| *given);
+    if let (true, Some((_, mode))) = (oversize > 1, other_mode) {
+        println!("{} doesn't support oversized renders, ignoring --oversize-then-crop", mode);
+    }
+    let output_alpha = match (args.output_alpha, other_mode) {
+        (Some(_), _) if !is_png => {
+            println!("Transparency is only supported in PNG files, ignoring --output-alpha");
+            None
+        }
+        (Some(_), Some((_, mode @ ("--buddhabrot" | "--mandel-julia")))) => {
+            println!("{} doesn't support transparency, ignoring --output-alpha", mode);
+            None
+        }
+        (region, _) => region,
+    };
+    let budget_ring = match other_mode {
+        Some((_, mode @ ("--buddhabrot" | "--mandel-julia"))) if args.max_escape_iterations_ring => {
+            println!("{} doesn't support the iteration ring, ignoring --max-escape-iterations-ring", mode);
+            false
+        }
+        _ => args.max_escape_iterations_ring,
+    };
+
+    timings.finish("setup");
+
+    let mut complete = true;
+    // Whether the image is only a coarser pass of the render, which isn't cached
+    let mut out_of_time = false;
+    let mut final_image = if args.progressive {
+        build_mandelbrot_progressive(&options, render, |image, iterations| {
+            if iterations < options.iterations {
+                let written = RenderOutput::new(image.to_vec(), options.color_type()).and_then(|preview| {
+                    write_output(image_path, &preview, &options, params.as_deref(), &encoders).map_err(|error| error.to_string())
+                });
+                match written {
+                    Ok(_) => println!("\nPreview with {} iterations outputted", iterations),
+                    Err(error) => println!("\n{}, continuing without a preview", error),
                 }
             }
+        })
+    } else if args.buddhabrot {
+        // The rest is done on an image that isn't an escape time render
+        complete = false;
+        buddhabrot::build_buddhabrot(&options, args.min_iterations)
+    } else if let Some(point) = &args.mandel_julia {
+        let image = mandel_julia::build_mandel_julia(&options, render, point[0], point[1], args.overlay_color);
+        // The rest is done on the whole image, which isn't a render of the options anymore
+        complete = false;
+        options.image_width = 2 * options.image_width + mandel_julia::DIVIDER_WIDTH;
+        image
+    } else if let Some(seconds) = args.time_budget {
+        let budget = std::time::Duration::from_secs_f64(seconds.max(0.0));
+        let (image, (scale, iterations)) = build_mandelbrot_time_budget(&options, render, budget, |_, _, _| {});
+        complete = scale == 1 && iterations == options.iterations;
+        out_of_time = !complete;
+        if !complete {
+            println!(
+                "\nThe time budget ran out, outputting the pass at 1/{} resolution with {} iterations",
+                scale, iterations
+            );
         }
-        // Receive messages for the progress counter
-        match prx.try_recv() {
-            Ok(inc) => {
-                // Update the progress counter and report
-                progress += inc;
-                print!("Progress: {}%  \r", (progress / total * 100.0).round());
-            }
-            // Check for any disconnect errors
+        image
+    } else if let (Some(path), Some(center)) = (&args.reuse_previous, &args.previous_center) {
+        let previous_image = reuse::load_previous(Path::new(path), &options).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        let previous = MandelbrotCpu {
+            real_start: options.real_start + (center[0].value - args.center[0].value).hi,
+            i_start: options.i_start + (center[1].value - args.center[1].value).hi,
+            ..options.clone()
+        };
+        if reuse::pan_offset(&previous, &options).is_none() {
+            println!("The view didn't move by a whole number of pixels, rendering the full image");
+        }
+        reuse::build_mandelbrot_reusing(&previous, &previous_image, &options, render)
+    } else {
+        oversize::build_mandelbrot_oversized(&options, render, oversize)
+    };
+
+    timings.finish("compute");
+
+    if complete {
+        if let Some(hint) = detail::degenerate_view_hint(&options) {
+            println!("\n{}", hint);
+        }
+    }
+
+    if args.validate_output && !complete {
+        println!("\nSkipping validation, the outputted image isn't a full render");
+    } else if args.validate_output && oversize > 1 && other_mode.is_none() {
+        println!("\nSkipping validation, the pixels of an oversized render are averaged");
+    } else if args.validate_output {
+        match validate::validate_image(&final_image, &options, validate::SPOT_CHECKS) {
+            Ok(0) => println!("\nValidated the rendered image"),
+            Ok(mismatches) => println!(
+                "\nWarning: {} of {} recomputed pixels don't match the rendered image",
+                mismatches,
+                validate::SPOT_CHECKS
+            ),
             Err(error) => {
-                if error == mpsc::TryRecvError::Disconnected {
-                    println!("Progress Counter Disconnected!");
-                }
+                eprintln!("\nThe rendered image is invalid: {}", error);
+                std::process::exit(1);
             }
         }
     }
 
-    // Sort the image slices by thread number
-    image_slices.sort_by_key(|k| k.0);
+    let (width, height, bytes_per_pixel) = (options.image_width, options.image_height, options.bytes_per_pixel());
+    if args.overlay_thirds {
+        overlay::draw_thirds(&mut final_image, width, height, bytes_per_pixel, args.overlay_color);
+    }
+    if args.overlay_crosshair {
+        overlay::draw_crosshair(&mut final_image, width, height, bytes_per_pixel, args.overlay_color);
+    }
+    if budget_ring {
+        let ring = detail::budget_ring(&options);
+        let pixels = ring.iter().filter(|&&escaped| escaped).count();
+        println!(
+            "\n{} of {} pixels escaped in the last 1% of the iterations{}",
+            pixels,
+            ring.len(),
+            if pixels > 0 { ", a higher --iterations would show more detail there" } else { "" }
+        );
+        overlay::draw_mask(&mut final_image, width, bytes_per_pixel, &ring, args.overlay_color);
+    }
 
-    // Join all of the image slices together
-    let mut final_image = vec![];
-    for mut slice in image_slices {
-        final_image.append(&mut slice.1);
+    let (mut pixels, color_type) = match output_alpha {
+        Some(region) => {
+            let inside = minibrots::membership(&options);
+            (alpha::with_alpha(&final_image, bytes_per_pixel, &inside, region).concat(), ColorType::Rgba8)
+        }
+        None => (final_image, options.color_type()),
+    };
+    if let Some(mode) = args.seamless {
+        seamless::make_seamless(&mut pixels, width, height, color_type.bytes_per_pixel() as usize, mode);
+    }
+    let output = RenderOutput::new(pixels, color_type).unwrap_or_else(|error| {
+        eprintln!("\nThe rendered image is invalid: {}", error);
+        std::process::exit(1);
+    });
+
+    timings.finish("postprocess");
+
+    let written = write_output(image_path, &output, &options, params.as_deref(), &encoders).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    if let (Some((cache, hash)), false) = (&frame_cache, out_of_time) {
+        if let Err(error) = cache.store(*hash, &written) {
+            println!("\n{}, the frame isn't cached", error);
+        }
+    }
+    timings.finish("write");
+    if let Some(path) = &args.dump_timings_json {
+        std::fs::write(path, timings.to_json(engine)).unwrap_or_else(|error| {
+            eprintln!("Couldn't write the timings to {:?}: {}", path, error);
+            std::process::exit(1);
+        });
+        println!("\nTimings outputted to {:?}", dunce::canonicalize(Path::new(path)).unwrap());
     }
 
-    final_image
+    // Done! (image files close automatically when dropped)
+    println!("\nDone. File outputted to {:?}", written);
 }
 
-/// A simple version of the mandelbrot generator that does not use threads.
-pub fn build_mandelbrot_cpu_simple(options: &MandelbrotCpu) -> Vec<u8> {
-    let mut final_image = vec![u8::MAX; options.image_width * options.image_height];
+// Returns the number of logical cores of the machine, 1 if that can't be found out
+fn logical_cores() -> usize {
+    thread::available_parallelism().map_or(1, |cores| cores.get())
+}
 
-    let mut x = options.real_start;
-    let mut y = options.i_start;
-    for i in 0..options.image_height {
-        for j in 0..options.image_width {
-            //let x = options.real_start + (j as f64 * options.real_step);
-            //let y = options.i_start - (i as f64 * options.i_step);
-            
-            let point = Complex::new(x, y);
-            if point.is_stable(options.iterations) {
-                final_image[j + (i * options.image_width)] = 0;
-            }
-            x += options.real_step;
+// Returns a warning if rendering on {threads} threads oversubscribes the {cores} logical cores.
+// The render is CPU bound, so extra threads only take turns on the cores.
+fn oversubscription_warning(threads: usize, cores: usize) -> Option<String> {
+    (threads > cores).then(|| format!("--threads {} exceeds {} logical cores; this may reduce performance", threads, cores))
+}
+
+// Returns the options to render with for the command line arguments {args}, before the
+// adjustments for unsupported combinations
+fn options_from_args(args: &Args) -> MandelbrotCpu {
+    let image_width: usize = args.image_size[0];
+    let image_height: usize = args.image_size[1];
+
+    let real_step: f64 = args.size[0] / (image_width as f64);
+    let i_step: f64 = args.size[1] / (image_height as f64);
+
+    // The start is calculated in double-double precision, so deep zooms keep every digit of
+    // the center. The f64 calculations only use the high part.
+    let real_start = args.center[0].value - DoubleDouble::from(args.size[0] / 2.0);
+    let i_start = args.center[1].value + DoubleDouble::from(args.size[1] / 2.0);
+
+    let threads = args.threads.unwrap_or_else(logical_cores);
+
+    MandelbrotCpu {
+        threads,
+        image_width: image_width,
+        image_height: image_height,
+        real_step,
+        i_step,
+        real_start: real_start.hi,
+        i_start: i_start.hi,
+        real_start_lo: real_start.lo,
+        i_start_lo: i_start.lo,
+        iterations: args.iterations,
+        samples: args.samples,
+        supersample_threshold: args.supersample_adaptive_threshold,
+        sample_budget: args.sample_budget,
+        downsample_filter: args.downsample_filter,
+        sample_center: false,
+        row_order: args.row_order,
+        shading: if args.hsv_cycle {
+            Shading::HsvCycle
+        } else if args.binary_decomp {
+            Shading::BinaryDecomp
+        } else if args.palette.is_some() && args.shading == Shading::Flat {
+            Shading::Palette
+        } else {
+            args.shading
+        },
+        repeat: args.repeat,
+        hsv_saturation: args.hsv_saturation,
+        hsv_value: args.hsv_value,
+        palette: args.palette.unwrap_or(BuiltinPalette::Grayscale),
+        palette_gamma: args.palette_gamma,
+        color_space: args.color_space,
+        smooth_iter_clamp: args.smooth_iter_clamp,
+        atom_domains: args.atom_domains,
+        interior_modulus: args.color_interior_by_iteration_fraction,
+        gamma: args.gamma,
+        invert_plane: args.invert_plane,
+        transform: args.transform.as_ref().map(|matrix| transform::Affine {
+            matrix: [matrix[0], matrix[1], matrix[2], matrix[3], matrix[4], matrix[5]],
+            center: Complex::new(args.center[0].value.hi, args.center[1].value.hi),
+        }),
+        start_z: args.start_z.as_ref().map(|start| Complex::new(start[0], start[1])),
+        formula: args.fractal_formula.as_deref().and_then(|text| formula::Formula::parse(text).ok()).map(Arc::new),
+        precision: args.precision,
+        gpu_retries: args.gpu_retries,
+        gpu_cpu_fallback: args.gpu_cpu_fallback,
+        gpu_fraction: args.gpu_fraction,
+        verbose: args.verbose,
+        progress: progress::enabled(args.no_progress),
+        ..MandelbrotCpu::default()
+    }
+}
+
+// Returns the arguments of {command_line}, with the parameters embedded in the image at {path}
+// (see --from-image) as the base. Everything given on {command_line} overrides those.
+fn args_from_image(path: &Path, command_line: &[std::ffi::OsString]) -> Result<Args, String> {
+    let params = params::read_params(path)?;
+    let defaults = serde_json::to_string(&Args::parse_from(["gendelbrot"])).unwrap();
+    let matches = Args::command().try_get_matches_from(command_line).map_err(|error| error.to_string())?;
+    let given: Vec<String> = matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(clap::parser::ValueSource::CommandLine))
+        .map(|id| id.to_string())
+        .collect();
+    let base = params::params_to_args(&params, &defaults, &given)?;
+    let arguments = command_line.iter().take(1).cloned().chain(base.into_iter().map(Into::into)).chain(command_line.iter().skip(1).cloned());
+    Args::try_parse_from(arguments).map_err(|error| format!("Couldn't use the parameters of {:?}: {}", path, error))
+}
+
+// Decides what to render on for {engine}, with {gpu_available} telling if there is a usable
+// GPU. Never returns Engine::Auto.
+pub fn resolve_engine(engine: Engine, gpu_available: impl FnOnce() -> bool) -> Engine {
+    match engine {
+        Engine::Auto if gpu_available() => Engine::Gpu,
+        Engine::Auto => Engine::Cpu,
+        engine => engine,
+    }
+}
+
+// Whether there is a CUDA device to render on, never in a build without the gpu feature
+#[cfg(not(feature = "gpu"))]
+fn gpu_available() -> bool {
+    false
+}
+
+// Returns why {engine} can't be rendered on if this build was compiled without it
+pub fn engine_unsupported(engine: Engine) -> Option<&'static str> {
+    match engine {
+        #[cfg(not(feature = "gpu"))]
+        Engine::Gpu => Some("This build doesn't support CUDA, build it with --features gpu to use --engine gpu"),
+        #[cfg(not(feature = "gpu"))]
+        Engine::Hybrid => Some("This build doesn't support CUDA, build it with --features gpu to use --engine hybrid"),
+        #[cfg(not(feature = "wgpu"))]
+        Engine::Wgpu => Some("This build doesn't support wgpu, build it with --features wgpu to use --engine wgpu"),
+        _ => None,
+    }
+}
+
+// Writes the coordinates in mandelbrot space of every pixel to {out} as CSV, one pixel per line
+fn write_coordinates(options: &MandelbrotCpu, out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out, "column,row,re,im")?;
+    for row in 0..options.image_height {
+        for column in 0..options.image_width {
+            let point = options.pixel_point(row, column);
+            writeln!(out, "{},{},{},{}", column, row, point.real, point.imaginary)?;
         }
-        y -= options.i_step;
-        x = options.real_start;
+    }
+    Ok(())
+}
 
+// Returns how many of the pixels escaped at every iteration count in {counts}, the escape counts
+// from render_iterations
+fn iteration_histogram(counts: &[u32]) -> BTreeMap<u32, usize> {
+    let mut histogram = BTreeMap::new();
+    for &count in counts {
+        *histogram.entry(count).or_insert(0) += 1;
     }
+    histogram
+}
 
-    final_image
+// Writes the {histogram} from iteration_histogram to {out} as CSV, one iteration count per line
+fn write_iteration_histogram(histogram: &BTreeMap<u32, usize>, out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out, "count,num_pixels")?;
+    for (count, pixels) in histogram {
+        writeln!(out, "{},{}", count, pixels)?;
+    }
+    Ok(())
 }
 
+// Writes the completion script for {shell} to {out}
+fn write_completions(shell: Shell, out: &mut impl Write) {
+    let mut command = Args::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, out);
+}
 
+// Whether the file at {path} has the extension {extension}, in any case
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|found| found.eq_ignore_ascii_case(extension))
+}
 
-// ==========================================================================
-//  GPU Mandelbrot Functions
-// ==========================================================================
+// Returns the parameters to embed in the outputted file for {args}, if any, see params.rs.
+// {is_png} says whether the file is a PNG, parameters can only be embedded in those.
+fn embedded_params(args: &Args, is_png: bool) -> Option<String> {
+    if args.save_params_with_image && args.strip_metadata {
+        println!("--strip-metadata leaves out all metadata, ignoring --save-params-with-image");
+        return None;
+    }
+    if args.save_params_with_image && !is_png {
+        println!("Parameters can only be embedded in PNG files, ignoring --save-params-with-image");
+    }
+    (args.save_params_with_image && is_png).then(|| serde_json::to_string(args).unwrap())
+}
 
+// Writes the rendered {image} to {path} with the encoder from {encoders} for its extension (see
+// encoder.rs), or as a PNG with {params} embedded if given (see params.rs). Returns the full path
+// of the written file.
+fn write_output(
+    path: &Path,
+    image: &RenderOutput,
+    options: &MandelbrotCpu,
+    params: Option<&str>,
+    encoders: &EncoderRegistry,
+) -> Result<PathBuf, RenderError> {
+    let (width, height, color_type) = (options.image_width, options.image_height, image.color_type());
+    let pixels = image.as_bytes();
+    match params {
+        Some(params) => params::save_png_with_params(path, pixels, width, height, color_type, params)?,
+        None => encoders.encode(path, pixels, width, height, color_type)?,
+    }
+    dunce::canonicalize(path).map_err(|cause| RenderError::Io { path: path.to_path_buf(), cause: cause.to_string() })
+}
 
-#[kernel]
-fn mandelbrot_kernel(
-    mut image: Buffer<u8>,
-    offset: usize,
-    image_width: usize,
-    image_height: usize,
-    real_start: f64,
-    i_start: f64,
-    real_step: f64,
-    i_step: f64,
-    iterations: i32,
-) {
-    let pos = offset + gpu::global_tid_x() as usize;
-    let i = pos / image_width;
-    let j = pos % image_width;
-
-    if i >= image_height {
-        return; // Out of bounds
-    }
-    
-    // compute x and y coordinates in mandelbrot space
-    let x = real_start + (j as f64 * real_step);
-    let y = i_start - (i as f64 * i_step);
-
-    // Create a complex number from the x and y coordinates
-    let point = Complex::new(x, y);
-    //If the point is stable, set the pixel to black (1), otherwise leave it white (0)
-    if point.is_stable(iterations) {
-        image.set(i * image_width + j, 0); // Set pixel to black
-    } else {
-        image.set(i * image_width + j, u8::MAX); // Leave pixel white
+// How long a render of --compute-only took
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputeReport {
+    pub pixels: usize,
+    pub elapsed: std::time::Duration,
+}
+
+impl ComputeReport {
+    pub fn pixels_per_second(&self) -> f64 {
+        self.pixels as f64 / self.elapsed.as_secs_f64()
     }
-   
+}
 
-    // report progress TODO
+impl std::fmt::Display for ComputeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Rendered {} pixels in {:.3} s, {:.2} megapixels per second. Nothing was written",
+            self.pixels,
+            self.elapsed.as_secs_f64(),
+            self.pixels_per_second() / 1e6
+        )
+    }
 }
 
-pub fn build_mandelbrot_gpu_simple(options: &MandelbrotCpu) -> Vec<u8> {
-    let MandelbrotCpu {
-        image_width,
-        image_height,
-        real_start,
-        i_start,
-        real_step,
-        i_step,
-        iterations,
-        ..
-    } = *options;
-    // prepare arguments for the kernel
-    let image_buffer: Buffer<u8> = Buffer::alloc(image_width * image_height).unwrap();
-    let threads_per_block = 256;
-    let blocks = (image_width * image_height + threads_per_block - 1) / threads_per_block;
-    match mandelbrot_kernel.launch(
-        threads_per_block as usize,
-        blocks as usize,
-        image_buffer,
-        0,
-        image_width,
-        image_height,
-        real_start,
-        i_start,
-        real_step,
-        i_step,
-        iterations,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Error launching kernel: {:?}", e);
+// Renders {options} with {render} and throws the image away, returning how long that took
+pub fn compute_only(options: &MandelbrotCpu, render: impl Fn(&MandelbrotCpu) -> Vec<u8>) -> ComputeReport {
+    let start = std::time::Instant::now();
+    let image = render(options);
+    let elapsed = start.elapsed();
+    drop(image);
+    ComputeReport {
+        pixels: options.image_width * options.image_height,
+        elapsed,
+    }
+}
+
+// Renders the image in passes of increasing iteration counts, so a rough version of the image
+// is available almost immediately. {on_pass} is called with the image and the iteration count
+// after every pass, the last pass always uses the full iteration count of {options}.
+pub fn build_mandelbrot_progressive(
+    options: &MandelbrotCpu,
+    render: fn(&MandelbrotCpu) -> Vec<u8>,
+    mut on_pass: impl FnMut(&[u8], u32),
+) -> Vec<u8> {
+    if options.iterations > PREVIEW_ITERATIONS {
+        let preview_options = MandelbrotCpu {
+            iterations: PREVIEW_ITERATIONS,
+            ..options.clone()
+        };
+        let preview = render(&preview_options);
+        on_pass(&preview, PREVIEW_ITERATIONS);
+    }
+
+    let final_image = render(options);
+    on_pass(&final_image, options.iterations);
+    final_image
+}
+
+// Renders the image in passes of increasing resolution and iteration count until {budget} runs
+// out. The first pass renders every {2^BUDGET_LEVELS}th pixel, every next pass doubles the
+// resolution, until the last one renders the full image with the full iteration count of
+// {options}. A pass is only started if it's expected to finish within the budget, judging by
+// how long the pass before it took, but the first pass is always rendered. {on_pass} is called
+// with the image, scaled up to full size, the scale and the iteration count after every pass.
+// Returns the last image along with its scale and iteration count.
+pub fn build_mandelbrot_time_budget(
+    options: &MandelbrotCpu,
+    render: fn(&MandelbrotCpu) -> Vec<u8>,
+    budget: std::time::Duration,
+    mut on_pass: impl FnMut(&[u8], usize, u32),
+) -> (Vec<u8>, (usize, u32)) {
+    let start = std::time::Instant::now();
+    let mut best = None;
+    let mut last_cost = 0.0;
+    let mut last_elapsed = std::time::Duration::ZERO;
+    for level in (0..=BUDGET_LEVELS).rev() {
+        let scale = 1 << level;
+        let pass_options = MandelbrotCpu {
+            image_width: options.image_width.div_ceil(scale),
+            image_height: options.image_height.div_ceil(scale),
+            real_step: options.real_step * scale as f64,
+            i_step: options.i_step * scale as f64,
+            iterations: (options.iterations >> level).max(PREVIEW_ITERATIONS.min(options.iterations)),
+            // The iteration map only fits the full size image
+            iterations_map: if scale == 1 { options.iterations_map.clone() } else { None },
+            ..options.clone()
+        };
+
+        // How much work the pass is, compared to the last one
+        let cost = (pass_options.image_width * pass_options.image_height) as f64 * pass_options.iterations as f64;
+        if best.is_some() && start.elapsed() + last_elapsed.mul_f64(cost / last_cost) > budget {
+            break;
         }
+
+        let pass_start = std::time::Instant::now();
+        let image = render(&pass_options);
+        last_elapsed = pass_start.elapsed();
+        last_cost = cost;
+
+        let image = upscale(&image, &pass_options, scale, options.image_width, options.image_height);
+        on_pass(&image, scale, pass_options.iterations);
+        best = Some((image, (scale, pass_options.iterations)));
     }
+    best.unwrap()
+}
 
-    println!("Waiting for GPU to finish...");
-    let result: Vec<u8> = image_buffer.retrieve().unwrap();
-    result
+// Scales up an image rendered with {options} {scale} times, and crops it to {width} x {height}
+fn upscale(image: &[u8], options: &MandelbrotCpu, scale: usize, width: usize, height: usize) -> Vec<u8> {
+    let bytes_per_pixel = options.bytes_per_pixel();
+    let mut scaled = Vec::with_capacity(width * height * bytes_per_pixel);
+    for i in 0..height {
+        let row = (i / scale) * options.image_width;
+        for j in 0..width {
+            let index = (row + j / scale) * bytes_per_pixel;
+            scaled.extend_from_slice(&image[index..index + bytes_per_pixel]);
+        }
+    }
+    scaled
+}
+
+// Returns whether the pixel step of {options} is within PRECISION_MARGIN times {epsilon}, the
+// machine epsilon of the precision the image is calculated at, relative to the largest
+// coordinate in the view. Neighboring pixels then round to the same or nearly the same number.
+pub fn precision_exhausted(options: &MandelbrotCpu, epsilon: f64) -> bool {
+    options.real_step.min(options.i_step) < PRECISION_MARGIN * epsilon * coordinate_magnitude(options)
 }
 
+// Returns the largest absolute value of the coordinates in the view of {options}
+fn coordinate_magnitude(options: &MandelbrotCpu) -> f64 {
+    let real_end = options.real_start + options.image_width as f64 * options.real_step;
+    let i_end = options.i_start - options.image_height as f64 * options.i_step;
+    [options.real_start, real_end, options.i_start, i_end]
+        .iter()
+        .fold(0.0_f64, |magnitude, coordinate| magnitude.max(coordinate.abs()))
+}
+
+// Returns the number of significant decimal digits the coordinates of the view of {options} need
+// for neighboring pixels to be distinct, with the same margin as precision_exhausted. A precision
+// with a machine epsilon of 10^-digits has enough digits for the view if this is at most digits.
+pub fn digits_needed(options: &MandelbrotCpu) -> f64 {
+    (PRECISION_MARGIN * coordinate_magnitude(options) / options.real_step.min(options.i_step)).log10()
+}
+
+// Returns a readout of how many digits the view of {options} needs and which of the precisions
+// Gendel calculates in have enough of them (see --probe-precision)
+pub fn precision_report(options: &MandelbrotCpu) -> String {
+    let precisions = [("f32", f32::EPSILON as f64), ("f64", f64::EPSILON), ("double-double", f64::EPSILON * f64::EPSILON)];
+    let mut report = format!(
+        "Telling neighboring pixels apart takes {} significant digits",
+        digits_needed(options).ceil().max(1.0)
+    );
+    for (name, epsilon) in precisions {
+        let verdict = if precision_exhausted(options, epsilon) { "not enough" } else { "enough" };
+        report.push_str(&format!("\n  {:<14} ~{:.0} digits, {}", name, -epsilon.log10(), verdict));
+    }
+    report
+}
+
+// Returns the options for a log-polar zoom strip (see --log-polar) of the same size as {options},
+// around {center} out to {radius}. The rows go from an angle of pi down to -pi, and the columns
+// take the same step in the log of the radius, so the pixels stay square in the plane.
+pub fn log_polar_view(options: &MandelbrotCpu, center: Complex, radius: f64) -> MandelbrotCpu {
+    let step = 2.0 * std::f64::consts::PI / options.image_height as f64;
+    MandelbrotCpu {
+        real_start: radius.ln() - options.image_width as f64 * step,
+        real_step: step,
+        i_start: std::f64::consts::PI,
+        i_step: step,
+        real_start_lo: 0.0,
+        i_start_lo: 0.0,
+        log_polar: Some(center),
+        ..options.clone()
+    }
+}
+
+// Returns the options for the same view with every pixel sampled at its center instead of its
+// top left corner, which keeps the image symmetric around the center of the view. Supersampled
+// pixels are already sampled all over the pixel, so they stay where they are.
+pub fn sample_centers(options: &MandelbrotCpu) -> MandelbrotCpu {
+    if options.sample_center {
+        return options.clone();
+    }
+    MandelbrotCpu {
+        sample_center: true,
+        ..pixel_offset(options, 0.5, 0.5)
+    }
+}
+
+// Returns the options for the same image, with every pixel shifted {dx} pixels to the right and
+// {dy} pixels down in mandelbrot space
+pub fn pixel_offset(options: &MandelbrotCpu, dx: f64, dy: f64) -> MandelbrotCpu {
+    let real_start = DoubleDouble::new(options.real_start, options.real_start_lo)
+        + DoubleDouble::from(options.real_step).mul_f64(dx);
+    let i_start = DoubleDouble::new(options.i_start, options.i_start_lo)
+        - DoubleDouble::from(options.i_step).mul_f64(dy);
+    MandelbrotCpu {
+        real_start: real_start.hi,
+        real_start_lo: real_start.lo,
+        i_start: i_start.hi,
+        i_start_lo: i_start.lo,
+        ..options.clone()
+    }
+}
 
-pub fn build_mandelbrot_gpu(options: &MandelbrotCpu) -> Vec<u8> {
+// Returns an error if rendering {options} at {oversize} times the resolution takes more than
+// {max_pixels} pixels, before the image is allocated
+fn check_pixel_limit(options: &MandelbrotCpu, oversize: usize, max_pixels: usize) -> Result<(), String> {
+    let pixels = options.image_width as u128 * options.image_height as u128 * (oversize * oversize) as u128;
+    if pixels > max_pixels as u128 {
+        return Err(format!(
+            "Rendering this image takes {} pixels, more than the limit of {}. Pass a larger --max-pixels if the image is meant to be this large, or render it in bands with --row-range and combine them with --stitch",
+            pixels, max_pixels
+        ));
+    }
+    Ok(())
+}
+
+// Returns the options for rendering only the rows from {start} up to (but not including) {end}
+// of the image described by {options}. The rows keep the same position in mandelbrot space.
+pub fn row_range(options: &MandelbrotCpu, start: usize, end: usize) -> MandelbrotCpu {
+    let i_start = DoubleDouble::new(options.i_start, options.i_start_lo)
+        - DoubleDouble::from(options.i_step).mul_f64(start as f64);
+    MandelbrotCpu {
+        image_height: end - start,
+        i_start: i_start.hi,
+        i_start_lo: i_start.lo,
+        iterations_map: options.iterations_map.as_ref().map(|map| Arc::new(map.rows(start, end))),
+        ..options.clone()
+    }
+}
+
+// Loads the partial images rendered with --row-range and joins them together from top to
+// bottom. Returns the joined image along with its width, height and color type.
+pub fn stitch_images(paths: &[String]) -> Result<(Vec<u8>, usize, usize, ColorType), String> {
+    let mut final_image = vec![];
+    let mut format = None;
+    let mut height = 0;
+    for path in paths {
+        let part = image::open(path)
+            .map_err(|error| format!("Couldn't open partial image {}: {}", path, error))?;
+        let color_type = if part.color().has_color() {
+            ColorType::Rgb8
+        } else {
+            ColorType::L8
+        };
+        let width = part.width() as usize;
+        match format {
+            None => format = Some((width, color_type)),
+            Some(expected) if expected != (width, color_type) => {
+                return Err(format!(
+                    "Partial image {} is a {} pixels wide {:?} image, expected a {} pixels wide {:?} image",
+                    path, width, color_type, expected.0, expected.1
+                ));
+            }
+            _ => {}
+        }
+        height += part.height() as usize;
+        if color_type == ColorType::Rgb8 {
+            final_image.extend_from_slice(part.into_rgb8().as_raw());
+        } else {
+            final_image.extend_from_slice(part.into_luma8().as_raw());
+        }
+    }
+    let (width, color_type) = format.unwrap_or((0, ColorType::L8));
+    Ok((final_image, width, height, color_type))
+}
+
+
+// ==========================================================================
+//  CPU Mandelbrot Functions
+// ==========================================================================
+
+pub fn build_mandelbrot_cpu(options: &MandelbrotCpu) -> Vec<u8> {
     let MandelbrotCpu {
+        threads,
         image_width,
         image_height,
         real_step,
         i_step,
         real_start,
         i_start,
-        iterations,
         ..
     } = *options;
+    let bytes_per_pixel = options.bytes_per_pixel();
+
+    // Initialize the image with all pixels white
+    let mut final_image = vec![u8::MAX; image_width * image_height * bytes_per_pixel];
+
+    progress::status(options.progress, "Generating Image...");
+    let render_row = |row: usize, pixels: &mut [u8]| {
+        // Calculate every coordinate from the start like the GPU does, adding up the steps
+        // drifts further off with every pixel of the row
+        let y = i_start - (row as f64) * i_step;
+        let mut pixel_options = options.clone();
+        for (j, pixel) in pixels.chunks_mut(bytes_per_pixel).enumerate() {
+            let x = real_start + (j as f64) * real_step;
+            pixel_options.iterations = options.iterations_at(row, j);
+            pixel_options.render_pixel(x, y, pixel);
+        }
+    };
+    render_rows_ordered(
+        &mut final_image,
+        image_width * bytes_per_pixel,
+        threads,
+        options.progress,
+        options.row_order,
+        render_row,
+    );
+
+    final_image
+}
 
-    let total = image_width * image_height;
-
-    let image_buffer: Buffer<u8> = Buffer::alloc(image_width * image_height).unwrap();
-    let threads_per_block = 256;
-
-    // convert arguments to dptr
-    let mut image_buffer_d = image_buffer.to_device().unwrap();
-    let mut image_width_d = image_width.to_device().unwrap();
-    let mut image_height_d = image_height.to_device().unwrap();
-    let mut real_start_d = real_start.to_device().unwrap();
-    let mut i_start_d = i_start.to_device().unwrap();
-    let mut real_step_d = real_step.to_device().unwrap();
-    let mut i_step_d = i_step.to_device().unwrap();
-    let mut iterations = iterations.to_device().unwrap();
-
-    // step is calculated based on how many pixels we want to generate at a time
-    let blocks_per_step = (total as f64 / threads_per_block as f64 / 100.0).ceil() as usize;
-    let offset_step = threads_per_block * blocks_per_step;
-    
-    let mut offset = 0;
-    while offset < total as usize {
-        // generate chucks of the mandelbrot set
-        let mut offset_d = offset.to_device().unwrap();
-        
-        match mandelbrot_kernel.launch_with_dptr(
-            threads_per_block as usize,
-            blocks_per_step as usize,
-            &mut image_buffer_d,
-            &mut offset_d,
-            &mut image_width_d,
-            &mut image_height_d,
-            &mut real_start_d,
-            &mut i_start_d,
-            &mut real_step_d,
-            &mut i_step_d,
-            &mut iterations,
-        ) {
-            Ok(_) => {}
-            Err(e) => {
-                panic!("Error launching kernel: {:?}", e);
+// Renders {image} row by row on {threads} threads, calling render_row with the index of the row
+// and a buffer of {row_len} values to render it into, and shows the progress if {progress}.
+// Instead of giving every thread a fixed slice of the image, the threads keep claiming the next
+// row that hasn't been claimed yet from a shared counter, so threads that got a fast part of the
+// image (far outside the set) don't sit idle while the others are still working.
+pub fn render_rows<T: Copy + Default + Send>(
+    image: &mut [T],
+    row_len: usize,
+    threads: usize,
+    progress: bool,
+    render_row: impl Fn(usize, &mut [T]) + Sync,
+) {
+    render_rows_ordered(image, row_len, threads, progress, RowOrder::TopDown, render_row);
+}
+
+// Same as render_rows, but hands out the rows in {order}
+pub fn render_rows_ordered<T: Copy + Default + Send>(
+    image: &mut [T],
+    row_len: usize,
+    threads: usize,
+    progress: bool,
+    order: RowOrder,
+    render_row: impl Fn(usize, &mut [T]) + Sync,
+) {
+    if row_len == 0 {
+        return;
+    }
+    // The rows are handed out as whole chunks of the image, which is what keeps the pixel writes
+    // of render_row in bounds without checking every index. A partial row at the end would be
+    // silently left unrendered.
+    debug_assert_eq!(image.len() % row_len, 0, "the image isn't made of whole rows of {} values", row_len);
+    let rows = image.len() / row_len;
+    let order = order.rows(rows);
+    let next_row = AtomicUsize::new(0);
+    let done_rows = AtomicUsize::new(0);
+    let image = Mutex::new(image);
+
+    thread::scope(|scope| {
+        for _i in 0..threads.clamp(1, rows.max(1)) {
+            scope.spawn(|| {
+                let mut buffer = vec![T::default(); row_len];
+                loop {
+                    let position = next_row.fetch_add(1, Ordering::Relaxed);
+                    if position >= rows {
+                        break;
+                    }
+                    let row = order[position];
+
+                    // Render the row into this thread's own buffer, so the image is only locked
+                    // to copy the finished row over
+                    let offset = row * row_len;
+                    buffer.copy_from_slice(&image.lock().unwrap()[offset..offset + row_len]);
+                    render_row(row, &mut buffer);
+                    image.lock().unwrap()[offset..offset + row_len].copy_from_slice(&buffer);
+
+                    // Report the progress for every row
+                    let done = done_rows.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress::report(progress, done, rows);
+                }
+            });
+        }
+    });
+}
+
+// Renders a single pixel from a {samples} x {samples} grid of sub-samples spread evenly over
+// the downsample filter (see filter.rs), where (x, y) is the top left corner of the pixel in
+// mandelbrot space. The sub-samples are combined in linear light (see color.rs) and written
+// to {pixel}.
+fn supersample_pixel(options: &MandelbrotCpu, x: f64, y: f64, pixel: &mut [u8]) {
+    let samples = options.downsample_filter.samples(options.samples);
+    let mut total = [0.0; 3];
+    for &(offset_y, weight_y) in &samples {
+        for &(offset_x, weight_x) in &samples {
+            // The sub-sample offsets are from the top left corner of the pixel
+            let (offset_x, offset_y) = (offset_x - options.sample_offset(), offset_y - options.sample_offset());
+            let point = Complex::new(x + offset_x * options.real_step, y - offset_y * options.i_step);
+            let color = options.pixel_color(&point);
+            for channel in 0..3 {
+                total[channel] += color::srgb_to_linear(color[channel]) * weight_x * weight_y;
             }
         }
-        offset += offset_step;
-        
-        print!("Progress: {}%  \r", (offset as f64 / total as f64 * 100.0).round());
-        // Flush the output to ensure the progress is displayed
-        std::io::stdout().flush().unwrap();
+    }
+    for (channel, value) in pixel.iter_mut().enumerate() {
+        *value = color::linear_to_srgb(total[channel]);
+    }
+}
+
+// Returns the iteration at which every pixel escaped, row by row, or the iteration count for
+// pixels in the set
+pub fn render_iterations(options: &MandelbrotCpu) -> Vec<u32> {
+    let mut counts = vec![0; options.image_width * options.image_height];
 
-        if offset == offset_step {
-            // Sleep for a short time to allow the GPU to catch up
-            thread::sleep(std::time::Duration::from_millis(50));
+    progress::status(options.progress, "Generating Image...");
+    render_rows(&mut counts, options.image_width, options.threads, options.progress, |row, pixels| {
+        for (j, count) in pixels.iter_mut().enumerate() {
+            *count = options.escape_count(row, j);
         }
+    });
 
-        // sync the device to ensure the kernel has finished executing
-        cuda::device_sync().unwrap();
-    }
+    counts
+}
+
+/// A simple version of the mandelbrot generator that does not use threads.
+pub fn build_mandelbrot_cpu_simple(options: &MandelbrotCpu) -> Vec<u8> {
+    let bytes_per_pixel = options.bytes_per_pixel();
+    let mut final_image = vec![u8::MAX; options.image_width * options.image_height * bytes_per_pixel];
 
-    // calculate how much data needs to be retrieved in mb
-    let data_size = image_width * image_height;
-    println!("\nRetrieving image of {:.2} MB", data_size as f64 / (1024.0 * 1024.0));
+    for i in 0..options.image_height {
+        // Rows are rendered independently of each other, so y is calculated for every row
+        let y = options.i_start - (i as f64 * options.i_step);
+        for j in 0..options.image_width {
+            let x = options.real_start + (j as f64 * options.real_step);
 
+            let point = Complex::new(x, y);
+            let index = (j + (i * options.image_width)) * bytes_per_pixel;
+            options.write_pixel(&point, &mut final_image[index..index + bytes_per_pixel]);
+        }
+    }
 
-    let result: Vec<u8> = image_buffer.retrieve().unwrap();
-    result
-}
\ No newline at end of file
+    final_image
+}
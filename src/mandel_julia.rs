@@ -0,0 +1,82 @@
+// The mandelbrot set next to the julia set of one of its points. The left panel shows the
+// mandelbrot set with a dot at the point c, the right panel the julia set for that c, with a
+// divider in between. Points inside the mandelbrot set have connected julia sets, points
+// outside of it have julia sets that fall apart into dust, which this view makes easy to see.
+
+use crate::color::Rgb;
+use crate::{build_mandelbrot_cpu, overlay, sample_centers, FractalKind, MandelbrotCpu};
+
+// Width in pixels of the divider between the panels
+pub const DIVIDER_WIDTH: usize = 4;
+
+// Color of the divider between the panels
+const DIVIDER_COLOR: Rgb = Rgb([0x80, 0x80, 0x80]);
+
+// Radius in pixels of the dot marking c in the mandelbrot panel
+pub const MARKER_RADIUS: i64 = 3;
+
+// Width of the julia panel in mandelbrot space, which fits all julia sets
+const JULIA_RADIUS: f64 = 4.0;
+
+// Returns the pixel of the image described by {options} that (real, imaginary) falls in. The
+// pixel can be outside of the image.
+pub fn point_pixel(options: &MandelbrotCpu, real: f64, imaginary: f64) -> (i64, i64) {
+    (
+        ((real - options.real_start) / options.real_step + options.sample_offset()).floor() as i64,
+        ((options.i_start - imaginary) / options.i_step + options.sample_offset()).floor() as i64,
+    )
+}
+
+// Returns the options for the julia set of (real, imaginary), centered on 0 in a panel of the
+// same size as {options}
+pub fn julia_options(options: &MandelbrotCpu, real: f64, imaginary: f64) -> MandelbrotCpu {
+    let scale = JULIA_RADIUS / (options.real_step * options.image_width as f64);
+    let real_step = options.real_step * scale;
+    let i_step = options.i_step * scale;
+    let julia = MandelbrotCpu {
+        real_step,
+        i_step,
+        real_start: -real_step * options.image_width as f64 / 2.0,
+        i_start: i_step * options.image_height as f64 / 2.0,
+        real_start_lo: 0.0,
+        i_start_lo: 0.0,
+        sample_center: false,
+        fractal: FractalKind::Julia { real, imaginary },
+        iterations_map: None,
+        ..options.clone()
+    };
+    if options.sample_center {
+        sample_centers(&julia)
+    } else {
+        julia
+    }
+}
+
+// Renders the two panels for the point (real, imaginary), the mandelbrot one with {render} and
+// the julia one on the CPU, which is the only renderer that knows julia sets. Returns the
+// combined image, which is 2 * image_width + DIVIDER_WIDTH pixels wide.
+pub fn build_mandel_julia(
+    options: &MandelbrotCpu,
+    render: fn(&MandelbrotCpu) -> Vec<u8>,
+    real: f64,
+    imaginary: f64,
+    marker_color: Rgb,
+) -> Vec<u8> {
+    let (width, height, bytes_per_pixel) = (options.image_width, options.image_height, options.bytes_per_pixel());
+    let mut mandelbrot = render(options);
+    let marker = point_pixel(options, real, imaginary);
+    overlay::draw_dot(&mut mandelbrot, width, height, bytes_per_pixel, marker, MARKER_RADIUS, marker_color);
+    let julia = build_mandelbrot_cpu(&julia_options(options, real, imaginary));
+
+    let row_len = width * bytes_per_pixel;
+    let divider: Vec<u8> = (0..DIVIDER_WIDTH)
+        .flat_map(|_| if bytes_per_pixel == 1 { vec![DIVIDER_COLOR.luma()] } else { DIVIDER_COLOR.0.to_vec() })
+        .collect();
+    let mut image = Vec::with_capacity(height * (2 * row_len + divider.len()));
+    for (mandelbrot_row, julia_row) in mandelbrot.chunks(row_len).zip(julia.chunks(row_len)) {
+        image.extend_from_slice(mandelbrot_row);
+        image.extend_from_slice(&divider);
+        image.extend_from_slice(julia_row);
+    }
+    image
+}
@@ -0,0 +1,47 @@
+// The escape-time loop of mandelbrot_kernel (see main.rs) as a WGSL compute shader, for
+// rendering through wgpu. WGSL has no f64, so everything is calculated in f32.
+
+struct Params {
+    image_width: u32,
+    image_height: u32,
+    iterations: u32,
+    bailout_sq: f32,
+    real_start: f32,
+    i_start: f32,
+    real_step: f32,
+    i_step: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+// One pixel per element, 0 inside the mandelbrot set and 255 outside of it
+@group(0) @binding(1) var<storage, read_write> image: array<u32>;
+
+// Every invocation renders one pixel, the workgroup size has to match WORKGROUP_SIZE in
+// wgpu_engine.rs
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let j = id.x;
+    let i = id.y;
+    if (j >= params.image_width || i >= params.image_height) {
+        return; // Out of bounds
+    }
+
+    // compute x and y coordinates in mandelbrot space
+    let x = params.real_start + f32(j) * params.real_step;
+    let y = params.i_start - f32(i) * params.i_step;
+
+    // Leave the pixel black unless the point escapes
+    var value = 0u;
+    var z_real = x;
+    var z_imaginary = y;
+    for (var n = 0u; n < params.iterations; n = n + 1u) {
+        if (z_real * z_real + z_imaginary * z_imaginary >= params.bailout_sq) {
+            value = 255u;
+            break;
+        }
+        let next_real = z_real * z_real - z_imaginary * z_imaginary + x;
+        z_imaginary = (z_real + z_real) * z_imaginary + y;
+        z_real = next_real;
+    }
+    image[i * params.image_width + j] = value;
+}
@@ -0,0 +1,114 @@
+// A census of the small copies of the set in the view. Every copy shows up as a small blob of
+// pixels in the set with pixels outside of the set all around it, so the connected blobs of
+// pixels in the set that are small enough and don't touch the edges of the image are reported as
+// candidates to zoom in on.
+
+use crate::{render_rows, Complex, MandelbrotCpu};
+
+// A connected blob of pixels in the set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    // Number of pixels in the blob
+    pub pixels: usize,
+    // The average column and row of the pixels, in pixels from the top left corner of the image
+    pub column: f64,
+    pub row: f64,
+    // The size of the bounding box of the blob in pixels
+    pub width: usize,
+    pub height: usize,
+    // Whether the blob touches an edge of the image, so it may go on outside of the view
+    pub touches_edge: bool,
+}
+
+// A candidate copy of the set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Minibrot {
+    // The center of the copy in mandelbrot space
+    pub re: f64,
+    pub im: f64,
+    // The width of the copy in mandelbrot space, about the size to zoom in to
+    pub scale: f64,
+    pub pixels: usize,
+}
+
+// Returns the blobs of horizontally or vertically connected pixels that are {inside} the set in
+// an image {width} pixels wide, ordered by their first pixel from the top left
+pub fn components(inside: &[bool], width: usize) -> Vec<Component> {
+    let height = inside.len().checked_div(width).unwrap_or(0);
+    let mut seen = vec![false; inside.len()];
+    let mut components = vec![];
+    let mut stack = vec![];
+    for start in 0..inside.len() {
+        if !inside[start] || seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        stack.push(start);
+        let (mut pixels, mut column_total, mut row_total) = (0, 0, 0);
+        let (mut left, mut right, mut top, mut bottom) = (usize::MAX, 0, usize::MAX, 0);
+        while let Some(index) = stack.pop() {
+            let (row, column) = (index / width, index % width);
+            pixels += 1;
+            column_total += column;
+            row_total += row;
+            (left, right, top, bottom) = (left.min(column), right.max(column), top.min(row), bottom.max(row));
+            let neighbors = [
+                (column > 0).then(|| index - 1),
+                (column + 1 < width).then(|| index + 1),
+                (row > 0).then(|| index - width),
+                (row + 1 < height).then(|| index + width),
+            ];
+            for neighbor in neighbors.into_iter().flatten() {
+                if inside[neighbor] && !seen[neighbor] {
+                    seen[neighbor] = true;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        components.push(Component {
+            pixels,
+            column: column_total as f64 / pixels as f64,
+            row: row_total as f64 / pixels as f64,
+            width: right - left + 1,
+            height: bottom - top + 1,
+            touches_edge: left == 0 || top == 0 || right + 1 == width || bottom + 1 == height,
+        });
+    }
+    components
+}
+
+// Returns for every pixel of the view of {options} whether it's in the set
+pub fn membership(options: &MandelbrotCpu) -> Vec<bool> {
+    let mut inside = vec![false; options.image_width * options.image_height];
+    render_rows(&mut inside, options.image_width, options.threads, options.progress, |row, pixels| {
+        for (column, pixel) in pixels.iter_mut().enumerate() {
+            let point = options.plane_point(&options.pixel_point(row, column));
+            *pixel = options.escape_iteration(&point, options.iterations_at(row, column)).is_none();
+        }
+    });
+    inside
+}
+
+// Returns the copies of the set in the view of {options}: the blobs of pixels in the set of at
+// most {max_pixels} pixels that don't touch the edges of the image, largest first
+pub fn find_minibrots(options: &MandelbrotCpu, max_pixels: usize) -> Vec<Minibrot> {
+    let mut minibrots: Vec<Minibrot> = components(&membership(options), options.image_width)
+        .into_iter()
+        .filter(|component| !component.touches_edge && component.pixels <= max_pixels)
+        .map(|component| {
+            let center = Complex::new(
+                options.real_start + component.column * options.real_step,
+                options.i_start - component.row * options.i_step,
+            );
+            let center = options.plane_point(&center);
+            Minibrot {
+                re: center.real,
+                im: center.imaginary,
+                scale: (component.width as f64 * options.real_step).max(component.height as f64 * options.i_step),
+                pixels: component.pixels,
+            }
+        })
+        .collect();
+    minibrots.sort_by(|a, b| b.pixels.cmp(&a.pixels));
+    minibrots
+}
@@ -0,0 +1,82 @@
+// Names of the outputted files. Everything that writes images goes through output_path, so a
+// --name-template and --output-directory name every output the same way, instead of every mode
+// coming up with its own names.
+
+use std::path::{Path, PathBuf};
+
+// The placeholders a name template can use
+pub const PLACEHOLDERS: [&str; 5] = ["frame", "index", "zoom", "re", "im"];
+
+// The values the placeholders of a name template are replaced with
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameValues {
+    // Number of the frame of an animation, 0 for single images
+    pub frame: usize,
+    // Number of the output among the outputs of a run, like a tile or a step of a sweep
+    pub index: usize,
+    // How far the view is zoomed in compared to the default view
+    pub zoom: f64,
+    // The center of the view, as it was given
+    pub re: String,
+    pub im: String,
+}
+
+// Expands the placeholders in {template} like {frame} with {values}. The numbers {frame} and
+// {index} can be padded with zeros to a width, like {frame:04}. Returns an error for unknown
+// placeholders and unclosed braces.
+pub fn expand_template(template: &str, values: &NameValues) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("the name template {:?} has an unclosed {{", template))?;
+        let placeholder = &rest[start + 1..start + end];
+        let (key, width) = match placeholder.split_once(':') {
+            Some((key, width)) => (key, Some(width)),
+            None => (placeholder, None),
+        };
+        let number = match key {
+            "frame" => Some(values.frame),
+            "index" => Some(values.index),
+            _ => None,
+        };
+        match (number, width) {
+            (Some(number), None) => name.push_str(&number.to_string()),
+            (Some(number), Some(width)) => {
+                let width: usize = width
+                    .strip_prefix('0')
+                    .and_then(|width| width.parse().ok())
+                    .ok_or_else(|| format!("{{{}}} must be padded like {{{}:04}}", placeholder, key))?;
+                name.push_str(&format!("{:0width$}", number, width = width));
+            }
+            (None, None) if key == "zoom" => name.push_str(&values.zoom.to_string()),
+            (None, None) if key == "re" => name.push_str(&values.re),
+            (None, None) if key == "im" => name.push_str(&values.im),
+            _ => {
+                return Err(format!(
+                    "unknown placeholder {{{}}} in the name template, the placeholders are {{{}}}",
+                    placeholder,
+                    PLACEHOLDERS.join("}, {")
+                ))
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
+
+// Returns the path of an output in {directory}, named after {template} if there is one and
+// {file} otherwise
+pub fn output_path(directory: Option<&Path>, template: Option<&str>, file: &str, values: &NameValues) -> Result<PathBuf, String> {
+    let name = match template {
+        Some(template) => expand_template(template, values)?,
+        None => file.to_string(),
+    };
+    Ok(match directory {
+        Some(directory) => directory.join(name),
+        None => PathBuf::from(name),
+    })
+}
@@ -0,0 +1,48 @@
+// Orbit clouds: the orbits of a sparse grid of pixels, every z value they go through until they
+// escape, written as the points of a CSV file. Plotting the points shows the dynamics of the set,
+// like a buddhabrot of only a few orbits does.
+
+use crate::{Complex, MandelbrotCpu};
+use std::io::Write;
+
+// Most points written to an orbit cloud, so a small stride with many iterations doesn't turn into
+// a file of gigabytes
+pub const MAX_ORBIT_POINTS: usize = 1_000_000;
+
+// Returns the orbit of {point}: the number itself, then every number iterating it gives, up to
+// and including the first one that escapes, at most {max} iterations. Adds {julia} on every
+// iteration if given, and the point itself otherwise.
+pub fn orbit(point: &Complex, julia: Option<&Complex>, max: u32, bailout_sq: f64) -> Vec<Complex> {
+    let origin = julia.unwrap_or(point);
+    let mut z = point.clone();
+    let mut orbit = vec![z.clone()];
+    for _i in 0..max {
+        if z.has_escaped_radius(bailout_sq) {
+            break;
+        }
+        z.iterate(origin);
+        orbit.push(z.clone());
+    }
+    orbit
+}
+
+// Writes the orbits of every {stride}th pixel of {options}, counting row by row, to {out} as CSV
+// with a line per point. Stops after {max_points} points, the last orbit can be cut off. Returns
+// the number of points written.
+pub fn write_orbit_cloud(options: &MandelbrotCpu, stride: usize, max_points: usize, out: &mut impl Write) -> std::io::Result<usize> {
+    let mut written = 0;
+    writeln!(out, "column,row,step,re,im")?;
+    let pixels = (0..options.image_height).flat_map(|row| (0..options.image_width).map(move |column| (row, column)));
+    for (row, column) in pixels.step_by(stride.max(1)) {
+        let (start, constant) = options.orbit_start(&options.plane_point(&options.pixel_point(row, column)));
+        let iterations = options.iterations_at(row, column);
+        for (step, z) in orbit(&start, constant.as_ref(), iterations, options.bailout_sq).iter().enumerate() {
+            if written == max_points {
+                return Ok(written);
+            }
+            writeln!(out, "{},{},{},{},{}", column, row, step, z.real, z.imaginary)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
@@ -0,0 +1,82 @@
+// Composition aids drawn on top of the rendered image as a post-pass, after the image has
+// been validated, so they never count as rendering errors.
+
+use crate::color::Rgb;
+
+// Length of each arm of the crosshair, in pixels from the center pixel
+pub const CROSSHAIR_ARM: usize = 8;
+
+// Sets pixel ({x}, {y}) of an image with {bytes_per_pixel} bytes per pixel to {color}.
+// Grayscale images get the brightness of the color.
+pub fn set_pixel(image: &mut [u8], width: usize, bytes_per_pixel: usize, x: usize, y: usize, color: Rgb) {
+    let index = (y * width + x) * bytes_per_pixel;
+    let pixel = &mut image[index..index + bytes_per_pixel];
+    if bytes_per_pixel == 1 {
+        pixel[0] = color.luma();
+    } else {
+        pixel.copy_from_slice(&color.0);
+    }
+}
+
+// Returns the pixel at the center of a {width} x {height} image
+pub fn center_pixel(width: usize, height: usize) -> (usize, usize) {
+    (width / 2, height / 2)
+}
+
+// Draws a crosshair in {color} over the center pixel of the image
+pub fn draw_crosshair(image: &mut [u8], width: usize, height: usize, bytes_per_pixel: usize, color: Rgb) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (center_x, center_y) = center_pixel(width, height);
+    let left = center_x.saturating_sub(CROSSHAIR_ARM);
+    let right = (center_x + CROSSHAIR_ARM).min(width - 1);
+    let top = center_y.saturating_sub(CROSSHAIR_ARM);
+    let bottom = (center_y + CROSSHAIR_ARM).min(height - 1);
+    for x in left..=right {
+        set_pixel(image, width, bytes_per_pixel, x, center_y, color);
+    }
+    for y in top..=bottom {
+        set_pixel(image, width, bytes_per_pixel, center_x, y, color);
+    }
+}
+
+// Draws one pixel wide lines in {color} that divide the image into thirds both ways
+pub fn draw_thirds(image: &mut [u8], width: usize, height: usize, bytes_per_pixel: usize, color: Rgb) {
+    for third in [1, 2] {
+        let x = width * third / 3;
+        let y = height * third / 3;
+        if x < width {
+            for row in 0..height {
+                set_pixel(image, width, bytes_per_pixel, x, row, color);
+            }
+        }
+        if y < height {
+            for column in 0..width {
+                set_pixel(image, width, bytes_per_pixel, column, y, color);
+            }
+        }
+    }
+}
+
+// Sets the pixels of the image that are true in {mask}, one element per pixel, to {color}
+pub fn draw_mask(image: &mut [u8], width: usize, bytes_per_pixel: usize, mask: &[bool], color: Rgb) {
+    for (index, &set) in mask.iter().enumerate() {
+        if set {
+            set_pixel(image, width, bytes_per_pixel, index % width, index / width, color);
+        }
+    }
+}
+
+// Draws a filled dot in {color} with a radius of {radius} pixels around pixel ({x}, {y}).
+// Parts of the dot that fall outside of the image are left out.
+pub fn draw_dot(image: &mut [u8], width: usize, height: usize, bytes_per_pixel: usize, (x, y): (i64, i64), radius: i64, color: Rgb) {
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            let (px, py) = (x + dx, y + dy);
+            if dx * dx + dy * dy <= radius * radius && (0..width as i64).contains(&px) && (0..height as i64).contains(&py) {
+                set_pixel(image, width, bytes_per_pixel, px as usize, py as usize, color);
+            }
+        }
+    }
+}
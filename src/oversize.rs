@@ -0,0 +1,55 @@
+// Anti-aliasing by rendering the whole image at a multiple of its resolution and averaging every
+// factor x factor block of pixels into one outputted pixel. Unlike --samples every pixel gets the
+// same samples, and since the oversized image is a plain render it works on every engine and with
+// --row-range. The price is memory: the oversized image takes factor² times the memory of the
+// outputted one, where --samples only keeps the outputted image.
+
+use crate::{color, pixel_offset, MandelbrotCpu};
+use std::sync::Arc;
+
+// Returns the options for rendering the view of {options} at {factor} times its resolution
+pub fn oversized(options: &MandelbrotCpu, factor: usize) -> MandelbrotCpu {
+    let oversized = MandelbrotCpu {
+        image_width: options.image_width * factor,
+        image_height: options.image_height * factor,
+        real_step: options.real_step / factor as f64,
+        i_step: options.i_step / factor as f64,
+        iterations_map: options.iterations_map.as_ref().map(|map| Arc::new(map.scaled(factor))),
+        ..options.clone()
+    };
+    // Move the start from the sampled point of the large pixel to that of the first small one
+    let offset = (1.0 - factor as f64) * options.sample_offset();
+    pixel_offset(&oversized, offset, offset)
+}
+
+// Averages every {factor} x {factor} block of pixels of {image}, which is {factor} times as large
+// as {width} x {height}, into one pixel in linear light
+pub fn box_downsample(image: &[u8], width: usize, height: usize, bytes_per_pixel: usize, factor: usize) -> Vec<u8> {
+    let oversized_width = width * factor;
+    let mut downsampled = Vec::with_capacity(width * height * bytes_per_pixel);
+    let mut samples = Vec::with_capacity(factor * factor);
+    for row in 0..height {
+        for column in 0..width {
+            for channel in 0..bytes_per_pixel {
+                samples.clear();
+                for y in row * factor..(row + 1) * factor {
+                    for x in column * factor..(column + 1) * factor {
+                        samples.push(image[(y * oversized_width + x) * bytes_per_pixel + channel]);
+                    }
+                }
+                downsampled.push(color::average_linear(&samples));
+            }
+        }
+    }
+    downsampled
+}
+
+// Renders {options} with {render} at {factor} times the resolution and downsamples the result to
+// the size of {options}
+pub fn build_mandelbrot_oversized(options: &MandelbrotCpu, render: fn(&MandelbrotCpu) -> Vec<u8>, factor: usize) -> Vec<u8> {
+    if factor == 1 {
+        return render(options);
+    }
+    let image = render(&oversized(options, factor));
+    box_downsample(&image, options.image_width, options.image_height, options.bytes_per_pixel(), factor)
+}
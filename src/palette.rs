@@ -0,0 +1,101 @@
+// The built-in palettes. Every palette is a list of color stops spread evenly from 0 to 1, and
+// colors in between stops are interpolated.
+
+use crate::color::{self, ColorSpace};
+
+// The built-in palettes, see the stops in BuiltinPalette::stops
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuiltinPalette {
+    Grayscale,
+    Fire,
+    Ocean,
+    Rainbow,
+    Sunset,
+}
+
+impl BuiltinPalette {
+    // Every built-in palette, in the order they're listed in
+    pub const ALL: [BuiltinPalette; 5] = [
+        BuiltinPalette::Grayscale,
+        BuiltinPalette::Fire,
+        BuiltinPalette::Ocean,
+        BuiltinPalette::Rainbow,
+        BuiltinPalette::Sunset,
+    ];
+
+    // The name of the palette, as used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinPalette::Grayscale => "grayscale",
+            BuiltinPalette::Fire => "fire",
+            BuiltinPalette::Ocean => "ocean",
+            BuiltinPalette::Rainbow => "rainbow",
+            BuiltinPalette::Sunset => "sunset",
+        }
+    }
+
+    // Returns the palette called {name}, ignoring case
+    pub fn from_name(name: &str) -> Option<BuiltinPalette> {
+        BuiltinPalette::ALL
+            .into_iter()
+            .find(|palette| palette.name().eq_ignore_ascii_case(name))
+    }
+
+    // The colors the palette goes through. sample_in(0.0, ..) returns the first one and
+    // sample_in(1.0, ..) the last one.
+    pub fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            // Black to white
+            BuiltinPalette::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+            // Black through red and yellow to white
+            BuiltinPalette::Fire => &[[0, 0, 0], [128, 0, 0], [255, 64, 0], [255, 192, 0], [255, 255, 255]],
+            // Deep blue through teal to white
+            BuiltinPalette::Ocean => &[[0, 0, 32], [0, 64, 128], [0, 160, 192], [224, 255, 255]],
+            // Red through every hue to magenta
+            BuiltinPalette::Rainbow => &[
+                [255, 0, 0],
+                [255, 255, 0],
+                [0, 255, 0],
+                [0, 255, 255],
+                [0, 0, 255],
+                [255, 0, 255],
+            ],
+            // Dark purple through pink and orange to pale yellow
+            BuiltinPalette::Sunset => &[[32, 0, 64], [192, 32, 96], [255, 128, 64], [255, 224, 160]],
+        }
+    }
+
+    // Returns the color of the palette at {t} raised to the power {gamma}, which moves where the
+    // colors fall: below 1 the low values of t get more of the palette, above 1 the high ones.
+    // The stops are interpolated in {space}.
+    pub fn sample_gamma(&self, t: f64, gamma: f64, space: ColorSpace) -> [u8; 3] {
+        self.sample_in(t.clamp(0.0, 1.0).powf(gamma), space)
+    }
+
+    // Returns the color of the palette at {t}, which is clamped to [0, 1], interpolating between
+    // the stops in {space}
+    pub fn sample_in(&self, t: f64, space: ColorSpace) -> [u8; 3] {
+        let stops = self.stops();
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let index = (position.floor() as usize).min(stops.len() - 2);
+        let fraction = position - index as f64;
+        color::interpolate(stops[index], stops[index + 1], fraction, space)
+    }
+}
+
+impl std::str::FromStr for BuiltinPalette {
+    type Err = String;
+    fn from_str(name: &str) -> Result<BuiltinPalette, String> {
+        BuiltinPalette::from_name(name).ok_or_else(|| {
+            let names: Vec<&str> = BuiltinPalette::ALL.iter().map(|palette| palette.name()).collect();
+            format!("unknown palette {:?}, expected one of {}", name, names.join(", "))
+        })
+    }
+}
+
+impl std::fmt::Display for BuiltinPalette {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
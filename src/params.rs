@@ -0,0 +1,94 @@
+// Render parameters embedded in the outputted PNG, so a shared image describes how to render
+// it again without a separate file. The parameters are the JSON of --dump-args, stored in a
+// tEXt chunk with the key PARAMS_KEY.
+
+use crate::renderer::RenderError;
+use image::ColorType;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+// The key of the text chunk the parameters are stored in
+pub const PARAMS_KEY: &str = "gendelbrot:params";
+
+// Saves {image} as a PNG at {path} with {params} in a text chunk
+pub fn save_png_with_params(
+    path: &Path,
+    image: &[u8],
+    width: usize,
+    height: usize,
+    color_type: ColorType,
+    params: &str,
+) -> Result<(), RenderError> {
+    let file = File::create(path).map_err(|cause| RenderError::Io { path: path.to_path_buf(), cause: cause.to_string() })?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(match color_type {
+        ColorType::L8 => png::ColorType::Grayscale,
+        ColorType::Rgba8 => png::ColorType::Rgba,
+        _ => png::ColorType::Rgb,
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    let encode_error = |error: png::EncodingError| match error {
+        png::EncodingError::IoError(cause) => RenderError::Io { path: path.to_path_buf(), cause: cause.to_string() },
+        error => RenderError::Encode { path: path.to_path_buf(), cause: error.to_string() },
+    };
+    encoder
+        .add_text_chunk(PARAMS_KEY.to_string(), params.to_string())
+        .map_err(encode_error)?;
+    let mut writer = encoder.write_header().map_err(encode_error)?;
+    writer.write_image_data(image).map_err(encode_error)?;
+    writer.finish().map_err(encode_error)
+}
+
+// Arguments that aren't taken from the embedded parameters: where the image goes, so rendering
+// from an image never overwrites it, and what doesn't say anything about the render
+pub const SKIPPED_ARGS: [&str; 5] = ["file", "output_directory", "name_template", "dump_args", "frame_cache"];
+
+// Turns the JSON {params} of --dump-args back into command line arguments, leaving out the ones
+// in {given}, which are replaced by other values. Arguments with the same value as in {defaults},
+// the JSON of the default arguments, are left out too, so they can't conflict with the arguments
+// they are combined with.
+pub fn params_to_args(params: &str, defaults: &str, given: &[String]) -> Result<Vec<String>, String> {
+    let parse = |json: &str| match serde_json::from_str(json) {
+        Ok(serde_json::Value::Object(map)) => Ok(map),
+        _ => Err(format!("the embedded parameters {:?} aren't a JSON object", json)),
+    };
+    let (params, defaults) = (parse(params)?, parse(defaults)?);
+    let mut args = vec![];
+    for (key, value) in params {
+        if SKIPPED_ARGS.contains(&key.as_str()) || given.contains(&key) || defaults.get(&key) == Some(&value) {
+            continue;
+        }
+        let flag = format!("--{}", key.replace('_', "-"));
+        let text = |value: &serde_json::Value| match value {
+            serde_json::Value::String(text) => text.clone(),
+            value => value.to_string(),
+        };
+        match value {
+            serde_json::Value::Null | serde_json::Value::Bool(false) => {}
+            serde_json::Value::Bool(true) => args.push(flag),
+            serde_json::Value::Array(values) if values.is_empty() => {}
+            serde_json::Value::Array(values) => {
+                args.push(flag);
+                args.extend(values.iter().map(text));
+            }
+            value => args.extend([flag, text(&value)]),
+        }
+    }
+    Ok(args)
+}
+
+// Returns the parameters embedded in the PNG at {path}
+pub fn read_params(path: &Path) -> Result<String, String> {
+    let file = File::open(path).map_err(|error| format!("Couldn't open {:?}: {}", path, error))?;
+    let reader = png::Decoder::new(BufReader::new(file))
+        .read_info()
+        .map_err(|error| format!("Couldn't read {:?}: {}", path, error))?;
+    reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == PARAMS_KEY)
+        .map(|chunk| chunk.text.clone())
+        .ok_or_else(|| format!("{:?} has no embedded parameters, it wasn't saved with --save-params-with-image", path))
+}
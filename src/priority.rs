@@ -0,0 +1,125 @@
+// Lowering the scheduling priority of the render, so long renders in the background don't make
+// the rest of the machine sluggish. The priority is lowered on the thread that calls
+// lower_priority and inherited by the threads it starts afterwards, so calling it before
+// rendering covers every render thread.
+
+// The niceness Unix systems go up to
+pub const MAX_NICE: i32 = 19;
+
+#[cfg(unix)]
+extern "C" {
+    fn getpriority(which: std::ffi::c_int, who: std::ffi::c_uint) -> std::ffi::c_int;
+    fn setpriority(which: std::ffi::c_int, who: std::ffi::c_uint, prio: std::ffi::c_int) -> std::ffi::c_int;
+}
+
+#[cfg(unix)]
+const PRIO_PROCESS: std::ffi::c_int = 0;
+
+// Where errno is kept, every libc names the function that returns it differently
+#[cfg(any(target_os = "linux", target_os = "android"))]
+extern "C" {
+    #[link_name = "__errno_location"]
+    fn errno_location() -> *mut std::ffi::c_int;
+}
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly"))]
+extern "C" {
+    #[link_name = "__error"]
+    fn errno_location() -> *mut std::ffi::c_int;
+}
+#[cfg(any(target_os = "netbsd", target_os = "openbsd"))]
+extern "C" {
+    #[link_name = "__errno"]
+    fn errno_location() -> *mut std::ffi::c_int;
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn clear_errno() {
+    unsafe { *errno_location() = 0 }
+}
+
+// getpriority can't fail for this process, so a stale errno only matters when the niceness is -1
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))
+))]
+fn clear_errno() {}
+
+// Returns the niceness this thread runs at
+#[cfg(unix)]
+pub fn current_nice() -> Result<i32, String> {
+    // -1 is a valid niceness as well as the error value of getpriority, so errors can only be told
+    // apart by errno, which has to be cleared first
+    clear_errno();
+    let nice = unsafe { getpriority(PRIO_PROCESS, 0) };
+    if nice == -1 {
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error().is_some_and(|code| code != 0) {
+            return Err(error.to_string());
+        }
+    }
+    Ok(nice)
+}
+
+// Lowers the priority of this thread and the ones it starts by {nice}, from 0 (no change) to
+// MAX_NICE (only run when nothing else wants to). The niceness goes up by {nice} from where it
+// is, up to MAX_NICE, since only privileged users can raise the priority again.
+#[cfg(unix)]
+pub fn lower_priority(nice: i32) -> Result<(), String> {
+    let current = current_nice()?;
+    let lowered = current.saturating_add(nice.clamp(0, MAX_NICE)).min(MAX_NICE);
+    if lowered <= current {
+        return Ok(());
+    }
+    // On Linux this only affects the calling thread, elsewhere the whole process
+    if unsafe { setpriority(PRIO_PROCESS, 0, lowered) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetCurrentProcess() -> *mut std::ffi::c_void;
+    fn SetPriorityClass(process: *mut std::ffi::c_void, priority_class: u32) -> i32;
+}
+
+// Windows only has a few priority classes, so the niceness picks the closest one
+#[cfg(windows)]
+pub fn lower_priority(nice: i32) -> Result<(), String> {
+    const NORMAL_PRIORITY_CLASS: u32 = 0x20;
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+    const IDLE_PRIORITY_CLASS: u32 = 0x40;
+    let class = match nice {
+        i32::MIN..=0 => NORMAL_PRIORITY_CLASS,
+        1..=14 => BELOW_NORMAL_PRIORITY_CLASS,
+        _ => IDLE_PRIORITY_CLASS,
+    };
+    if unsafe { SetPriorityClass(GetCurrentProcess(), class) } == 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn lower_priority(_nice: i32) -> Result<(), String> {
+    Err("lowering the priority isn't supported on this platform".to_string())
+}
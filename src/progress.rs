@@ -0,0 +1,35 @@
+// The progress shown while rendering. The report is rewritten in place with a carriage return,
+// which only works on a terminal, so it's left out when stdout is redirected to a file or a log
+// and with --no-progress.
+
+use std::io::{IsTerminal, Write};
+
+// Returns whether to show the progress, for --no-progress {no_progress}
+pub fn enabled(no_progress: bool) -> bool {
+    !no_progress && std::io::stdout().is_terminal()
+}
+
+// Writes the progress of {done} out of {total} to {out} over the previous report if {enabled}
+pub fn write_progress(out: &mut impl Write, enabled: bool, done: usize, total: usize) -> std::io::Result<()> {
+    if !enabled {
+        return Ok(());
+    }
+    write!(out, "Progress: {}%  \r", (done as f64 / total as f64 * 100.0).round())?;
+    // Flush the output to ensure the progress is displayed
+    out.flush()
+}
+
+// Shows the progress of {done} out of {total} on stdout if {enabled}
+pub fn report(enabled: bool, done: usize, total: usize) {
+    // The progress is only for show, a closed stdout doesn't stop the render
+    let _ = write_progress(&mut std::io::stdout(), enabled, done, total);
+}
+
+// Prints {message} about what the render is doing on stdout if {enabled}. These go with the
+// progress, so they're left out along with it, and --preview-ascii-live can draw over the
+// terminal without the renders scrolling it.
+pub fn status(enabled: bool, message: &str) {
+    if enabled {
+        println!("{}", message);
+    }
+}
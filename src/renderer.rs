@@ -0,0 +1,68 @@
+// The rendered image as it's handed to the encoders, and the errors from writing it
+
+use image::ColorType;
+use std::path::PathBuf;
+
+// Errors from writing the rendered image
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderError {
+    // The image couldn't be written to the file at path
+    Io { path: PathBuf, cause: String },
+    // The image couldn't be encoded in the format of the file at path
+    Encode { path: PathBuf, cause: String },
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RenderError::Io { path, cause } => write!(f, "Couldn't write the image to {:?}: {}", path, cause),
+            RenderError::Encode { path, cause } => write!(f, "Couldn't encode the image for {:?}: {}", path, cause),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+// A rendered image, as the flat buffer of bytes the encoders take, tagged with the pixel format
+// so the pixels can't be misread with the wrong number of bytes per pixel
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderOutput {
+    // Shadings with colors, 3 bytes per pixel
+    Rgb(Vec<u8>),
+    // Flat shading, which only has black and white pixels, 1 byte per pixel
+    Gray(Vec<u8>),
+    // Any shading with a transparent part, see alpha.rs, 4 bytes per pixel
+    Rgba(Vec<u8>),
+}
+
+impl RenderOutput {
+    // Wraps the flat {image} with pixels of {color_type} without copying it. Returns an error if
+    // the color type isn't L8, Rgb8 or Rgba8, or the image isn't made of whole pixels.
+    pub fn new(image: Vec<u8>, color_type: ColorType) -> Result<RenderOutput, String> {
+        let bytes_per_pixel = color_type.bytes_per_pixel() as usize;
+        if image.len() % bytes_per_pixel != 0 {
+            return Err(format!("{} bytes aren't whole pixels of {:?}", image.len(), color_type));
+        }
+        match color_type {
+            ColorType::L8 => Ok(RenderOutput::Gray(image)),
+            ColorType::Rgb8 => Ok(RenderOutput::Rgb(image)),
+            ColorType::Rgba8 => Ok(RenderOutput::Rgba(image)),
+            color_type => Err(format!("images are never rendered as {:?}", color_type)),
+        }
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        match self {
+            RenderOutput::Rgb(_) => ColorType::Rgb8,
+            RenderOutput::Gray(_) => ColorType::L8,
+            RenderOutput::Rgba(_) => ColorType::Rgba8,
+        }
+    }
+
+    // Returns the pixels as the flat buffer of bytes the encoders take
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            RenderOutput::Rgb(pixels) | RenderOutput::Gray(pixels) | RenderOutput::Rgba(pixels) => pixels,
+        }
+    }
+}
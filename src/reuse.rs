@@ -0,0 +1,145 @@
+// Reusing the previous frame of a pan animation. When the view moves by a whole number of
+// pixels, most of the new frame is already in the previous one, shifted over. Only the strips
+// that scrolled into view have to be rendered, the rest is copied.
+
+use crate::{MandelbrotCpu, Precision};
+use std::path::Path;
+
+// How far off a whole number of pixels a pan may be and still be reused
+const PIXEL_TOLERANCE: f64 = 1e-6;
+
+// A rectangle of pixels in the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub column: usize,
+    pub row: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// Returns by how many pixels the view of {next} moved right and down from the view of
+// {previous}, if it moved by a whole number of pixels and the frames still overlap. Only the
+// views are compared, everything else about the frames is assumed to be the same.
+pub fn pan_offset(previous: &MandelbrotCpu, next: &MandelbrotCpu) -> Option<(isize, isize)> {
+    if previous.image_width != next.image_width
+        || previous.image_height != next.image_height
+        || previous.real_step != next.real_step
+        || previous.i_step != next.i_step
+        // The map is tied to the pixels of the image, not to the view
+        || next.iterations_map.is_some()
+        || next.precision != Precision::F64
+        // Inverting isn't the same everywhere in the view, so a pan doesn't shift the image
+        || next.invert_plane
+        // Neither are the transform around the center of the view and the log-polar strip
+        || next.transform.is_some()
+        || next.log_polar.is_some()
+    {
+        return None;
+    }
+    let to_pixels = |distance: f64, step: f64| {
+        let pixels = distance / step;
+        if (pixels - pixels.round()).abs() > PIXEL_TOLERANCE {
+            return None;
+        }
+        Some(pixels.round() as isize)
+    };
+    let dx = to_pixels(next.real_start - previous.real_start, next.real_step)?;
+    let dy = to_pixels(previous.i_start - next.i_start, next.i_step)?;
+    if dx.unsigned_abs() >= next.image_width || dy.unsigned_abs() >= next.image_height {
+        return None;
+    }
+    Some((dx, dy))
+}
+
+// Returns the parts of a {width} x {height} image that scrolled into view when panning by
+// {dx} pixels right and {dy} pixels down, which are the parts that have to be rendered
+pub fn exposed_regions(width: usize, height: usize, dx: isize, dy: isize) -> Vec<Region> {
+    let mut regions = vec![];
+    let (dx_pixels, dy_pixels) = (dx.unsigned_abs(), dy.unsigned_abs());
+    // The columns on the side the view moved towards, over the full height
+    let columns = if dx > 0 { width - dx_pixels..width } else { 0..dx_pixels };
+    if !columns.is_empty() {
+        regions.push(Region {
+            column: columns.start,
+            row: 0,
+            width: columns.len(),
+            height,
+        });
+    }
+    // The rows on the side the view moved towards, without the corner the columns already cover
+    let rows = if dy > 0 { height - dy_pixels..height } else { 0..dy_pixels };
+    if !rows.is_empty() && width > dx_pixels {
+        regions.push(Region {
+            column: if dx < 0 { dx_pixels } else { 0 },
+            row: rows.start,
+            width: width - dx_pixels,
+            height: rows.len(),
+        });
+    }
+    regions
+}
+
+// Loads the previous frame from {path}, which has to have the size and color type {options}
+// renders
+pub fn load_previous(path: &Path, options: &MandelbrotCpu) -> Result<Vec<u8>, String> {
+    let image = image::open(path).map_err(|error| format!("Couldn't open previous frame {:?}: {}", path, error))?;
+    if (image.width() as usize, image.height() as usize) != (options.image_width, options.image_height) {
+        return Err(format!(
+            "The previous frame {:?} is {}x{} pixels, expected {}x{} pixels",
+            path,
+            image.width(),
+            image.height(),
+            options.image_width,
+            options.image_height
+        ));
+    }
+    Ok(if options.bytes_per_pixel() == 3 {
+        image.into_rgb8().into_raw()
+    } else {
+        image.into_luma8().into_raw()
+    })
+}
+
+// Renders the image of {options} by shifting {previous_image}, the image of {previous}, and
+// only rendering the newly exposed strips with {render}. Renders the full image if the view
+// didn't move by a whole number of pixels, see pan_offset.
+pub fn build_mandelbrot_reusing(
+    previous: &MandelbrotCpu,
+    previous_image: &[u8],
+    options: &MandelbrotCpu,
+    render: impl Fn(&MandelbrotCpu) -> Vec<u8>,
+) -> Vec<u8> {
+    let (width, height, bytes_per_pixel) = (options.image_width, options.image_height, options.bytes_per_pixel());
+    let (dx, dy) = match pan_offset(previous, options) {
+        Some(offset) if previous_image.len() == width * height * bytes_per_pixel => offset,
+        _ => return render(options),
+    };
+
+    // Copy the overlap, pixel (row, column) of the new frame is (row + dy, column + dx) of the
+    // previous one
+    let mut final_image = vec![u8::MAX; previous_image.len()];
+    let columns = dx.max(0) as usize..(width as isize + dx.min(0)) as usize;
+    let row_len = columns.len() * bytes_per_pixel;
+    for row in dy.max(0) as usize..(height as isize + dy.min(0)) as usize {
+        let from = (row * width + columns.start) * bytes_per_pixel;
+        let to = (((row as isize - dy) as usize) * width + (columns.start as isize - dx) as usize) * bytes_per_pixel;
+        final_image[to..to + row_len].copy_from_slice(&previous_image[from..from + row_len]);
+    }
+
+    for region in exposed_regions(width, height, dx, dy) {
+        let start = options.pixel_point(region.row, region.column);
+        let strip = render(&MandelbrotCpu {
+            image_width: region.width,
+            image_height: region.height,
+            real_start: start.real,
+            i_start: start.imaginary,
+            ..options.clone()
+        });
+        let row_len = region.width * bytes_per_pixel;
+        for (i, strip_row) in strip.chunks(row_len).enumerate() {
+            let to = ((region.row + i) * width + region.column) * bytes_per_pixel;
+            final_image[to..to + row_len].copy_from_slice(strip_row);
+        }
+    }
+    final_image
+}
@@ -0,0 +1,36 @@
+// A compact file format for the escape iteration count of every pixel (see
+// render_iterations), for archiving renders without losing the counts to shading. Fractals have
+// long runs of equal counts, especially inside the set, so the counts are run-length encoded.
+//
+// The format, with every number a little-endian u32:
+//   the magic bytes "GRLE", the width, the height, the iteration cap, then (length, count)
+//   pairs for the runs of equal counts, row by row.
+
+// Marks the start of every .rle file
+const MAGIC: &[u8; 4] = b"GRLE";
+
+// The escape iteration counts of a rendered image
+#[derive(Debug, Clone, PartialEq)]
+pub struct IterationCounts {
+    pub width: usize,
+    pub height: usize,
+    // The iteration count at which pixels count as part of the set
+    pub iterations: u32,
+    // One count per pixel, row by row
+    pub counts: Vec<u32>,
+}
+
+impl IterationCounts {
+    // Returns the contents of the .rle file of the counts, in the format above
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        for value in [self.width as u32, self.height as u32, self.iterations] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for run in self.counts.chunk_by(|a, b| a == b) {
+            data.extend_from_slice(&(run.len() as u32).to_le_bytes());
+            data.extend_from_slice(&run[0].to_le_bytes());
+        }
+        data
+    }
+}
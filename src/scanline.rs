@@ -0,0 +1,54 @@
+// Calculating a single row or column of the image, for looking at how the escape counts change
+// along a line through a feature, like the equipotential bands or the period of the bulbs it
+// crosses. The pixels are mapped to the plane the same way as in a full render, including the
+// transforms and the inversion of the view, so the counts are the same as in that line of
+// render_iterations. The coordinates in the CSV are those of the pixels in the view.
+
+use crate::{render_rows, MandelbrotCpu};
+use std::io::Write;
+
+// The line of the image to calculate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scanline {
+    // The row, counting from 0 at the top
+    Row(usize),
+    // The column, counting from 0 on the left
+    Column(usize),
+}
+
+impl Scanline {
+    // Returns the (row, column) of every pixel on the line in the image of {options}, or an error
+    // if the line isn't in the image
+    pub fn pixels(&self, options: &MandelbrotCpu) -> Result<Vec<(usize, usize)>, String> {
+        match *self {
+            Scanline::Row(row) if row < options.image_height => Ok((0..options.image_width).map(|column| (row, column)).collect()),
+            Scanline::Column(column) if column < options.image_width => Ok((0..options.image_height).map(|row| (row, column)).collect()),
+            Scanline::Row(row) => Err(format!("--scanline {} is outside of the image, which has {} rows", row, options.image_height)),
+            Scanline::Column(column) => Err(format!("--scanline-col {} is outside of the image, which has {} columns", column, options.image_width)),
+        }
+    }
+}
+
+// Returns the escape count of every pixel in {pixels}, see MandelbrotCpu::escape_count
+pub fn scanline_counts(options: &MandelbrotCpu, pixels: &[(usize, usize)]) -> Vec<u32> {
+    let mut counts = vec![0; pixels.len()];
+
+    // Every pixel is a row of its own, so the pixels are spread over the threads
+    render_rows(&mut counts, 1, options.threads, options.progress, |index, count| {
+        let (row, column) = pixels[index];
+        count[0] = options.escape_count(row, column);
+    });
+
+    counts
+}
+
+// Writes the coordinates and the escape count of every pixel in {pixels}, with {counts} from
+// scanline_counts, to {out} as CSV, one pixel per line
+pub fn write_scanline(options: &MandelbrotCpu, pixels: &[(usize, usize)], counts: &[u32], out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out, "column,row,re,im,count")?;
+    for (&(row, column), count) in pixels.iter().zip(counts) {
+        let point = options.pixel_point(row, column);
+        writeln!(out, "{},{},{},{},{}", column, row, point.real, point.imaginary, count)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+// The order the rows of an image are handed out to the render threads in. The final image is the
+// same in every order, only the order the rows are finished in changes, which is what a live
+// preview of the render shows.
+
+// The row orders --row-order accepts
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RowOrder {
+    // From the top of the image down
+    TopDown,
+    // From the middle row outwards, alternating between the rows above and below it, so the
+    // center of the view, usually the interesting part, is finished first
+    CenterOut,
+}
+
+impl RowOrder {
+    // Returns the indices of the {rows} rows of an image in the order they're rendered in
+    pub fn rows(&self, rows: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..rows).collect();
+        if *self == RowOrder::CenterOut {
+            // Twice the distance to the middle of the image, which keeps it a whole number. Rows
+            // at the same distance stay in order, so the row above the middle comes first.
+            order.sort_by_key(|&row| (2 * row).abs_diff(rows.saturating_sub(1)));
+        }
+        order
+    }
+}
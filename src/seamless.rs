@@ -0,0 +1,75 @@
+// Making the outputted image tile seamlessly, for using it as a texture. Every row is blended
+// with itself reversed, and then every column, so the opposite edges of the image end up the same
+// and tiles put next to each other continue where the last one left off. This is done on the
+// finished image, so it works with every engine and shading.
+
+// How the edges of the image are made to match
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeamlessMode {
+    // The right half is the left half mirrored and the bottom half the top half mirrored
+    Mirror,
+    // Only a band along every edge is blended with the mirrored image, more and more towards the
+    // edge, see CROSS_FADE_FRACTION
+    CrossFade,
+}
+
+// Width of the band along every edge that cross-fade blends, as a fraction of the side
+pub const CROSS_FADE_FRACTION: f64 = 0.125;
+
+impl SeamlessMode {
+    // Returns how much of the mirrored pixel goes into the pixel at {position} of a line of
+    // {length} pixels
+    fn mirror_weight(&self, position: usize, length: usize) -> f64 {
+        match self {
+            SeamlessMode::Mirror => {
+                if position >= length.div_ceil(2) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            SeamlessMode::CrossFade => {
+                let band = (length as f64 * CROSS_FADE_FRACTION).max(1.0);
+                let edge_distance = position.min(length - 1 - position) as f64;
+                (0.5 * (1.0 - edge_distance / band)).max(0.0)
+            }
+        }
+    }
+}
+
+// Blends {lines} lines of {length} pixels of {image}, with {bytes_per_pixel} bytes per pixel,
+// with themselves reversed. {index} returns where pixel {position} of line {line} is in the image.
+fn blend_lines(
+    image: &mut [u8],
+    lines: usize,
+    length: usize,
+    bytes_per_pixel: usize,
+    mode: SeamlessMode,
+    index: impl Fn(usize, usize) -> usize,
+) {
+    let mut original = vec![0; length * bytes_per_pixel];
+    for line in 0..lines {
+        for position in 0..length {
+            let start = index(line, position) * bytes_per_pixel;
+            original[position * bytes_per_pixel..(position + 1) * bytes_per_pixel]
+                .copy_from_slice(&image[start..start + bytes_per_pixel]);
+        }
+        for position in 0..length {
+            let weight = mode.mirror_weight(position, length);
+            let mirrored = length - 1 - position;
+            for channel in 0..bytes_per_pixel {
+                let own = original[position * bytes_per_pixel + channel] as f64;
+                let other = original[mirrored * bytes_per_pixel + channel] as f64;
+                image[index(line, position) * bytes_per_pixel + channel] = ((1.0 - weight) * own + weight * other).round() as u8;
+            }
+        }
+    }
+}
+
+// Makes the {width} x {height} {image}, with {bytes_per_pixel} bytes per pixel, tile seamlessly
+// with {mode}. The first and last column end up the same, and so do the first and last row.
+pub fn make_seamless(image: &mut [u8], width: usize, height: usize, bytes_per_pixel: usize, mode: SeamlessMode) {
+    blend_lines(image, height, width, bytes_per_pixel, mode, |row, column| row * width + column);
+    blend_lines(image, width, height, bytes_per_pixel, mode, |column, row| row * width + column);
+}
@@ -0,0 +1,45 @@
+// Rendering in f32 on the CPU, the precision the wgpu engine calculates in. The coordinates and
+// the iteration are calculated the same way as in mandelbrot.wgsl, so a render on a machine
+// without a GPU shows where f32 runs out of precision, at a zoom of around 1e-6.
+
+use crate::{progress, render_rows, MandelbrotCpu};
+
+// Runs the mandelbrot algorithm on (real, imaginary) in f32 and returns whether it stays within
+// the bailout for {stable_iterations} iterations
+pub fn is_stable(real: f32, imaginary: f32, stable_iterations: u32, bailout_sq: f32) -> bool {
+    let mut z_real = real;
+    let mut z_imaginary = imaginary;
+    for _i in 0..stable_iterations {
+        if z_real * z_real + z_imaginary * z_imaginary >= bailout_sq {
+            return false;
+        }
+        let next_real = z_real * z_real - z_imaginary * z_imaginary + real;
+        z_imaginary = (z_real + z_real) * z_imaginary + imaginary;
+        z_real = next_real;
+    }
+    true
+}
+
+// Renders the image with flat shading, doing all calculations in f32
+pub fn build_mandelbrot_f32(options: &MandelbrotCpu) -> Vec<u8> {
+    let (real_start, i_start) = (options.real_start as f32, options.i_start as f32);
+    let (real_step, i_step) = (options.real_step as f32, options.i_step as f32);
+    let bailout_sq = options.bailout_sq as f32;
+
+    let mut final_image = vec![u8::MAX; options.image_width * options.image_height];
+
+    progress::status(options.progress, "Generating Image...");
+    render_rows(&mut final_image, options.image_width, options.threads, options.progress, |row, pixels| {
+        let y = i_start - row as f32 * i_step;
+        for (j, pixel) in pixels.iter_mut().enumerate() {
+            let x = real_start + j as f32 * real_step;
+            *pixel = if is_stable(x, y, options.iterations, bailout_sq) {
+                0
+            } else {
+                u8::MAX
+            };
+        }
+    });
+
+    final_image
+}
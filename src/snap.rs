@@ -0,0 +1,68 @@
+// Snapping the center of the view to the boundary of the set, where the detail is. A small grid
+// around the requested center is checked for membership, and the view is moved to the nearest
+// point of the grid on the boundary: one in the set next to one outside of it.
+
+use crate::{pixel_offset, Complex, MandelbrotCpu};
+
+// Number of grid points on every side of the center
+pub const SNAP_GRID: usize = 32;
+
+// How far from the center the boundary is looked for, as a fraction of the smallest side of the
+// view
+const SNAP_RADIUS: f64 = 0.1;
+
+// Returns the point on the boundary of the set nearest to {center}, within SNAP_RADIUS of the
+// view of {options}, along with the distance between the grid points. Returns None if there is
+// no boundary that close.
+pub fn nearest_boundary(options: &MandelbrotCpu, center: &Complex) -> Option<(Complex, f64)> {
+    let radius = SNAP_RADIUS
+        * (options.real_step * options.image_width as f64).min(options.i_step * options.image_height as f64);
+    let step = radius / SNAP_GRID as f64;
+    let side = 2 * SNAP_GRID + 1;
+    let point = |row: usize, column: usize| {
+        Complex::new(
+            center.real + (column as f64 - SNAP_GRID as f64) * step,
+            center.imaginary - (row as f64 - SNAP_GRID as f64) * step,
+        )
+    };
+    let inside: Vec<bool> = (0..side * side).map(|index| options.contains(&point(index / side, index % side))).collect();
+
+    let mut nearest: Option<(usize, usize, usize)> = None;
+    for row in 0..side {
+        for column in 0..side {
+            if !inside[row * side + column] {
+                continue;
+            }
+            let neighbors = [
+                (column > 0).then(|| row * side + column - 1),
+                (column + 1 < side).then(|| row * side + column + 1),
+                (row > 0).then(|| (row - 1) * side + column),
+                (row + 1 < side).then(|| (row + 1) * side + column),
+            ];
+            if !neighbors.into_iter().flatten().any(|neighbor| !inside[neighbor]) {
+                continue;
+            }
+            let distance = row.abs_diff(SNAP_GRID).pow(2) + column.abs_diff(SNAP_GRID).pow(2);
+            match nearest {
+                Some((closest, _, _)) if closest <= distance => {}
+                _ => nearest = Some((distance, row, column)),
+            }
+        }
+    }
+    nearest.map(|(_, row, column)| (point(row, column), step))
+}
+
+// Returns the view of {options} moved so its center is on the nearest boundary of the set.
+// Returns None if there is no boundary near the center.
+pub fn snap_to_boundary(options: &MandelbrotCpu) -> Option<MandelbrotCpu> {
+    let center = Complex::new(
+        options.real_start + options.real_step * options.image_width as f64 / 2.0,
+        options.i_start - options.i_step * options.image_height as f64 / 2.0,
+    );
+    let (boundary, _) = nearest_boundary(options, &center)?;
+    Some(pixel_offset(
+        options,
+        (boundary.real - center.real) / options.real_step,
+        (center.imaginary - boundary.imaginary) / options.i_step,
+    ))
+}
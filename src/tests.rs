@@ -1,4 +1,6 @@
 use crate::*;
+#[cfg(feature = "gpu")]
+use crate::cuda_engine::*;
 
 #[test]
 fn test_complex() {
@@ -26,86 +28,2961 @@ fn test_complex_iterate() {
     assert_eq!(c1.imaginary, 1366.0);
 }
 
+// The mandelbrot algorithm written out with iterate and has_escaped_radius, to check the
+// optimized loop of is_stable_radius against
+fn is_stable_reference(point: &Complex, stable_iterations: u32, bailout_sq: f64) -> bool {
+    let mut copy = point.clone();
+    for _i in 0..stable_iterations {
+        if copy.has_escaped_radius(bailout_sq) {
+            return false;
+        }
+        copy.iterate(point);
+    }
+    true
+}
+
+#[test]
+fn test_complex_is_stable_squares() {
+    // Reusing the squares gives exactly the same result for every point, including the ones
+    // right on the edge of the set
+    for i in 0..200 {
+        for j in 0..300 {
+            let point = Complex::new(-2.0 + j as f64 / 100.0, -1.0 + i as f64 / 100.0);
+            for iterations in [1, 10, 1000] {
+                assert_eq!(
+                    point.is_stable_radius(iterations, BAILOUT_SQ),
+                    is_stable_reference(&point, iterations, BAILOUT_SQ),
+                    "{:?} {}",
+                    point,
+                    iterations
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_escape_info() {
+    let info = |real: f64, imaginary: f64, max: u32| Complex::new(real, imaginary).escape_info(max, BAILOUT_SQ);
+    let escaped = |iterations: u32, final_re: f64, final_im: f64| EscapeInfo { iterations, escaped: true, final_re, final_im };
+    let stable = |iterations: u32, final_re: f64, final_im: f64| EscapeInfo { iterations, escaped: false, final_re, final_im };
+
+    // 2 starts on the escape radius, 1 gets there after one iteration and 1 + i after two
+    assert_eq!(info(2.0, 0.0, 50), escaped(0, 2.0, 0.0));
+    assert_eq!(info(1.0, 0.0, 50), escaped(1, 2.0, 0.0));
+    assert_eq!(info(1.0, 1.0, 50), escaped(1, 1.0, 3.0));
+    // 0 stays put and -1 alternates between -1 and 0
+    assert_eq!(info(0.0, 0.0, 50), stable(50, 0.0, 0.0));
+    assert_eq!(info(-1.0, 0.0, 10), stable(10, -1.0, 0.0));
+    assert_eq!(info(-1.0, 0.0, 11), stable(11, 0.0, 0.0));
+    // Without iterations nothing escapes
+    assert_eq!(info(3.0, 0.0, 0), stable(0, 3.0, 0.0));
+
+    // The same numbers as iterating step by step, for julia sets too
+    let origin = Complex::new(-0.8, 0.156);
+    for i in 0..40 {
+        for j in 0..60 {
+            let point = Complex::new(-2.0 + j as f64 / 20.0, -1.0 + i as f64 / 20.0);
+            let mut copy = point.clone();
+            let mut iterations = 0;
+            while iterations < 100 && !copy.has_escaped_radius(BAILOUT_SQ) {
+                copy.iterate(&origin);
+                iterations += 1;
+            }
+            let info = point.escape_info_from(&origin, 100, BAILOUT_SQ);
+            assert_eq!(info.iterations, iterations);
+            assert_eq!(info.escaped, iterations < 100);
+            assert_eq!((info.final_re, info.final_im), (copy.real, copy.imaginary));
+        }
+    }
+}
+
+#[test]
+fn test_huge_iteration_counts() {
+    // Iteration counts past i32::MAX parse, negative ones don't
+    let args = Args::try_parse_from(["gendelbrot", "--iterations", "3000000000"]).unwrap();
+    assert_eq!(args.iterations, 3_000_000_000);
+    assert!(Args::try_parse_from(["gendelbrot", "--iterations", "-1"]).is_err());
+    assert!(Args::try_parse_from(["gendelbrot", "--iterations", "5000000000"]).is_err());
+
+    // Escaping numbers still stop at their escape, whatever the budget
+    for max in [i32::MAX as u32, i32::MAX as u32 + 1, u32::MAX] {
+        let info = Complex::new(1.0, 0.0).escape_info(max, BAILOUT_SQ);
+        assert_eq!((info.iterations, info.escaped), (1, true));
+        let (smooth, _) = Complex::new(0.3, 0.0).escape_distance(None, max, BAILOUT_SQ).unwrap();
+        assert!(smooth > 0.0 && smooth < 1000.0, "{}", smooth);
+        // The shadings that divide by the budget stay in range
+        let shade = color::hybrid_shade(smooth, max, 0.1, 0.01);
+        assert!(shade < 10, "{}", shade);
+        assert_eq!(color::fade_to_interior([200; 3], smooth, max, 100), [200; 3]);
+        assert_eq!(color::fade_to_interior([200; 3], max as f64, max, 100), [0; 3]);
+    }
+    // A clamp larger than the budget fades from the start
+    assert_eq!(color::fade_to_interior([200; 3], 0.0, 10, 20), [100; 3]);
+}
+
+// Prints how many iterations per second is_stable_radius does compared to the plain loop. Run
+// with cargo test --release -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_is_stable_radius() {
+    let points: Vec<Complex> = (0..10000).map(|i| Complex::new(-0.75 + i as f64 * 1e-6, 0.1)).collect();
+    let iterations = 10000;
+    let measure = |name: &str, is_stable: &dyn Fn(&Complex) -> bool| {
+        let start = std::time::Instant::now();
+        let stable = points.iter().filter(|point| is_stable(point)).count();
+        let elapsed = start.elapsed().as_secs_f64();
+        // Stable points take every iteration, the others are only a small fraction of the work
+        println!(
+            "{}: {:.0} million iterations per second ({} stable)",
+            name,
+            (stable as f64 * iterations as f64) / elapsed / 1e6,
+            stable
+        );
+    };
+    measure("iterate + has_escaped_radius", &|point| is_stable_reference(point, iterations, BAILOUT_SQ));
+    measure("is_stable_radius", &|point| point.is_stable_radius(iterations, BAILOUT_SQ));
+}
+
+#[cfg(feature = "num-complex")]
+#[test]
+fn test_complex_num_complex() {
+    let value = num_complex::Complex::new(-0.75, 0.1);
+    let complex = Complex::from(value);
+    assert_eq!((complex.real, complex.imaginary), (-0.75, 0.1));
+    assert_eq!(num_complex::Complex::<f64>::from(complex), value);
+
+    // f32 numbers are widened without loss
+    let complex = Complex::from(num_complex::Complex::new(0.1f32, -2.5f32));
+    assert_eq!((complex.real, complex.imaginary), (0.1f32 as f64, -2.5));
+}
+
+#[test]
+fn test_mandelbrot_cpu_options_default() {
+    let options = MandelbrotCpu::default();
+    assert_eq!(options.fractal, FractalKind::Mandelbrot);
+    assert_eq!(options.bailout_sq, 4.0);
+
+    // The defaults render the plain mandelbrot set with an escape radius of 2
+    let options = small_options(64);
+    let image = build_mandelbrot_cpu(&options);
+    for i in 0..options.image_height {
+        for j in 0..options.image_width {
+            let point = Complex::new(
+                options.real_start + j as f64 * options.real_step,
+                options.i_start - i as f64 * options.i_step,
+            );
+            let expected = if point.is_stable(options.iterations) { 0 } else { u8::MAX };
+            assert_eq!(image[j + i * options.image_width], expected);
+        }
+    }
+}
+
+
+#[test]
+fn test_mandelbrot_cpu_default() {
+    let options = MandelbrotCpu::default();
+    let image = build_mandelbrot_cpu(&options);
+    assert_eq!(image.len(), options.image_width * options.image_height);
+
+    let expected_image = build_mandelbrot_cpu_simple(&options);
+    assert_images_equal(&image, &expected_image, options.image_width, options.image_height, "cpu default");
+}
+
+
+
+#[test]
+fn test_mandelbrot_cpu_broad() {
+    for real_step in [0.01, 0.02, 0.04, 0.08] {
+        for i_step in [0.01, 0.02, 0.04, 0.08] {
+            let options = MandelbrotCpu {
+                threads: 6,
+                image_width: 100,
+                image_height: 100,
+                real_start: -2.0,
+                real_step,
+                i_start: 1.0,
+                i_step,
+                iterations: 1000,
+                ..MandelbrotCpu::default()
+            };
+            let image = build_mandelbrot_cpu(&options);
+            assert_eq!(image.len(), options.image_width * options.image_height);
+
+            let expected_image = build_mandelbrot_cpu_simple(&options);
+            assert_images_equal(
+                &image,
+                &expected_image,
+                options.image_width,
+                options.image_height,
+                &format!("cpu broad real_step {} i_step {}", real_step, i_step),
+            );
+        }
+    }
+}
+
+
+#[test]
+fn test_mandelbrot_cpu_supersampled() {
+    let options = MandelbrotCpu {
+        threads: 4,
+        samples: 3,
+        ..small_options(64)
+    };
+    let image = build_mandelbrot_cpu(&options);
+    assert_eq!(image.len(), options.image_width * options.image_height);
+    // Edges get blended, but the corners are far outside the set and stay white
+    assert_eq!(image[0], u8::MAX);
+    assert!(image.iter().any(|&p| p != 0 && p != u8::MAX));
+}
+
+
+#[test]
+fn test_adaptive_supersampling() {
+    let options = MandelbrotCpu {
+        image_height: 48,
+        iterations: 100,
+        samples: 3,
+        shading: Shading::HsvCycle,
+        ..small_options(64)
+    };
+    let counts = render_iterations(&options);
+    let marked = |threshold| adaptive::edge_pixels(&counts, 64, threshold).iter().filter(|&&edge| edge).count();
+    assert!(marked(1) > marked(5), "{} <= {}", marked(1), marked(5));
+    assert!(marked(5) > marked(50), "{} <= {}", marked(5), marked(50));
+    assert!(marked(50) > 0);
+    assert_eq!(marked(u32::MAX), 0);
+
+    // Edge pixels are supersampled, everything else is a single sample
+    let threshold = 5;
+    let edges = adaptive::edge_pixels(&counts, 64, threshold);
+    let image = adaptive::build_mandelbrot_adaptive(&MandelbrotCpu {
+        supersample_threshold: Some(threshold),
+        ..options.clone()
+    });
+    let supersampled = build_mandelbrot_cpu(&options);
+    let single_sample = build_mandelbrot_cpu(&MandelbrotCpu { samples: 1, ..options.clone() });
+    for (i, &edge) in edges.iter().enumerate() {
+        let expected = if edge { &supersampled } else { &single_sample };
+        assert_eq!(image[i * 3..i * 3 + 3], expected[i * 3..i * 3 + 3], "pixel {}", i);
+    }
+}
+
+#[test]
+fn test_sample_count_map() {
+    let options = MandelbrotCpu {
+        image_height: 48,
+        iterations: 100,
+        samples: 3,
+        supersample_threshold: Some(5),
+        ..small_options(64)
+    };
+    let counts = adaptive::sample_counts(&options);
+    let edges = adaptive::edge_pixels(&render_iterations(&options), 64, 5);
+    for (&count, &edge) in counts.iter().zip(&edges) {
+        assert_eq!(count, if edge { 9 } else { 1 });
+    }
+    // The flat inside of the set and the corners far outside of it only get one sample, the
+    // edge of the set all of them
+    let count = |row: usize, column: usize| counts[row * 64 + column];
+    assert_eq!(count(24, 40), 1);
+    assert_eq!(count(0, 0), 1);
+    assert!(counts.contains(&9));
+
+    let map = adaptive::sample_count_map(&counts, 3);
+    assert_eq!(map.len(), 64 * 48);
+    assert_eq!(map[24 * 64 + 40], 28);
+    assert_eq!(*map.iter().max().unwrap(), u8::MAX);
+}
+
+#[test]
+fn test_sample_budget() {
+    let options = MandelbrotCpu {
+        image_height: 48,
+        iterations: 100,
+        shading: Shading::Hybrid,
+        ..small_options(64)
+    };
+    let pixels = 64 * 48;
+    let counts = render_iterations(&options);
+    let complexity = adaptive::complexity(&counts, 64);
+    for budget in [pixels / 2, pixels, 4 * pixels, 20 * pixels] {
+        let samples = adaptive::allocate_samples(&complexity, budget);
+        assert!(samples.iter().all(|&samples| (1..=adaptive::MAX_BUDGET_SAMPLES).contains(&samples)));
+        let total: usize = samples.iter().map(|&samples| samples * samples).sum();
+        assert!(total <= budget.max(pixels), "{} samples for a budget of {}", total, budget);
+    }
+
+    // The boundary of the set gets more samples than the flat inside of it and the far outside
+    let budgeted = MandelbrotCpu {
+        sample_budget: Some(8 * pixels),
+        ..options.clone()
+    };
+    let samples = adaptive::sample_counts(&budgeted);
+    let edges = adaptive::edge_pixels(&counts, 64, 5);
+    let boundary = samples.iter().zip(&edges).filter(|(_, &edge)| edge).map(|(&samples, _)| samples);
+    let boundary: Vec<u32> = boundary.collect();
+    assert_eq!(samples[24 * 64 + 40], 1);
+    assert_eq!(samples[0], 1);
+    assert!(boundary.iter().sum::<u32>() as f64 / boundary.len() as f64 > 4.0);
+    assert!(samples.iter().sum::<u32>() as usize <= 8 * pixels);
+
+    // A budget of one sample per pixel is a plain render
+    let single = MandelbrotCpu {
+        sample_budget: Some(pixels),
+        ..options.clone()
+    };
+    assert_eq!(adaptive::build_mandelbrot_adaptive(&single), build_mandelbrot_cpu(&options));
+    assert_ne!(adaptive::build_mandelbrot_adaptive(&budgeted), build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_downsample_filters() {
+    // The box filter is a plain average of the sub-samples inside the pixel
+    let options = MandelbrotCpu {
+        samples: 4,
+        shading: Shading::Hybrid,
+        iterations: 100,
+        ..MandelbrotCpu::default()
+    };
+    for (x, y) in [(-0.75, 0.1), (-0.1, 0.65), (0.3, 0.0)] {
+        let mut sub_samples = vec![];
+        for sy in 0..4 {
+            for sx in 0..4 {
+                let point = Complex::new(
+                    x + (sx as f64 + 0.5) / 4.0 * options.real_step,
+                    y - (sy as f64 + 0.5) / 4.0 * options.i_step,
+                );
+                sub_samples.push(options.pixel_color(&point)[0]);
+            }
+        }
+        let mut pixel = [0];
+        options.render_pixel(x, y, &mut pixel);
+        assert!(pixel[0].abs_diff(color::average_linear(&sub_samples)) <= 1, "({}, {})", x, y);
+    }
+
+    // The lanczos kernel sampled a pixel apart adds up to about 1 wherever it's sampled
+    for shift in [0.0, 0.25, 0.5] {
+        let total: f64 = (-2..=2).map(|k| DownsampleFilter::Lanczos.weight(k as f64 - shift)).sum();
+        assert!((total - 1.0).abs() < 0.05, "shift: {}", shift);
+    }
+    assert_eq!(DownsampleFilter::Lanczos.weight(0.0), 1.0);
+    assert_eq!(DownsampleFilter::Lanczos.weight(2.0), 0.0);
+
+    // The weights of the sub-samples are normalized, and spread symmetrically over the filter
+    for filter in [DownsampleFilter::Box, DownsampleFilter::Tent, DownsampleFilter::Lanczos] {
+        for samples in [1, 2, 3, 8] {
+            let sub_samples = filter.samples(samples);
+            let total: f64 = sub_samples.iter().map(|(_, weight)| weight).sum();
+            assert!((total - 1.0).abs() < 1e-12, "{:?} with {} samples", filter, samples);
+            let first = sub_samples[0];
+            let last = sub_samples[samples - 1];
+            assert!((first.0 + last.0 - 1.0).abs() < 1e-12);
+            assert!((first.1 - last.1).abs() < 1e-12);
+        }
+    }
+    assert_eq!(DownsampleFilter::Box.samples(2), [(0.25, 0.5), (0.75, 0.5)]);
+}
+
+#[test]
+fn test_mandelbrot_cpu_work_queue() {
+    // With exact steps, every thread count gives the same image as the single threaded version
+    let options = MandelbrotCpu {
+        image_width: 96,
+        image_height: 80,
+        real_step: RADIUS / 64.0,
+        i_step: RADIUS / 64.0,
+        ..MandelbrotCpu::default()
+    };
+    let expected_image = build_mandelbrot_cpu_simple(&options);
+    for threads in [1, 3, 8, 200] {
+        let image = build_mandelbrot_cpu(&MandelbrotCpu { threads, ..options.clone() });
+        assert_eq!(image, expected_image, "threads: {}", threads);
+    }
+}
+
+#[test]
+fn test_render_rows() {
+    // Every row is rendered exactly once, into the right part of the image
+    let rows = 50;
+    let row_len = 7;
+    let renders: Vec<AtomicUsize> = (0..rows).map(|_| AtomicUsize::new(0)).collect();
+    let mut image = vec![0u8; rows * row_len];
+    render_rows(&mut image, row_len, 6, false, |row, pixels| {
+        renders[row].fetch_add(1, Ordering::Relaxed);
+        pixels.fill(row as u8);
+    });
+    assert!(renders.iter().all(|count| count.load(Ordering::Relaxed) == 1));
+    for (row, pixels) in image.chunks(row_len).enumerate() {
+        assert!(pixels.iter().all(|&pixel| pixel == row as u8));
+    }
+}
+
+#[test]
+fn test_wide_image_coordinates() {
+    // A step that can't be represented exactly, so adding it up drifts away from the coordinate
+    // of the pixel
+    let options = MandelbrotCpu {
+        image_width: 8192,
+        image_height: 2,
+        real_step: 1.0 / 3000.0,
+        real_start: -2.2,
+        i_start: 0.1,
+        iterations: 50,
+        shading: Shading::HsvCycle,
+        ..MandelbrotCpu::default()
+    };
+    let mut accumulated = options.real_start;
+    for _j in 0..options.image_width - 1 {
+        accumulated += options.real_step;
+    }
+    let recomputed = options.pixel_point(0, options.image_width - 1).real;
+    assert_ne!(accumulated, recomputed);
+
+    // Every pixel is rendered at its own coordinate, no matter how far right it is
+    let image = build_mandelbrot_cpu(&options);
+    for row in 0..options.image_height {
+        for column in 0..options.image_width {
+            let index = (row * options.image_width + column) * 3;
+            assert_eq!(
+                image[index..index + 3],
+                options.pixel_color(&options.pixel_point(row, column)),
+                "({}, {})",
+                row,
+                column
+            );
+        }
+    }
+}
+
+// Reads back the .rle file {data} written by IterationCounts::encode, checking that it's
+// complete and that the runs add up to the pixels of the image
+fn decode_rle(data: &[u8]) -> Result<rle::IterationCounts, String> {
+    let body = data.strip_prefix(b"GRLE").ok_or("not an .rle file")?;
+    let mut words = body
+        .chunks(4)
+        .map(|word| <[u8; 4]>::try_from(word).map(u32::from_le_bytes));
+    let mut next = |what: &str| match words.next() {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(format!("the file ends in the middle of the {}", what)),
+    };
+    let width = next("header")? as usize;
+    let height = next("header")? as usize;
+    let iterations = next("header")?;
+
+    // The size in the header isn't trusted for preallocating, as a corrupt file could ask for
+    // any amount of memory. The counts only grow as the runs in the file are read.
+    let pixels = width
+        .checked_mul(height)
+        .ok_or_else(|| format!("the image size {}x{} is too large", width, height))?;
+    let mut counts = Vec::new();
+    while counts.len() < pixels {
+        let length = next("runs")? as usize;
+        let count = next("runs")?;
+        if length > pixels - counts.len() {
+            return Err(format!("the runs add up to more than the {} pixels of the image", pixels));
+        }
+        counts.resize(counts.len() + length, count);
+    }
+    if words.next().is_some() {
+        return Err("there is data after the last pixel".to_string());
+    }
+    Ok(rle::IterationCounts {
+        width,
+        height,
+        iterations,
+        counts,
+    })
+}
+
+#[test]
+fn test_iterations_rle() {
+    let options = MandelbrotCpu {
+        image_height: 48,
+        iterations: 100,
+        ..small_options(64)
+    };
+    let counts = rle::IterationCounts {
+        width: 64,
+        height: 48,
+        iterations: 100,
+        counts: render_iterations(&options),
+    };
+    assert_eq!(counts.counts[0], 0);
+    assert_eq!(counts.counts[24 * 64 + 40], 100);
+    let data = counts.encode();
+    assert_eq!(decode_rle(&data), Ok(counts.clone()));
+    assert!(decode_rle(&data[..data.len() - 1]).is_err());
+    assert!(decode_rle(b"GRLA").is_err());
+
+    // A corrupt header with an enormous size but no runs is an error, not an allocation
+    let mut huge = b"GRLE".to_vec();
+    for value in [u32::MAX, u32::MAX, 100] {
+        huge.extend_from_slice(&value.to_le_bytes());
+    }
+    assert!(decode_rle(&huge).is_err());
+
+    // A view inside the cardioid is one long run
+    let interior = MandelbrotCpu {
+        real_start: -0.25,
+        i_start: 0.125,
+        real_step: 1.0 / 512.0,
+        i_step: 1.0 / 512.0,
+        ..options
+    };
+    let counts = rle::IterationCounts {
+        counts: render_iterations(&interior),
+        ..counts
+    };
+    let data = counts.encode();
+    assert!(data.len() * 100 < counts.counts.len() * 4, "{} bytes", data.len());
+    assert_eq!(decode_rle(&data).unwrap(), counts);
+}
+
+#[test]
+fn test_stepping_error() {
+    // The CPU calculates every coordinate from the start instead of adding up the steps, which
+    // stays within rounding of the exact coordinate where adding up drifts away from it
+    let options = MandelbrotCpu {
+        real_start: -2.0,
+        real_step: 1.0 / 3000.0,
+        ..MandelbrotCpu::default()
+    };
+    let start = DoubleDouble::from(options.real_start);
+    let mut accumulated = options.real_start;
+    let (mut accumulated_error, mut direct_error) = (0.0f64, 0.0f64);
+    for j in 0..10000 {
+        let exact = start + DoubleDouble::from(options.real_step).mul_f64(j as f64);
+        let direct = options.pixel_point(0, j).real;
+        accumulated_error = accumulated_error.max((DoubleDouble::from(accumulated) - exact).hi.abs());
+        direct_error = direct_error.max((DoubleDouble::from(direct) - exact).hi.abs());
+        accumulated += options.real_step;
+    }
+    assert!(direct_error <= f64::EPSILON, "{}", direct_error);
+    assert!(accumulated_error > 100.0 * direct_error, "{} {}", accumulated_error, direct_error);
+}
+
+#[test]
+fn test_render_output() {
+    // One element per pixel, whatever the number of bytes per pixel, and the same bytes as the
+    // flat image when flattened
+    for shading in [Shading::Flat, Shading::HsvCycle] {
+        let options = MandelbrotCpu {
+            image_width: 40,
+            image_height: 30,
+            real_step: RADIUS / 40.0,
+            i_step: RADIUS / 40.0,
+            shading,
+            ..MandelbrotCpu::default()
+        };
+        let flat = build_mandelbrot_cpu(&options);
+        let output = RenderOutput::new(flat.clone(), options.color_type()).unwrap();
+        match (&output, shading) {
+            (RenderOutput::Gray(pixels), Shading::Flat) => assert_eq!(pixels.len(), 40 * 30),
+            (RenderOutput::Rgb(pixels), Shading::HsvCycle) => assert_eq!(pixels.len(), 40 * 30 * 3),
+            (output, shading) => panic!("{:?} gave {:?}", shading, output.color_type()),
+        }
+        assert_eq!(output.color_type(), options.color_type());
+        assert_eq!(output.as_bytes(), flat.as_slice());
+    }
+    let palette = MandelbrotCpu {
+        image_width: 4,
+        image_height: 4,
+        shading: Shading::Palette,
+        ..MandelbrotCpu::default()
+    };
+    let output = RenderOutput::new(build_mandelbrot_cpu(&palette), palette.color_type()).unwrap();
+    assert!(matches!(output, RenderOutput::Rgb(_)));
+
+    // Partial pixels and formats that are never rendered are errors
+    assert!(RenderOutput::new(vec![0; 5], ColorType::Rgb8).is_err());
+    assert!(RenderOutput::new(vec![0; 6], ColorType::La8).is_err());
+}
+
+#[test]
+fn test_output_alpha() {
+    for shading in [Shading::Flat, Shading::HsvCycle] {
+        let options = MandelbrotCpu {
+            image_width: 40,
+            image_height: 30,
+            real_step: RADIUS / 40.0,
+            i_step: RADIUS / 40.0,
+            shading,
+            ..MandelbrotCpu::default()
+        };
+        let image = build_mandelbrot_cpu(&options);
+        let inside = minibrots::membership(&options);
+        assert!(inside.contains(&true) && inside.contains(&false));
+        for region in [alpha::AlphaRegion::Interior, alpha::AlphaRegion::Exterior] {
+            let pixels = alpha::with_alpha(&image, options.bytes_per_pixel(), &inside, region);
+            assert_eq!(pixels.len(), 40 * 30);
+            for (index, pixel) in pixels.iter().enumerate() {
+                // The chosen region is transparent and the rest opaque
+                let transparent = inside[index] == (region == alpha::AlphaRegion::Interior);
+                assert_eq!(pixel[3], if transparent { 0 } else { u8::MAX }, "{:?} {}", region, index);
+                // The colors stay as they were
+                let color = &image[index * options.bytes_per_pixel()..(index + 1) * options.bytes_per_pixel()];
+                assert!(pixel[..3].iter().zip(color.iter().cycle()).all(|(a, b)| a == b), "{:?} {:?}", pixel, color);
+            }
+            let output = RenderOutput::new(pixels.concat(), ColorType::Rgba8).unwrap();
+            assert_eq!(output.color_type(), ColorType::Rgba8);
+            assert_eq!(output.as_bytes(), pixels.concat().as_slice());
+        }
+    }
+}
+
+#[test]
+fn test_seamless() {
+    let options = MandelbrotCpu {
+        image_height: 30,
+        shading: Shading::HsvCycle,
+        ..small_options(40)
+    };
+    let original = build_mandelbrot_cpu(&options);
+    let pixel = |image: &[u8], x: usize, y: usize| image[(y * 40 + x) * 3..(y * 40 + x + 1) * 3].to_vec();
+    for mode in [seamless::SeamlessMode::Mirror, seamless::SeamlessMode::CrossFade] {
+        let mut image = original.clone();
+        seamless::make_seamless(&mut image, 40, 30, 3, mode);
+        // The opposite edges match, so the tiles line up
+        for y in 0..30 {
+            assert_eq!(pixel(&image, 0, y), pixel(&image, 39, y), "{:?} row {}", mode, y);
+        }
+        for x in 0..40 {
+            assert_eq!(pixel(&image, x, 0), pixel(&image, x, 29), "{:?} column {}", mode, x);
+        }
+        // The top left stays as it was
+        assert_eq!(pixel(&image, 10, 10), pixel(&original, 10, 10), "{:?}", mode);
+    }
+
+    // Mirroring copies the top left quarter over the rest of the image
+    let mut image = original.clone();
+    seamless::make_seamless(&mut image, 40, 30, 3, seamless::SeamlessMode::Mirror);
+    assert_eq!(pixel(&image, 30, 25), pixel(&original, 9, 4));
+    // Cross-fading leaves the middle of the image alone
+    let mut image = original.clone();
+    seamless::make_seamless(&mut image, 40, 30, 3, seamless::SeamlessMode::CrossFade);
+    assert_eq!(pixel(&image, 25, 20), pixel(&original, 25, 20));
+}
+
+#[test]
+fn test_render_rows_in_bounds() {
+    // Every byte of the image is handed to render_row exactly once, as part of its own row
+    let (row_len, rows) = (7 * 3, 13);
+    let mut image = vec![0u8; row_len * rows];
+    render_rows(&mut image, row_len, 4, false, |row, pixels| {
+        assert_eq!(pixels.len(), row_len);
+        for pixel in pixels.iter_mut() {
+            assert_eq!(*pixel, 0);
+            *pixel = row as u8 + 1;
+        }
+    });
+    let expected: Vec<u8> = (0..rows).flat_map(|row| iter::repeat_n(row as u8 + 1, row_len)).collect();
+    assert_eq!(image, expected);
+}
+
+// Prints how fast an image is written split into rows and pixels with chunks_mut, like
+// render_rows hands it out, compared to indexing the image for every pixel. The chunks are in
+// bounds by construction, so there are no per-pixel checks left to skip with unchecked writes.
+// Run with cargo test --release -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_pixel_writes() {
+    let (width, height, bytes_per_pixel) = (4096, 4096, 3);
+    let measure = |name: &str, write: &dyn Fn(&mut [u8])| {
+        let mut image = vec![0u8; width * height * bytes_per_pixel];
+        let start = std::time::Instant::now();
+        write(std::hint::black_box(&mut image));
+        let elapsed = start.elapsed().as_secs_f64();
+        println!("{}: {:.0} million pixels per second", name, (width * height) as f64 / elapsed / 1e6);
+        image
+    };
+    let indexed = measure("indexed", &|image| {
+        for i in 0..height {
+            for j in 0..width {
+                for channel in 0..bytes_per_pixel {
+                    image[(j + i * width) * bytes_per_pixel + channel] = (i ^ j) as u8;
+                }
+            }
+        }
+    });
+    let chunked = measure("chunks_mut", &|image| {
+        for (i, row) in image.chunks_mut(width * bytes_per_pixel).enumerate() {
+            for (j, pixel) in row.chunks_mut(bytes_per_pixel).enumerate() {
+                pixel.fill((i ^ j) as u8);
+            }
+        }
+    });
+    assert_eq!(indexed, chunked);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "whole rows")]
+fn test_render_rows_partial_row() {
+    let mut image = vec![0u8; 10];
+    render_rows(&mut image, 4, 1, false, |_, _| {});
+}
+
+#[test]
+fn test_no_progress() {
+    let args = Args::try_parse_from(["gendelbrot", "--no-progress"]).unwrap();
+    let options = options_from_args(&args);
+    assert!(!options.progress);
+    let mut out = vec![];
+    for done in 0..=10 {
+        progress::write_progress(&mut out, options.progress, done, 10).unwrap();
+    }
+    assert!(!out.contains(&b'\r'));
+    assert!(out.is_empty());
+
+    // Shown progress is rewritten in place
+    progress::write_progress(&mut out, true, 5, 10).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "Progress: 50%  \r");
+}
+
+#[test]
+fn test_row_order() {
+    assert_eq!(RowOrder::TopDown.rows(5), [0, 1, 2, 3, 4]);
+    assert_eq!(RowOrder::CenterOut.rows(5), [2, 1, 3, 0, 4]);
+    assert_eq!(RowOrder::CenterOut.rows(4), [1, 2, 0, 3]);
+    assert!(RowOrder::CenterOut.rows(0).is_empty());
+    // Every row is rendered exactly once
+    let mut rows = RowOrder::CenterOut.rows(101);
+    assert_eq!(rows[0], 50);
+    rows.sort_unstable();
+    assert_eq!(rows, (0..101).collect::<Vec<usize>>());
+
+    // The image stays the same
+    let options = MandelbrotCpu {
+        threads: 8,
+        image_width: 40,
+        image_height: 30,
+        shading: Shading::HsvCycle,
+        ..MandelbrotCpu::default()
+    };
+    let center_out = MandelbrotCpu {
+        row_order: RowOrder::CenterOut,
+        ..options.clone()
+    };
+    assert_eq!(build_mandelbrot_cpu(&center_out), build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_reuse_previous_pan() {
+    let previous = MandelbrotCpu {
+        image_height: 48,
+        iterations: 100,
+        shading: Shading::HsvCycle,
+        ..small_options(64)
+    };
+    let previous_image = build_mandelbrot_cpu(&previous);
+    let rendered = Mutex::new(vec![]);
+    let render = |options: &MandelbrotCpu| {
+        rendered.lock().unwrap().push((options.image_width, options.image_height));
+        build_mandelbrot_cpu(options)
+    };
+
+    // Panning one pixel to the right only renders the column that scrolled in on the right
+    let next = MandelbrotCpu {
+        real_start: previous.real_start + previous.real_step,
+        ..previous.clone()
+    };
+    assert_eq!(reuse::pan_offset(&previous, &next), Some((1, 0)));
+    assert_eq!(
+        reuse::exposed_regions(64, 48, 1, 0),
+        vec![reuse::Region { column: 63, row: 0, width: 1, height: 48 }]
+    );
+    let image = reuse::build_mandelbrot_reusing(&previous, &previous_image, &next, render);
+    assert_eq!(*rendered.lock().unwrap(), vec![(1, 48)]);
+    assert_eq!(image, build_mandelbrot_cpu(&next));
+
+    // Panning diagonally renders a column and a row
+    rendered.lock().unwrap().clear();
+    let next = MandelbrotCpu {
+        real_start: previous.real_start - 2.0 * previous.real_step,
+        i_start: previous.i_start - 3.0 * previous.i_step,
+        ..previous.clone()
+    };
+    let image = reuse::build_mandelbrot_reusing(&previous, &previous_image, &next, render);
+    assert_eq!(*rendered.lock().unwrap(), vec![(2, 48), (62, 3)]);
+    assert_eq!(image, build_mandelbrot_cpu(&next));
+
+    // Half a pixel doesn't line up with the previous frame, so everything is rendered
+    rendered.lock().unwrap().clear();
+    let next = MandelbrotCpu {
+        real_start: previous.real_start + previous.real_step / 2.0,
+        ..previous.clone()
+    };
+    assert_eq!(reuse::pan_offset(&previous, &next), None);
+    let image = reuse::build_mandelbrot_reusing(&previous, &previous_image, &next, render);
+    assert_eq!(*rendered.lock().unwrap(), vec![(64, 48)]);
+    assert_eq!(image, build_mandelbrot_cpu(&next));
+
+    // Neither does a whole pixel of the inverted plane
+    let inverted = |real_start: f64| MandelbrotCpu {
+        real_start,
+        invert_plane: true,
+        ..previous.clone()
+    };
+    let next = inverted(previous.real_start + previous.real_step);
+    assert_eq!(reuse::pan_offset(&inverted(previous.real_start), &next), None);
+
+    // Or of a transformed view
+    let transformed = |real_start: f64| MandelbrotCpu {
+        real_start,
+        transform: Some(transform::Affine {
+            matrix: [1.0, 0.5, 0.0, 1.0, 0.0, 0.0],
+            center: Complex::new(-0.5, 0.0),
+        }),
+        ..previous.clone()
+    };
+    let next = transformed(previous.real_start + previous.real_step);
+    assert_eq!(reuse::pan_offset(&transformed(previous.real_start), &next), None);
+}
+
+#[test]
+fn test_pixel_offset() {
+    let options = small_options(64);
+    let image = build_mandelbrot_cpu(&options);
+
+    // Shifting a whole pixel to the right moves every column one to the left
+    let shifted = build_mandelbrot_cpu(&pixel_offset(&options, 1.0, 0.0));
+    for (row, shifted_row) in image.chunks(64).zip(shifted.chunks(64)) {
+        assert_eq!(shifted_row[..63], row[1..]);
+    }
+
+    // And shifting a whole pixel down moves every row one up
+    let shifted = build_mandelbrot_cpu(&pixel_offset(&options, 0.0, 1.0));
+    assert_eq!(shifted[..63 * 64], image[64..]);
+
+    // Half a pixel lands in between
+    let shifted = pixel_offset(&options, 0.5, -0.5);
+    assert_eq!(shifted.real_start, options.real_start + options.real_step / 2.0);
+    assert_eq!(shifted.i_start, options.i_start + options.i_step / 2.0);
+}
+
+#[test]
+fn test_sample_centers() {
+    // The view is symmetric around the real axis, and so is the set
+    let args = Args::try_parse_from(["gendelbrot", "-d", "48", "32", "--center", "-0.5", "0", "--size", "3", "2", "--shading", "hsv-cycle"]).unwrap();
+    let corners = options_from_args(&args);
+    let centers = sample_centers(&corners);
+    let mirrored = |image: &[u8]| image.chunks(48 * 3).eq(image.chunks(48 * 3).rev());
+    assert!(mirrored(&build_mandelbrot_cpu(&centers)));
+    // Sampling the corners moves the image half a pixel, which breaks the symmetry
+    assert!(!mirrored(&build_mandelbrot_cpu(&corners)));
+
+    // The center of the view is right between the middle two pixels
+    let (left, right) = (centers.pixel_point(15, 23), centers.pixel_point(16, 24));
+    assert!(((left.real + right.real) / 2.0 + 0.5).abs() < 1e-12);
+    assert!(((left.imaginary + right.imaginary) / 2.0).abs() < 1e-12);
+    // Centering twice doesn't move the view again
+    assert_eq!(sample_centers(&centers).real_start, centers.real_start);
+    // The first oversized pixel is centered in its quarter of the first pixel
+    let oversized = oversize::oversized(&centers, 2);
+    assert!((oversized.real_start - (corners.real_start + corners.real_step / 4.0)).abs() < 1e-12);
+
+    assert!(Args::try_parse_from(["gendelbrot", "--sample-corners"]).unwrap().sample_corners);
+}
+
+#[test]
+fn test_crop_to_set() {
+    let options = MandelbrotCpu {
+        image_width: 400,
+        image_height: 300,
+        real_step: 4.0 / 400.0,
+        i_step: 4.0 / 400.0,
+        real_start: -2.5,
+        i_start: 1.5,
+        iterations: 100,
+        ..MandelbrotCpu::default()
+    };
+    let cropped = crop::crop_to_set(&options).unwrap();
+    let real_end = cropped.real_start + 400.0 * cropped.real_step;
+    let i_end = cropped.i_start - 300.0 * cropped.i_step;
+    // The view got tighter, kept its shape and still contains the cardioid
+    assert!(cropped.real_step < options.real_step);
+    assert_eq!(cropped.real_step, cropped.i_step);
+    assert!(cropped.real_start < -0.75 && real_end > 0.25, "{}..{}", cropped.real_start, real_end);
+    assert!(cropped.i_start > 0.65 && i_end < -0.65, "{}..{}", cropped.i_start, i_end);
+    // It's centered on the set, which is symmetric around the real axis
+    assert!((cropped.i_start + i_end).abs() < 1e-9);
+
+    // Without anything of the set in view, there's nothing to crop to
+    let outside = MandelbrotCpu {
+        real_start: 5.0,
+        ..options.clone()
+    };
+    assert_eq!(crop::set_bounds(&outside, crop::CROP_GRID), None);
+    assert!(crop::crop_to_set(&outside).is_none());
+}
+
+#[test]
+fn test_snap_to_boundary() {
+    // A 1 x 1 view centered on 0.2, just inside the cusp of the cardioid at 0.25
+    let options = MandelbrotCpu {
+        image_width: 100,
+        image_height: 100,
+        real_step: 0.01,
+        i_step: 0.01,
+        real_start: 0.2 - 0.5,
+        i_start: 0.5,
+        ..MandelbrotCpu::default()
+    };
+    assert!(options.contains(&Complex::new(0.2, 0.0)));
+    let (boundary, step) = snap::nearest_boundary(&options, &Complex::new(0.2, 0.0)).unwrap();
+    assert_eq!(step, 0.1 / snap::SNAP_GRID as f64);
+    // The boundary point is in the set, the next point out isn't, and it's at the cusp
+    assert!(options.contains(&boundary));
+    assert!(!options.contains(&Complex::new(boundary.real + step, boundary.imaginary)));
+    assert!((boundary.real - 0.25).abs() < 0.01, "{}", boundary.real);
+    assert!(boundary.imaginary.abs() < 1e-12);
+
+    // The view keeps its size and is centered on the boundary point
+    let snapped = snap::snap_to_boundary(&options).unwrap();
+    assert_eq!((snapped.real_step, snapped.i_step), (options.real_step, options.i_step));
+    assert!((snapped.real_start + 0.5 - boundary.real).abs() < 1e-12);
+    assert!((snapped.i_start - 0.5 - boundary.imaginary).abs() < 1e-12);
+
+    // Far away from the set there is nothing to snap to
+    let outside = MandelbrotCpu {
+        real_start: 5.0,
+        ..options.clone()
+    };
+    assert!(snap::snap_to_boundary(&outside).is_none());
+}
+
+#[test]
+fn test_estimate_sufficient_iterations() {
+    // Zoomed out, everything that escapes does so quickly, however large the budget
+    let options = MandelbrotCpu {
+        iterations: 1_000_000,
+        ..small_options(256)
+    };
+    let estimate = detail::estimate_sufficient_iterations(&options, detail::DETAIL_GRID).unwrap();
+    assert!(estimate < 100, "estimate: {}", estimate);
+
+    // Near the boundary the points take a lot longer to escape
+    let zoomed = MandelbrotCpu {
+        real_start: -0.7437,
+        real_step: 1e-7,
+        i_start: 0.1318,
+        i_step: 1e-7,
+        iterations: 20_000,
+        ..options.clone()
+    };
+    let zoomed_estimate = detail::estimate_sufficient_iterations(&zoomed, detail::DETAIL_GRID).unwrap();
+    assert!(zoomed_estimate > 5 * estimate, "estimate: {}, zoomed: {}", estimate, zoomed_estimate);
+
+    // If nothing escapes there's nothing to estimate from
+    let inside = MandelbrotCpu {
+        real_start: -0.1,
+        real_step: 1e-4,
+        i_start: 0.1,
+        i_step: 1e-4,
+        iterations: 500,
+        ..options.clone()
+    };
+    assert_eq!(detail::estimate_sufficient_iterations(&inside, 8), None);
+}
+
+#[test]
+fn test_degenerate_view_hint() {
+    let options = MandelbrotCpu {
+        iterations: 200,
+        ..small_options(64)
+    };
+    // The default view has both points in and out of the set
+    let fraction = detail::in_set_fraction(&options, detail::DETAIL_GRID);
+    assert!(fraction > 0.0 && fraction < 1.0, "{}", fraction);
+    assert_eq!(detail::degenerate_view_hint(&options), None);
+
+    // Far out of the set everything escapes
+    let outside = MandelbrotCpu {
+        real_start: 10.0,
+        i_start: 10.0,
+        ..options.clone()
+    };
+    assert_eq!(detail::in_set_fraction(&outside, detail::DETAIL_GRID), 0.0);
+    assert!(detail::degenerate_view_hint(&outside).unwrap().contains("outside"));
+
+    // Deep in the main cardioid nothing does
+    let inside = MandelbrotCpu {
+        real_start: -0.1,
+        real_step: 1e-4,
+        i_start: 0.1,
+        i_step: 1e-4,
+        ..options.clone()
+    };
+    assert_eq!(detail::in_set_fraction(&inside, detail::DETAIL_GRID), 1.0);
+    assert!(detail::degenerate_view_hint(&inside).unwrap().contains("--iterations"));
+}
+
+#[test]
+fn test_iterations_map() {
+    let options = MandelbrotCpu {
+        iterations: 200,
+        ..small_options(64)
+    };
+    let expected_image = build_mandelbrot_cpu(&options);
+
+    // A uniform white map, loaded from a smaller image, reproduces the constant iteration render
+    let dir = std::env::temp_dir().join("gendelbrot_test_iterations_map");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("map.png");
+    image::save_buffer(&path, &[u8::MAX; 16 * 8], 16, 8, ColorType::L8).unwrap();
+    let map = IterationsMap::load(&path, 64, 64).unwrap();
+    let map_options = MandelbrotCpu {
+        iterations_map: Some(Arc::new(map)),
+        ..options.clone()
+    };
+    assert_eq!(build_mandelbrot_cpu(&map_options), expected_image);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // A map that is black on the left half only changes the left half
+    let values = (0..64 * 64).map(|i| if i % 64 < 32 { 0 } else { u8::MAX }).collect();
+    let map_options = MandelbrotCpu {
+        iterations_map: Some(Arc::new(IterationsMap::new(64, values))),
+        ..options.clone()
+    };
+    let image = build_mandelbrot_cpu(&map_options);
+    for (row, expected_row) in image.chunks(64).zip(expected_image.chunks(64)) {
+        assert_eq!(row[32..], expected_row[32..]);
+    }
+    assert_ne!(image, expected_image);
+    assert_eq!(map_options.iterations_at(0, 0), 1);
+    assert_eq!(map_options.iterations_at(0, 63), 200);
+
+    // Row ranges take the matching rows of the map
+    let part = row_range(&map_options, 16, 40);
+    assert_eq!(build_mandelbrot_cpu(&part), image[16 * 64..40 * 64]);
+}
+
+#[test]
+fn test_oversize_then_crop() {
+    let options = MandelbrotCpu {
+        image_height: 32,
+        iterations: 200,
+        ..small_options(48)
+    };
+    let image = build_mandelbrot_cpu(&options);
+    assert_eq!(oversize::build_mandelbrot_oversized(&options, build_mandelbrot_cpu, 1), image);
+
+    // Twice the resolution covers the same view with four times the pixels
+    let oversized = oversize::oversized(&options, 2);
+    assert_eq!((oversized.image_width, oversized.image_height), (96, 64));
+    for ((row, column), (oversized_row, oversized_column)) in [((0, 0), (0, 0)), ((32, 48), (64, 96))] {
+        let (point, oversized_point) = (options.pixel_point(row, column), oversized.pixel_point(oversized_row, oversized_column));
+        assert_eq!((point.real, point.imaginary), (oversized_point.real, oversized_point.imaginary));
+    }
+
+    // Flat shading only has black and white, the averaged edges are in between
+    let smoothed = oversize::build_mandelbrot_oversized(&options, build_mandelbrot_cpu, 2);
+    assert_eq!(smoothed.len(), image.len());
+    assert!(image.iter().all(|&value| value == 0 || value == u8::MAX));
+    assert!(smoothed.iter().any(|&value| value != 0 && value != u8::MAX));
+    let oversized_image = build_mandelbrot_cpu(&oversized);
+    assert_eq!(smoothed, oversize::box_downsample(&oversized_image, 48, 32, 1, 2));
+    assert_eq!(smoothed[0], color::average_linear(&[oversized_image[0], oversized_image[1], oversized_image[96], oversized_image[97]]));
+
+    // Every value of an iterations map covers the block of pixels of its pixel
+    let map = IterationsMap::new(2, vec![0, 1, 2, 3]);
+    assert_eq!(map.scaled(2), IterationsMap::new(4, vec![0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 3, 3, 2, 2, 3, 3]));
+}
+
+#[test]
+fn test_oversubscription_warning() {
+    assert_eq!(oversubscription_warning(4, 8), None);
+    assert_eq!(oversubscription_warning(8, 8), None);
+    assert_eq!(
+        oversubscription_warning(9, 8).as_deref(),
+        Some("--threads 9 exceeds 8 logical cores; this may reduce performance")
+    );
+    assert!(logical_cores() >= 1);
+    // Without --threads every logical core is used, which never warns
+    let args = Args::parse_from(["gendelbrot"]);
+    assert_eq!(args.threads, None);
+    assert_eq!(options_from_args(&args).threads, logical_cores());
+    assert_eq!(oversubscription_warning(logical_cores(), logical_cores()), None);
+}
+
+#[test]
+fn test_compute_only() {
+    let options = MandelbrotCpu {
+        image_height: 48,
+        ..small_options(64)
+    };
+    let rendered = std::cell::Cell::new(0);
+    let report = compute_only(&options, |options| {
+        rendered.set(rendered.get() + 1);
+        build_mandelbrot_cpu(options)
+    });
+    assert_eq!(rendered.get(), 1);
+    assert_eq!(report.pixels, 64 * 48);
+    assert!(report.elapsed > std::time::Duration::ZERO);
+    assert!(report.pixels_per_second() > 0.0);
+    assert!(report.to_string().contains("3072 pixels"));
+}
+
+#[test]
+fn test_check_pixel_limit() {
+    let options = MandelbrotCpu {
+        image_width: 1000,
+        image_height: 800,
+        ..MandelbrotCpu::default()
+    };
+    assert_eq!(check_pixel_limit(&options, 1, 800_000), Ok(()));
+    let error = check_pixel_limit(&options, 1, 799_999).unwrap_err();
+    assert!(error.contains("800000") && error.contains("--max-pixels") && error.contains("--row-range") && error.contains("--stitch"));
+    // An oversized render allocates the oversized image
+    assert!(check_pixel_limit(&options, 2, 800_000).is_err());
+    assert_eq!(check_pixel_limit(&options, 2, 3_200_000), Ok(()));
+
+    // A band of rows only allocates its own rows
+    assert_eq!(check_pixel_limit(&row_range(&options, 0, 100), 1, 100_000), Ok(()));
+    assert!(check_pixel_limit(&row_range(&options, 0, 101), 1, 100_000).is_err());
+}
+
+#[test]
+fn test_row_range_stitch() {
+    // Power of two steps keep the row offsets exact
+    let options = MandelbrotCpu {
+        threads: 3,
+        ..small_options(256)
+    };
+    let full_image = build_mandelbrot_cpu(&options);
+
+    let dir = std::env::temp_dir();
+    let top_path = dir.join("gendelbrot_rows_top.png");
+    let bottom_path = dir.join("gendelbrot_rows_bottom.png");
+    for (path, start, end) in [(&top_path, 0, 100), (&bottom_path, 100, 256)] {
+        let part_options = row_range(&options, start, end);
+        let part = build_mandelbrot_cpu(&part_options);
+        assert_eq!(part.len(), options.image_width * (end - start));
+        image::save_buffer(path, &part, 256, (end - start) as u32, ColorType::L8).unwrap();
+    }
+
+    let paths = [top_path.to_string_lossy().into_owned(), bottom_path.to_string_lossy().into_owned()];
+    let (stitched, width, height, color_type) = stitch_images(&paths).unwrap();
+    assert_eq!((width, height, color_type), (256, 256, ColorType::L8));
+    assert_eq!(stitched, full_image);
+}
+
+#[test]
+fn test_tile_index() {
+    // Three bands of 512 pixels wide parts: black and white halves, white and black
+    let dir = std::env::temp_dir();
+    let mut halves = vec![];
+    for _row in 0..100 {
+        halves.extend(std::iter::repeat_n(0, 256).chain(std::iter::repeat_n(u8::MAX, 256)));
+    }
+    let parts = [(halves, 100), (vec![u8::MAX; 512 * 41], 41), (vec![0; 512 * 60], 60)];
+    let mut paths = vec![];
+    for (number, (part, height)) in parts.iter().enumerate() {
+        let path = dir.join(format!("gendelbrot_tile_index_{}.png", number));
+        image::save_buffer(&path, part, 512, *height, ColorType::L8).unwrap();
+        paths.push(path.to_string_lossy().into_owned());
+    }
+
+    // The parts are halved to fit INDEX_WIDTH, with the bottom row of the odd part on its own
+    assert_eq!(tile_index::index_factor(512), 2);
+    let index = tile_index::build_tile_index(&paths).unwrap();
+    assert_eq!((index.width, index.height, index.color_type), (256, 50 + 21 + 30 + 2 * tile_index::DIVIDER_HEIGHT, ColorType::L8));
+    assert_eq!(index.cells, vec![0..50, 52..73, 75..105]);
+    assert_eq!(index.image.len(), index.width * index.height);
+
+    // Every cell shows its own part
+    let pixel = |row: usize, column: usize| index.image[row * index.width + column];
+    assert_eq!((pixel(49, 10), pixel(49, 200)), (0, u8::MAX));
+    assert_eq!(pixel(72, 100), u8::MAX);
+    assert_eq!(pixel(104, 100), 0);
+    // Divided by gray lines
+    assert_eq!((pixel(50, 100), pixel(74, 100)), (0x80, 0x80));
+    // And labeled with its number in white on black at the top left
+    assert_eq!((pixel(52, 0), pixel(75, 1)), (0, u8::MAX));
+
+    // Parts of different widths can't be indexed together
+    let narrow = dir.join("gendelbrot_tile_index_narrow.png");
+    image::save_buffer(&narrow, &[0; 256], 256, 1, ColorType::L8).unwrap();
+    paths.push(narrow.to_string_lossy().into_owned());
+    assert!(tile_index::build_tile_index(&paths).is_err());
+}
+
+
+#[test]
+fn test_mandelbrot_progressive() {
+    let options = MandelbrotCpu {
+        threads: 4,
+        iterations: 200,
+        ..small_options(128)
+    };
+    let mut passes = vec![];
+    let image = build_mandelbrot_progressive(&options, build_mandelbrot_cpu, |image, iterations| {
+        passes.push((image.to_vec(), iterations));
+    });
+
+    assert_eq!(passes.len(), 2);
+    assert_eq!(passes[0].1, PREVIEW_ITERATIONS);
+    assert_eq!(passes[1].1, options.iterations);
+    // The preview only has fewer iterations, so everything it marks as escaped has escaped
+    assert!(passes[0].0.iter().zip(&image).all(|(&preview, &full)| preview != u8::MAX || full == u8::MAX));
+    assert_eq!(passes[1].0, image);
+    assert_eq!(image, build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_mandelbrot_time_budget() {
+    let options = MandelbrotCpu {
+        image_width: 100,
+        image_height: 60,
+        real_step: RADIUS / 64.0,
+        i_step: RADIUS / 64.0,
+        iterations: 200,
+        ..MandelbrotCpu::default()
+    };
+
+    // Without any time, only the coarse first pass is rendered, but it still fills the image
+    let (image, (scale, iterations)) =
+        build_mandelbrot_time_budget(&options, build_mandelbrot_cpu, std::time::Duration::ZERO, |_, _, _| {});
+    assert_eq!((scale, iterations), (8, 25));
+    assert_eq!(image.len(), options.image_width * options.image_height);
+    assert!(validate::validate_image(&image, &options, 0).is_ok());
+    // Every block of 8x8 pixels has the color of its top left pixel
+    let coarse = MandelbrotCpu {
+        image_width: 13,
+        image_height: 8,
+        real_step: options.real_step * 8.0,
+        i_step: options.i_step * 8.0,
+        iterations: 25,
+        ..options.clone()
+    };
+    let coarse_image = build_mandelbrot_cpu(&coarse);
+    for i in 0..options.image_height {
+        for j in 0..options.image_width {
+            assert_eq!(image[i * options.image_width + j], coarse_image[(i / 8) * 13 + j / 8]);
+        }
+    }
+
+    // With plenty of time, every pass is rendered and the last one is the full image
+    let mut passes = vec![];
+    let (image, pass) = build_mandelbrot_time_budget(
+        &options,
+        build_mandelbrot_cpu,
+        std::time::Duration::from_secs(600),
+        |image, scale, iterations| passes.push((image.len(), scale, iterations)),
+    );
+    assert_eq!(pass, (1, 200));
+    assert_eq!(passes, [(6000, 8, 25), (6000, 4, 50), (6000, 2, 100), (6000, 1, 200)]);
+    assert_eq!(image, build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_ascii_frames() {
+    assert_eq!(ascii::to_ascii(&[0, 255, 128, 255, 255, 0], 3, 1), "@ =\n  @");
+    assert_eq!(ascii::to_ascii(&[0, 0, 0, 255, 255, 255], 2, 3), "@ ");
+
+    // The 3 x 3 default view is fitted in 40 x 12 characters that are twice as tall as wide
+    let options = MandelbrotCpu {
+        iterations: 64,
+        ..MandelbrotCpu::default()
+    };
+    let view = ascii::terminal_view(&options, 40, 12);
+    assert_eq!((view.image_width, view.image_height), (24, 12));
+    assert_eq!((view.real_step, view.i_step), (RADIUS / 24.0, RADIUS / 12.0));
+
+    let frames = || {
+        let mut frames = vec![];
+        ascii::build_ascii_frames(&options, build_mandelbrot_cpu, 40, 12, |frame, scale, iterations| {
+            frames.push((frame.to_string(), scale, iterations))
+        });
+        frames
+    };
+    let first = frames();
+    assert_eq!(first, frames());
+    let passes: Vec<(usize, u32)> = first.iter().map(|(_, scale, iterations)| (*scale, *iterations)).collect();
+    assert_eq!(passes, [(8, 20), (4, 20), (2, 32), (1, 64)]);
+    for (frame, _, _) in &first {
+        let lines: Vec<&str> = frame.lines().collect();
+        assert_eq!(lines.len(), 12);
+        assert!(lines.iter().all(|line| line.len() == 24));
+    }
+    let last = &first.last().unwrap().0;
+    assert_eq!(*last, ascii::to_ascii(&build_mandelbrot_cpu(&view), 24, 1));
+    assert!(last.contains('@') && last.contains(' '));
+}
+
+#[test]
+fn test_validate_image() {
+    let options = MandelbrotCpu {
+        threads: 3,
+        ..small_options(64)
+    };
+    let mut image = build_mandelbrot_cpu(&options);
+    assert_eq!(validate::validate_image(&image, &options, 100), Ok(0));
+
+    // Flat shading never produces gray pixels
+    image[100] = 128;
+    assert!(validate::validate_image(&image, &options, 100).is_err());
+
+    // A truncated image, e.g. from a missing thread slice
+    image[100] = 0;
+    image.truncate(64 * 60);
+    assert!(validate::validate_image(&image, &options, 100).is_err());
+}
+
+
+fn export_image(image: &[u8], width: usize, height: usize, path: &Path) {
+    use image::{ImageBuffer, RgbImage};
+    let bytes_per_pixel = image.len() / (width * height).max(1);
+    let mut img: RgbImage = ImageBuffer::new(width as u32, height as u32);
+    for (i, pixel) in img.pixels_mut().enumerate() {
+        let color = &image[i * bytes_per_pixel..(i + 1) * bytes_per_pixel];
+        *pixel = if bytes_per_pixel == 3 {
+            image::Rgb([color[0], color[1], color[2]])
+        } else {
+            image::Rgb([color[0]; 3])
+        };
+    }
+    img.save(path).unwrap();
+}
+
+// Returns the pixels where the {width} x {height} images {actual} and {expected} differ
+fn image_mismatches(actual: &[u8], expected: &[u8], width: usize, height: usize) -> Vec<usize> {
+    let bytes_per_pixel = expected.len() / (width * height).max(1);
+    let pixel = |image: &[u8], i: usize| image[i * bytes_per_pixel..(i + 1) * bytes_per_pixel].to_vec();
+    (0..width * height).filter(|&i| pixel(actual, i) != pixel(expected, i)).collect()
+}
+
+// Exports {actual}, {expected} and a diff with the {mismatches} in red to the temp directory
+// under {label}, and fails the test with {reason}
+fn fail_images(actual: &[u8], expected: &[u8], width: usize, height: usize, label: &str, mismatches: &[usize], reason: &str) -> ! {
+    let dir = std::env::temp_dir().join("gendelbrot_test_images");
+    std::fs::create_dir_all(&dir).unwrap();
+    let name: String = label.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    let mut diff = vec![u8::MAX; width * height * 3];
+    for &i in mismatches {
+        diff[i * 3..i * 3 + 3].copy_from_slice(&[u8::MAX, 0, 0]);
+    }
+    export_image(actual, width, height, &dir.join(format!("{}_actual.png", name)));
+    export_image(expected, width, height, &dir.join(format!("{}_expected.png", name)));
+    export_image(&diff, width, height, &dir.join(format!("{}_diff.png", name)));
+    panic!("{}: {}. The images are in {:?}", label, reason, dir);
+}
+
+// Checks that the {width} x {height} images {actual} and {expected} are the same. If they
+// aren't, both images and a diff with the differing pixels in red are exported to the temp
+// directory under {label}, and the test fails with the number of differing pixels and the first
+// one of them.
+fn assert_images_equal(actual: &[u8], expected: &[u8], width: usize, height: usize, label: &str) {
+    assert_eq!(actual.len(), expected.len(), "{}: the images have different sizes", label);
+    let mismatches = image_mismatches(actual, expected, width, height);
+    if let Some(&first) = mismatches.first() {
+        let reason = format!(
+            "{} of {} pixels differ, the first at column {}, row {}",
+            mismatches.len(),
+            width * height,
+            first % width,
+            first / width
+        );
+        fail_images(actual, expected, width, height, label, &mismatches, &reason);
+    }
+}
+
+// Compares the {width} x {height} images {actual} and {expected}, allowing at most
+// {max_diff_pixels} differing pixels, all of which have to be within {max_boundary_band} pixels
+// of an edge in {expected}. Returns the number of differing pixels, or what's wrong.
+//
+// Engines legitimately disagree on points right on the edge of the set: one that is just about
+// to escape after the last iteration can end up on either side of the bailout radius depending on
+// how the engine rounds, e.g. when the GPU compiler fuses a multiply and an add into one fma, or
+// the GPU calculates in f32. That only ever moves the edge by a pixel, so a band of 1 pixel and a
+// tenth of a percent of the pixels is plenty. Anything farther from an edge is a real bug, like a
+// shifted row or a missing tile.
+fn compare_images_with_tolerance(
+    actual: &[u8],
+    expected: &[u8],
+    width: usize,
+    height: usize,
+    max_diff_pixels: usize,
+    max_boundary_band: usize,
+) -> Result<usize, String> {
+    if actual.len() != expected.len() {
+        return Err(format!("the images are {} and {} bytes", actual.len(), expected.len()));
+    }
+    let mismatches = image_mismatches(actual, expected, width, height);
+    if mismatches.len() > max_diff_pixels {
+        return Err(format!("{} pixels differ, at most {} may", mismatches.len(), max_diff_pixels));
+    }
+    let bytes_per_pixel = expected.len() / (width * height).max(1);
+    let pixel = |row: usize, column: usize| {
+        let i = (row * width + column) * bytes_per_pixel;
+        &expected[i..i + bytes_per_pixel]
+    };
+    for &i in &mismatches {
+        let (row, column) = (i / width, i % width);
+        let rows = row.saturating_sub(max_boundary_band)..(row + max_boundary_band + 1).min(height);
+        let near_edge = rows.flat_map(|r| {
+            (column.saturating_sub(max_boundary_band)..(column + max_boundary_band + 1).min(width)).map(move |c| (r, c))
+        })
+        .any(|(r, c)| pixel(r, c) != pixel(row, column));
+        if !near_edge {
+            return Err(format!(
+                "the pixel at column {}, row {} differs more than {} pixels away from any edge",
+                column, row, max_boundary_band
+            ));
+        }
+    }
+    Ok(mismatches.len())
+}
+
+// Same as assert_images_equal, but allows the differences of compare_images_with_tolerance
+fn assert_images_similar(
+    actual: &[u8],
+    expected: &[u8],
+    width: usize,
+    height: usize,
+    label: &str,
+    max_diff_pixels: usize,
+    max_boundary_band: usize,
+) {
+    if let Err(reason) = compare_images_with_tolerance(actual, expected, width, height, max_diff_pixels, max_boundary_band) {
+        let mismatches = image_mismatches(actual, expected, width, height);
+        fail_images(actual, expected, width, height, label, &mismatches, &reason);
+    }
+}
+
+#[test]
+fn test_compare_images_with_tolerance() {
+    // A black square in a white image
+    let mut expected = vec![u8::MAX; 20 * 20];
+    for row in 5..15 {
+        expected[row * 20 + 5..row * 20 + 15].fill(0);
+    }
+    assert_eq!(compare_images_with_tolerance(&expected, &expected, 20, 20, 0, 0), Ok(0));
+
+    // Moving the edge by a pixel is fine, as long as not too many pixels moved
+    let mut actual = expected.clone();
+    actual[5 * 20 + 4] = 0;
+    actual[10 * 20 + 14] = u8::MAX;
+    assert_eq!(compare_images_with_tolerance(&actual, &expected, 20, 20, 2, 1), Ok(2));
+    assert!(compare_images_with_tolerance(&actual, &expected, 20, 20, 1, 1).is_err());
+    assert!(compare_images_with_tolerance(&actual, &expected, 20, 20, 2, 0).is_err());
+
+    // A pixel far from any edge is a real difference
+    let mut actual = expected.clone();
+    actual[10 * 20 + 10] = u8::MAX;
+    assert!(compare_images_with_tolerance(&actual, &expected, 20, 20, 10, 1).is_err());
+    assert!(compare_images_with_tolerance(&actual[1..], &expected, 20, 20, 10, 1).is_err());
+}
+
+#[test]
+fn test_julia() {
+    // The julia set of 0 is the unit disk
+    let options = MandelbrotCpu {
+        fractal: FractalKind::Julia { real: 0.0, imaginary: 0.0 },
+        iterations: 100,
+        ..MandelbrotCpu::default()
+    };
+    assert!(options.contains(&Complex::new(0.7, -0.7)));
+    assert!(!options.contains(&Complex::new(0.72, -0.72)));
+    assert_eq!(options.pixel_color(&Complex::new(0.0, 0.99)), [0; 3]);
+    assert_eq!(options.pixel_color(&Complex::new(-1.01, 0.0)), [u8::MAX; 3]);
+}
+
+#[test]
+fn test_mandel_julia() {
+    let options = MandelbrotCpu {
+        image_height: 48,
+        i_start: 48.0 / 2.0 * RADIUS / 64.0,
+        ..small_options(64)
+    };
+    let marker = Rgb([255, 0, 0]);
+    let image = mandel_julia::build_mandel_julia(&options, build_mandelbrot_cpu, 0.25, 0.5, marker);
+    let width = 2 * 64 + mandel_julia::DIVIDER_WIDTH;
+    assert_eq!(image.len(), width * 48);
+
+    // The marker is drawn over the point in the left panel
+    let (x, y) = mandel_julia::point_pixel(&options, 0.25, 0.5);
+    assert_eq!((x, y), (48, 13));
+    assert_eq!(image[y as usize * width + x as usize], marker.luma());
+
+    // The right panel is the julia set of the point, after the divider
+    let julia = build_mandelbrot_cpu(&mandel_julia::julia_options(&options, 0.25, 0.5));
+    for row in 0..48 {
+        assert_eq!(image[row * width + 64 + mandel_julia::DIVIDER_WIDTH..(row + 1) * width], julia[row * 64..(row + 1) * 64]);
+    }
+    assert_ne!(julia, build_mandelbrot_cpu(&options));
+}
+
+
+// ==================================================
+// Command line tests
+// ==================================================
+
+#[test]
+fn test_completions() {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        let mut script = vec![];
+        write_completions(shell, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("iterations"), "{:?} completions are missing arguments", shell);
+    }
+}
+
+#[test]
+fn test_orbit_cloud() {
+    // The orbit of a point is what calling iterate over and over gives, up to escaping
+    let point = Complex::new(0.5, 0.5);
+    let orbit = orbits::orbit(&point, None, 1000, BAILOUT_SQ);
+    let mut z = point.clone();
+    for (step, recorded) in orbit.iter().enumerate() {
+        assert_eq!((recorded.real, recorded.imaginary), (z.real, z.imaginary), "step {}", step);
+        z.iterate(&point);
+    }
+    let escape = point.escape_info(1000, BAILOUT_SQ);
+    assert!(escape.escaped);
+    assert_eq!(orbit.len() as u32, escape.iterations + 1);
+    let last = orbit.last().unwrap();
+    assert_eq!((last.real, last.imaginary), (escape.final_re, escape.final_im));
+    // Points in the set go on for every iteration
+    assert_eq!(orbits::orbit(&Complex::new(-0.1, 0.1), None, 50, BAILOUT_SQ).len(), 51);
+
+    let options = MandelbrotCpu {
+        image_height: 6,
+        iterations: 20,
+        ..small_options(8)
+    };
+    let mut csv = vec![];
+    let written = orbits::write_orbit_cloud(&options, 5, usize::MAX, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "column,row,step,re,im");
+    assert_eq!(lines.len(), written + 1);
+    // Every 5th pixel starts an orbit, the 10th pixel is the third one, at column 2 of row 1
+    let starts: Vec<&str> = lines.iter().filter(|line| line.split(',').nth(2) == Some("0")).copied().collect();
+    assert_eq!(starts.len(), (8 * 6 + 4) / 5);
+    let tenth = options.pixel_point(1, 2);
+    assert_eq!(starts[2], format!("2,1,0,{},{}", tenth.real, tenth.imaginary));
+
+    // The points stop at the limit
+    let mut csv = vec![];
+    assert_eq!(orbits::write_orbit_cloud(&options, 1, 30, &mut csv).unwrap(), 30);
+    assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 31);
+}
+
+#[test]
+fn test_buddhabrot() {
+    // An odd height puts the real axis in the middle of the middle row
+    let options = MandelbrotCpu {
+        threads: 4,
+        image_width: 40,
+        image_height: 33,
+        real_step: RADIUS / 40.0,
+        i_step: RADIUS / 33.0,
+        iterations: 200,
+        samples: 2,
+        ..MandelbrotCpu::default()
+    };
+    let counts = buddhabrot::density(&options, 0);
+    assert_eq!(counts.len(), 40 * 33);
+    assert!(counts.iter().any(|&count| count > 0));
+    // The orbit of the conjugate of a point is the conjugate of its orbit
+    for (row, mirrored) in counts.chunks(40).zip(counts.chunks(40).rev()) {
+        assert_eq!(row, mirrored);
+    }
+    // The starting points are the same with every number of threads
+    let single = MandelbrotCpu {
+        threads: 1,
+        ..options.clone()
+    };
+    assert_eq!(buddhabrot::density(&single, 0), counts);
+
+    // A band only keeps the orbits of the points that escape within it
+    let band = buddhabrot::density(&options, 50);
+    assert!(band.iter().zip(&counts).all(|(band, all)| band <= all));
+    assert!(band.iter().sum::<u32>() < counts.iter().sum::<u32>());
+
+    let image = buddhabrot::density_image(&counts);
+    assert_eq!(*image.iter().max().unwrap(), u8::MAX);
+    assert_eq!(image, buddhabrot::build_buddhabrot(&options, 0));
+}
+
+#[test]
+fn test_dump_args() {
+    let args = Args::try_parse_from(["gendelbrot", "--iterations", "200"]).unwrap();
+    let json: serde_json::Value = serde_json::to_value(&args).unwrap();
+    assert_eq!(json["iterations"], 200);
+    // Without --threads the number of logical cores is used, whatever machine the args end up on
+    assert_eq!(json["threads"], serde_json::Value::Null);
+    assert_eq!(json["shading"], "flat");
+}
+
+// Unpacks the bits from pack_mask into a {width} x {height} grayscale image of black and white
+// pixels
+fn unpack_mask(packed: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let row_len = width.div_ceil(8);
+    let mut image = Vec::with_capacity(width * height);
+    for row in packed.chunks(row_len.max(1)).take(height) {
+        for column in 0..width {
+            let set = row[column / 8] & (0x80 >> (column % 8)) != 0;
+            image.push(if set { 0 } else { u8::MAX });
+        }
+    }
+    image
+}
+
+#[test]
+fn test_pbm_mask() {
+    // A width that isn't a multiple of 8, so the rows are padded
+    let options = MandelbrotCpu {
+        image_width: 37,
+        image_height: 20,
+        real_step: RADIUS / 37.0,
+        i_step: RADIUS / 20.0,
+        ..MandelbrotCpu::default()
+    };
+    let image = build_mandelbrot_cpu(&options);
+    let packed = bitmap::pack_mask(&image, 37);
+    assert_eq!(packed.len(), 5 * 20);
+    assert_eq!(unpack_mask(&packed, 37, 20), image);
+
+    let mut pbm = vec![];
+    bitmap::write_pbm(&mut pbm, &image, 37, 20).unwrap();
+    assert!(pbm.starts_with(b"P4\n37 20\n"));
+    let decoded = image::load_from_memory(&pbm).unwrap().into_luma8();
+    assert_eq!(decoded.dimensions(), (37, 20));
+    assert_eq!(decoded.into_raw(), image);
+}
+
+#[test]
+fn test_write_output_error() {
+    let options = MandelbrotCpu {
+        image_width: 16,
+        image_height: 8,
+        ..MandelbrotCpu::default()
+    };
+    let image = RenderOutput::Gray(vec![0; 16 * 8]);
+    let directory = std::env::temp_dir().join("gendelbrot_test_write_output_missing");
+    let _ = std::fs::remove_dir_all(&directory);
+    // Every way of writing reports a directory that doesn't exist instead of panicking
+    for (name, params) in [("image.png", None), ("image.png", Some("{}")), ("image.pbm", None)] {
+        let path = directory.join(name);
+        match write_output(&path, &image, &options, params, &EncoderRegistry::default()) {
+            Err(RenderError::Io { path: error_path, .. }) => assert_eq!(error_path, path),
+            result => panic!("writing {:?} gave {:?}", path, result),
+        }
+    }
+
+    let path = std::env::temp_dir().join("gendelbrot_test_write_output.png");
+    let written = write_output(&path, &image, &options, None, &EncoderRegistry::default()).unwrap();
+    assert_eq!(written, dunce::canonicalize(&path).unwrap());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_encoder_registry() {
+    // Counts the images it's asked to write instead of writing them
+    struct DummyEncoder(std::rc::Rc<std::cell::Cell<usize>>);
+    impl encoder::OutputEncoder for DummyEncoder {
+        fn extensions(&self) -> &[&str] {
+            &["dummy", "png"]
+        }
+        fn encode(&self, _path: &Path, pixels: &[u8], width: usize, height: usize, _color_type: ColorType) -> Result<(), RenderError> {
+            assert_eq!(pixels.len(), width * height);
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    let defaults = EncoderRegistry::default();
+    assert!(defaults.encoder_for(Path::new("image.PNG")).is_some());
+    assert_eq!(defaults.encoder_for(Path::new("image.pbm")).unwrap().extensions(), ["pbm"]);
+    assert!(defaults.encoder_for(Path::new("image.dummy")).is_none());
+    let path = std::env::temp_dir().join("gendelbrot_test_encoder_registry.dummy");
+    match defaults.encode(&path, &[0; 4], 2, 2, ColorType::L8) {
+        Err(RenderError::Encode { cause, .. }) => assert!(cause.contains("pbm"), "{}", cause),
+        result => panic!("encoding a .dummy file gave {:?}", result),
+    }
+
+    // A registered encoder gets its own extension and replaces the default one for the others
+    let encoded = std::rc::Rc::new(std::cell::Cell::new(0));
+    let mut registry = EncoderRegistry::default();
+    registry.register(DummyEncoder(encoded.clone()));
+    registry.encode(&path, &[0; 4], 2, 2, ColorType::L8).unwrap();
+    registry.encode(&path.with_extension("PNG"), &[0; 4], 2, 2, ColorType::L8).unwrap();
+    assert_eq!(encoded.get(), 2);
+    assert!(!path.exists() && !path.with_extension("PNG").exists());
+    assert_eq!(registry.encoder_for(Path::new("image.pbm")).unwrap().extensions(), ["pbm"]);
+}
+
+// Reads the channels written by write_exr back, returning them with the width and height
+fn read_exr(path: &Path) -> Result<(Vec<f32>, usize, usize), String> {
+    let image = image::open(path)
+        .map_err(|error| format!("Couldn't read {:?}: {}", path, error))?
+        .into_rgb32f();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    Ok((image.into_raw(), width, height))
+}
+
+#[test]
+fn test_escape_channels() {
+    let options = MandelbrotCpu {
+        image_height: 48,
+        ..small_options(64)
+    };
+    let channels = channels::render_channels(&options);
+    assert_eq!(channels.len(), 64 * 48 * 3);
+    for (index, pixel) in channels.chunks(3).enumerate() {
+        let point = options.pixel_point(index / 64, index % 64);
+        match point.escape_point(None, options.iterations, options.bailout_sq) {
+            None => assert_eq!(pixel, channels::INTERIOR),
+            Some((i, escaped)) => {
+                assert_eq!(pixel[0], i as f32 / options.iterations as f32);
+                assert!(pixel[0] < 1.0);
+                assert!(pixel[1] as f64 >= options.bailout_sq.sqrt());
+                assert_eq!(pixel[1], escaped.abs() as f32);
+                assert!((0.0..1.0).contains(&pixel[2]));
+            }
+        }
+    }
+
+    // The pixels show the inverted or transformed plane like in the rendered image, as do the
+    // iteration counts
+    let inverted = MandelbrotCpu {
+        invert_plane: true,
+        ..options.clone()
+    };
+    let transformed = MandelbrotCpu {
+        transform: Some(transform::Affine {
+            matrix: [0.0, -1.0, 1.0, 0.0, 0.0, 0.0],
+            center: Complex::new(-0.5, 0.0),
+        }),
+        ..options.clone()
+    };
+    for view in [inverted, transformed] {
+        let image = build_mandelbrot_cpu(&view);
+        let view_channels = channels::render_channels(&view);
+        let counts = render_iterations(&view);
+        assert_ne!(view_channels, channels);
+        for (index, pixel) in view_channels.chunks(3).enumerate() {
+            assert_eq!(pixel == channels::INTERIOR, image[index] == 0, "pixel {}", index);
+            assert_eq!(counts[index] == view.iterations, image[index] == 0, "pixel {}", index);
+        }
+    }
+
+    // The channels survive the EXR file exactly
+    let path = std::env::temp_dir().join("gendelbrot_test_channels.exr");
+    channels::write_exr(&path, &channels, 64, 48).unwrap();
+    assert_eq!(read_exr(&path), Ok((channels, 64, 48)));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_params_in_image() {
+    let args = Args::try_parse_from(["gendelbrot", "--iterations", "200", "--shading", "hsv-cycle"]).unwrap();
+    let json = serde_json::to_string(&args).unwrap();
+    let options = MandelbrotCpu {
+        image_width: 32,
+        image_height: 24,
+        shading: Shading::HsvCycle,
+        ..MandelbrotCpu::default()
+    };
+    let image = build_mandelbrot_cpu(&options);
+
+    let path = std::env::temp_dir().join("gendelbrot_test_params.png");
+    params::save_png_with_params(&path, &image, 32, 24, options.color_type(), &json).unwrap();
+    assert_eq!(params::read_params(&path), Ok(json));
+    // It is still a normal image
+    assert_eq!(image::open(&path).unwrap().into_rgb8().into_raw(), image);
+
+    // Images saved without parameters don't have any
+    image::save_buffer(&path, &image, 32, 24, ColorType::Rgb8).unwrap();
+    assert!(params::read_params(&path).is_err());
+}
+
+#[test]
+fn test_from_image() {
+    let args = Args::try_parse_from([
+        "gendelbrot", "-d", "48", "32", "--iterations", "200", "--center", "-0.75", "0.1", "--size", "0.5", "0.25",
+        "--shading", "hsv-cycle", "--repeat", "3", "--save-params-with-image",
+    ])
+    .unwrap();
+    let json = serde_json::to_string(&args).unwrap();
+    let options = options_from_args(&args);
+    let image = build_mandelbrot_cpu(&options);
+    let path = std::env::temp_dir().join("gendelbrot_test_from_image.png");
+    params::save_png_with_params(&path, &image, 48, 32, options.color_type(), &json).unwrap();
+
+    // Rendering from the image gives the same arguments and the same image
+    let command_line = |extra: &[&str]| {
+        let mut command_line: Vec<std::ffi::OsString> = vec!["gendelbrot".into(), "--from-image".into(), path.clone().into()];
+        command_line.extend(extra.iter().map(Into::into));
+        command_line
+    };
+    let again = args_from_image(&path, &command_line(&[])).unwrap();
+    assert_eq!(serde_json::to_string(&again).unwrap(), json);
+    assert_eq!(build_mandelbrot_cpu(&options_from_args(&again)), image);
+
+    // Arguments on the command line override the embedded ones, except for the output file
+    let tweaked = args_from_image(&path, &command_line(&["--iterations", "500", "--center", "-0.7", "0.2"])).unwrap();
+    assert_eq!(tweaked.iterations, 500);
+    assert_eq!((tweaked.center[0].value.hi, tweaked.center[1].value.hi), (-0.7, 0.2));
+    assert_eq!((tweaked.size[0], tweaked.size[1], tweaked.repeat), (0.5, 0.25, 3.0));
+    assert_eq!(tweaked.shading, Shading::HsvCycle);
+    assert_eq!(tweaked.file, IMAGE_NAME);
+
+    // Images without parameters can't be rendered from
+    image::save_buffer(&path, &image, 48, 32, ColorType::Rgb8).unwrap();
+    assert!(args_from_image(&path, &command_line(&[])).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_frame_cache() {
+    let directory = std::env::temp_dir().join("gendelbrot_test_frame_cache");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache = frame_cache::FrameCache::new(&directory.join("cache")).unwrap();
+    let frame = |file: &str, iterations: &str, extra: &[&str]| {
+        let path = directory.join(file);
+        let mut arguments = vec!["gendelbrot", "-d", "32", "24", "--iterations", iterations, "--file", path.to_str().unwrap()];
+        arguments.extend_from_slice(extra);
+        (Args::try_parse_from(arguments).unwrap(), path)
+    };
+
+    // Two frames with the same parameters are rendered once, even though they're outputted to
+    // different files with a different number of threads, and a frame with other parameters is
+    // rendered again
+    let mut computations = 0;
+    let frames: [(&str, &str, &[&str]); 3] = [
+        ("frame_0.png", "100", &["--threads", "1"]),
+        ("frame_1.png", "100", &["--threads", "3", "--no-progress", "--dump-timings-json", "timings.json"]),
+        ("frame_2.png", "200", &[]),
+    ];
+    for (file, iterations, extra) in frames {
+        let (args, path) = frame(file, iterations, extra);
+        let hash = frame_cache::params_hash(&serde_json::to_string(&args).unwrap()).unwrap();
+        if !cache.restore(hash, &path).unwrap() {
+            computations += 1;
+            let options = options_from_args(&args);
+            let image = RenderOutput::new(build_mandelbrot_cpu(&options), options.color_type()).unwrap();
+            let written = write_output(&path, &image, &options, None, &EncoderRegistry::default()).unwrap();
+            cache.store(hash, &written).unwrap();
+        }
+    }
+    assert_eq!(computations, 2);
+    let read = |file: &str| std::fs::read(directory.join(file)).unwrap();
+    assert_eq!(read("frame_0.png"), read("frame_1.png"));
+    assert_ne!(read("frame_0.png"), read("frame_2.png"));
+
+    // The output file isn't part of the parameters, but the format is
+    let hash = |file: &str| frame_cache::params_hash(&serde_json::to_string(&frame(file, "100", &[]).0).unwrap()).unwrap();
+    assert_eq!(hash("a.png"), hash("b.png"));
+    assert!(!cache.restore(hash("a.png"), &directory.join("frame_0.bmp")).unwrap());
+    assert!(frame_cache::params_hash("[]").is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_dump_coords() {
+    let options = MandelbrotCpu {
+        image_width: 4,
+        image_height: 3,
+        real_start: -2.0,
+        real_step: 0.5,
+        i_start: 1.0,
+        i_step: 0.25,
+        ..MandelbrotCpu::default()
+    };
+    let mut out = vec![];
+    write_coordinates(&options, &mut out).unwrap();
+    let csv = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 1 + 4 * 3);
+    assert_eq!(lines[0], "column,row,re,im");
+    // The corners, x grows to the right and y shrinks downwards
+    assert_eq!(lines[1], "0,0,-2,1");
+    assert_eq!(lines[4], "3,0,-0.5,1");
+    assert_eq!(lines[9], "0,2,-2,0.5");
+    assert_eq!(lines[12], "3,2,-0.5,0.5");
+}
+
+#[test]
+fn test_find_minibrots() {
+    // Three blobs in a 12 x 8 image: a plus, a diagonal pair that isn't connected and a strip
+    // along the bottom edge
+    let image = [
+        "............",
+        "..#.........",
+        ".###.....#..",
+        "..#.....#...",
+        "............",
+        "............",
+        "............",
+        "...#####....",
+    ];
+    let inside: Vec<bool> = image.iter().flat_map(|row| row.chars().map(|pixel| pixel == '#')).collect();
+    let components = minibrots::components(&inside, 12);
+    let summary: Vec<(usize, f64, f64, usize, usize, bool)> = components
+        .iter()
+        .map(|c| (c.pixels, c.column, c.row, c.width, c.height, c.touches_edge))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (5, 2.0, 2.0, 3, 3, false),
+            (1, 9.0, 2.0, 1, 1, false),
+            (1, 8.0, 3.0, 1, 1, false),
+            (5, 5.0, 7.0, 5, 1, true),
+        ]
+    );
+
+    // A view with no copies of the set in it
+    let options = MandelbrotCpu {
+        image_width: 16,
+        image_height: 16,
+        real_start: 1.0,
+        real_step: 0.01,
+        ..MandelbrotCpu::default()
+    };
+    assert!(minibrots::find_minibrots(&options, 100).is_empty());
+}
+
+#[test]
+fn test_iterations_histogram() {
+    let options = MandelbrotCpu {
+        image_height: 32,
+        iterations: 50,
+        ..small_options(48)
+    };
+    let histogram = iteration_histogram(&render_iterations(&options));
+    // Every pixel is counted once, and none of them take more than the iteration count
+    assert_eq!(histogram.values().sum::<usize>(), 48 * 32);
+    assert!(*histogram.keys().max().unwrap() <= options.iterations);
+    assert!(histogram.len() > 2);
+
+    let mut out = vec![];
+    write_iteration_histogram(&histogram, &mut out).unwrap();
+    let csv = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "count,num_pixels");
+    assert_eq!(lines.len(), 1 + histogram.len());
+    let (count, pixels) = histogram.iter().next().unwrap();
+    assert_eq!(lines[1], format!("{},{}", count, pixels));
+}
+
+#[test]
+fn test_invalid_view() {
+    let parse = |flag: &str, x: &str, y: &str| Args::try_parse_from(["gendelbrot", flag, x, y]);
+    for size in ["0", "-3", "NaN", "inf", "-inf", "1e400"] {
+        assert!(parse("--size", size, "3").is_err(), "size: {}", size);
+        assert!(parse("--size", "3", size).is_err(), "size: {}", size);
+    }
+    for center in ["NaN", "inf", "-inf", "1e400"] {
+        assert!(parse("--center", center, "0").is_err(), "center: {}", center);
+        assert!(parse("--center", "0", center).is_err(), "center: {}", center);
+    }
+    let error = parse("--size", "0", "3").unwrap_err();
+    assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation);
+    assert!(error.to_string().contains("positive finite number"));
+
+    // Negative centers and small sizes are fine
+    let args = parse("--center", "-1.5", "-0.25").unwrap();
+    assert_eq!((args.center[0].value.hi, args.center[1].value.hi), (-1.5, -0.25));
+    let args = parse("--size", "1e-20", "0.5").unwrap();
+    assert_eq!(args.size, [1e-20, 0.5]);
+}
+
+
+// ==================================================
+// Color tests
+// ==================================================
+
+#[test]
+fn test_average_linear_edge() {
+    // A pixel that is half black and half white
+    let samples = [0, u8::MAX];
+    let naive = ((samples[0] as u16 + samples[1] as u16) / 2) as u8;
+    let linear = color::average_linear(&samples);
+    assert_eq!(naive, 127);
+    // sRGB encoding of 0.5 linear light
+    assert_eq!(linear, 188);
+
+    // Averaging a single color is a no-op
+    for value in [0, 1, 64, 128, 200, u8::MAX] {
+        assert_eq!(color::average_linear(&[value, value, value]), value);
+    }
+}
+
+
+#[test]
+fn test_hybrid_shading() {
+    let options = MandelbrotCpu {
+        iterations: 200,
+        shading: Shading::Hybrid,
+        ..MandelbrotCpu::default()
+    };
+    // Points inside the set stay black
+    assert_eq!(options.pixel_color(&Complex::new(0.0, 0.0))[0], 0);
+    assert_eq!(options.pixel_color(&Complex::new(-1.0, 0.1))[0], 0);
+
+    // Just outside of the cusp of the cardioid is brighter than far away from the set
+    let near = options.pixel_color(&Complex::new(0.26, 0.0))[0];
+    let medium = options.pixel_color(&Complex::new(0.3, 0.0))[0];
+    let far = options.pixel_color(&Complex::new(1.5, 1.5))[0];
+    assert!(near > medium, "{} <= {}", near, medium);
+    assert!(medium > far, "{} <= {}", medium, far);
+}
+
+#[test]
+fn test_smooth_iteration() {
+    // The base of the usual iteration is 2
+    let smooth = smooth_iteration(7, 300.0, 2);
+    assert!((smooth - (8.0 - 300f64.ln().ln() / std::f64::consts::LN_2)).abs() < 1e-12, "{}", smooth);
+
+    // The smooth count of the orbit of x -> x³ escaping past 100, along a line of starting points
+    // that escape after fewer and fewer iterations
+    let counts = |degree| {
+        (0..400)
+            .map(|step| {
+                let mut x = 1.5 + step as f64 * 0.01;
+                let mut iteration = 0;
+                while x <= 100.0 {
+                    x = x * x * x;
+                    iteration += 1;
+                }
+                smooth_iteration(iteration, x, degree)
+            })
+            .collect::<Vec<f64>>()
+    };
+    let max_jump = |counts: Vec<f64>| counts.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f64::max);
+    // Is continuous with the degree of the iteration as the base
+    let smooth = max_jump(counts(3));
+    assert!(smooth < 0.02, "{}", smooth);
+    // And jumps where the escape iteration changes with the base of the usual iteration
+    let banded = max_jump(counts(2));
+    assert!(banded > 0.5, "{}", banded);
+}
+
+#[test]
+fn test_smooth_iter_clamp() {
+    // The brightest escaped pixel right next to a pixel of the set, around the cusp of the
+    // cardioid where points escape slowly
+    let halo = |smooth_iter_clamp| {
+        let options = MandelbrotCpu {
+            image_width: 64,
+            image_height: 64,
+            real_step: 1.0 / 8192.0,
+            i_step: 1.0 / 8192.0,
+            real_start: 0.255,
+            i_start: 1.0 / 256.0,
+            iterations: 30,
+            shading: Shading::HsvCycle,
+            smooth_iter_clamp,
+            ..MandelbrotCpu::default()
+        };
+        let mut max_jump = 0;
+        for row in 0..64 {
+            for column in 0..63 {
+                let left = options.pixel_point(row, column);
+                let right = options.pixel_point(row, column + 1);
+                if options.contains(&left) != options.contains(&right) {
+                    let escaped = if options.contains(&left) { right } else { left };
+                    max_jump = max_jump.max(*options.pixel_color(&escaped).iter().max().unwrap());
+                }
+            }
+        }
+        max_jump
+    };
+    let without = halo(0);
+    let with = halo(10);
+    assert!(with < without, "{} >= {}", with, without);
+
+    // Pixels escaping on the last iteration fade out completely, earlier ones are left alone
+    assert_eq!(color::fade_to_interior([200; 3], 30.0, 30, 10), [0; 3]);
+    assert_eq!(color::fade_to_interior([200; 3], 25.0, 30, 10), [100; 3]);
+    assert_eq!(color::fade_to_interior([200; 3], 12.0, 30, 10), [200; 3]);
+    assert_eq!(color::fade_to_interior([200; 3], 30.0, 30, 0), [200; 3]);
+}
+
+
+#[test]
+fn test_hsv_to_rgb() {
+    assert_eq!(color::hsv_to_rgb(0.0, 1.0, 1.0), [255, 0, 0]);
+    assert_eq!(color::hsv_to_rgb(120.0, 1.0, 1.0), [0, 255, 0]);
+    assert_eq!(color::hsv_to_rgb(240.0, 1.0, 1.0), [0, 0, 255]);
+    assert_eq!(color::hsv_to_rgb(360.0, 1.0, 1.0), [255, 0, 0]);
+    assert_eq!(color::hsv_to_rgb(60.0, 0.0, 0.5), [128, 128, 128]);
+}
+
+#[test]
+fn test_engine() {
+    // auto only uses the GPU if there is a usable one
+    assert_eq!(resolve_engine(Engine::Auto, || false), Engine::Cpu);
+    assert_eq!(resolve_engine(Engine::Auto, || true), Engine::Gpu);
+    assert_eq!(resolve_engine(Engine::Cpu, || true), Engine::Cpu);
+    assert_eq!(resolve_engine(Engine::Gpu, || panic!("cpu and gpu don't look for a GPU")), Engine::Gpu);
+
+    let args = Args::try_parse_from(["gendelbrot", "--engine", "auto"]).unwrap();
+    assert_eq!(args.engine, Engine::Auto);
+    // --gpu is still accepted, but can't be combined with --engine
+    let args = Args::try_parse_from(["gendelbrot", "--gpu"]).unwrap();
+    assert!(args.gpu);
+    assert!(Args::try_parse_from(["gendelbrot", "--gpu", "--engine", "cpu"]).is_err());
+}
+
+#[cfg(not(feature = "gpu"))]
+#[test]
+fn test_engine_without_gpu() {
+    // Without CUDA auto always renders on the CPU, and --engine gpu is refused with how to get it
+    assert_eq!(resolve_engine(Engine::Auto, gpu_available), Engine::Cpu);
+    assert_eq!(engine_unsupported(Engine::Cpu), None);
+    assert!(engine_unsupported(Engine::Gpu).unwrap().contains("--features gpu"));
+    let args = Args::try_parse_from(["gendelbrot", "--gpu"]).unwrap();
+    assert!(engine_unsupported(resolve_engine(if args.gpu { Engine::Gpu } else { args.engine }, gpu_available)).is_some());
+
+    // The CPU still renders
+    let options = small_options(64);
+    let image = build_mandelbrot_cpu(&options);
+    assert_eq!(image, build_mandelbrot_cpu_simple(&options));
+    assert!(image.contains(&0) && image.contains(&u8::MAX));
+}
+
+#[test]
+fn test_nice() {
+    let args = Args::try_parse_from(["gendelbrot", "--nice", "10"]).unwrap();
+    assert_eq!(args.nice, Some(10));
+    assert!(Args::try_parse_from(["gendelbrot", "--nice", "20"]).is_err());
+    assert!(Args::try_parse_from(["gendelbrot", "--nice", "-5"]).is_err());
+
+    // Lowering the priority is always allowed, and only makes the render slower
+    let options = small_options(64);
+    let expected = build_mandelbrot_cpu_simple(&options);
+    let image = std::thread::spawn(move || {
+        priority::lower_priority(10).unwrap();
+        build_mandelbrot_cpu(&options)
+    })
+    .join()
+    .unwrap();
+    assert_eq!(image, expected);
+
+    // The niceness goes up from where it is, and lowering it past MAX_NICE stops there instead
+    // of failing
+    #[cfg(unix)]
+    std::thread::spawn(|| {
+        let start = priority::current_nice().unwrap();
+        priority::lower_priority(3).unwrap();
+        priority::lower_priority(4).unwrap();
+        assert_eq!(priority::current_nice(), Ok((start + 7).min(priority::MAX_NICE)));
+        priority::lower_priority(priority::MAX_NICE).unwrap();
+        priority::lower_priority(5).unwrap();
+        assert_eq!(priority::current_nice(), Ok(priority::MAX_NICE));
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn test_atom_domains() {
+    let period = |real: f64, imaginary: f64| {
+        Complex::new(real, imaginary).interior_period(None, 1000, BAILOUT_SQ, MAX_ATOM_PERIOD)
+    };
+    // The main cardioid has period 1, the bulb left of it period 2
+    assert_eq!(period(0.0, 0.0), Some(1));
+    assert_eq!(period(-0.25, 0.25), Some(1));
+    for (real, imaginary) in [(-1.0, 0.0), (-1.1, 0.0), (-0.9, 0.1), (-1.2, -0.1)] {
+        assert_eq!(period(real, imaginary), Some(2), "{} {}", real, imaginary);
+    }
+    // The bulbs on top of the cardioid and at the end of the period 2 bulb
+    assert_eq!(period(-0.1225, 0.7449), Some(3));
+    assert_eq!(period(-1.3107, 0.0), Some(4));
+    // Outside of the set
+    assert_eq!(period(1.0, 0.0), None);
+
+    // The periods get different colors, the outside is shaded as usual
+    let options = MandelbrotCpu {
+        iterations: 1000,
+        atom_domains: true,
+        ..MandelbrotCpu::default()
+    };
+    assert_eq!(options.bytes_per_pixel(), 3);
+    let cardioid = options.pixel_color(&Complex::new(0.0, 0.0));
+    let bulb = options.pixel_color(&Complex::new(-1.0, 0.0));
+    assert_eq!(cardioid, color::atom_domain_color(1));
+    assert_eq!(bulb, color::atom_domain_color(2));
+    assert_ne!(cardioid, bulb);
+    assert_eq!(options.pixel_color(&Complex::new(1.0, 0.0)), [u8::MAX; 3]);
+}
+
+#[test]
+fn test_name_template() {
+    let values = naming::NameValues {
+        frame: 7,
+        index: 12,
+        zoom: 1.5,
+        re: "-0.75".to_string(),
+        im: "0.1".to_string(),
+    };
+    let expand = |template: &str| naming::expand_template(template, &values);
+    assert_eq!(expand("mandelbrot.png"), Ok("mandelbrot.png".to_string()));
+    assert_eq!(expand("frame_{frame}.png"), Ok("frame_7.png".to_string()));
+    assert_eq!(expand("frame_{frame:04}_{index:03}.png"), Ok("frame_0007_012.png".to_string()));
+    assert_eq!(expand("{re}_{im}_x{zoom}.bmp"), Ok("-0.75_0.1_x1.5.bmp".to_string()));
+    assert!(expand("{index:1}.png").is_err());
+    assert!(expand("{zoom:04}.png").is_err());
+    assert!(expand("{depth}.png").is_err());
+    assert!(expand("frame_{frame.png").is_err());
+
+    let path = naming::output_path(Some(Path::new("renders")), Some("{frame:02}.png"), "mandelbrot.png", &values);
+    assert_eq!(path, Ok(Path::new("renders").join("07.png")));
+    let path = naming::output_path(None, None, "mandelbrot.png", &values);
+    assert_eq!(path, Ok(Path::new("mandelbrot.png").to_path_buf()));
+}
 
 #[test]
-fn test_mandelbrot_cpu_default() {
-    let options = MandelbrotCpu::default();
+fn test_gamma() {
+    // Gamma 1 changes nothing
+    for c in 0..=u8::MAX {
+        assert_eq!(color::apply_gamma([c, c / 2, u8::MAX - c], 1.0), [c, c / 2, u8::MAX - c]);
+    }
+    // Gamma 2.2 brightens the midtones, but leaves black and white alone
+    assert_eq!(color::apply_gamma([0, 128, u8::MAX], 2.2), [0, 186, u8::MAX]);
+    let mut previous = 0;
+    for c in 1..u8::MAX {
+        let corrected = color::apply_gamma([c; 3], 2.2)[0];
+        assert!(corrected > c && corrected >= previous, "{} {}", c, corrected);
+        previous = corrected;
+    }
+
+    // The corrected colors end up in the image
+    let options = MandelbrotCpu {
+        shading: Shading::Hybrid,
+        ..small_options(32)
+    };
     let image = build_mandelbrot_cpu(&options);
-    assert_eq!(image.len(), options.image_width * options.image_height);
+    let corrected = build_mandelbrot_cpu(&MandelbrotCpu { gamma: 2.2, ..options });
+    let expected: Vec<u8> = image.iter().map(|&c| color::apply_gamma([c; 3], 2.2)[0]).collect();
+    assert_eq!(corrected, expected);
+}
 
-    let expected_image = build_mandelbrot_cpu_simple(&options);
-    //assert_eq!(image, expected_image);
-    if image != expected_image {
-        println!("Image does not match expected output.");
-        export_image(&image, options.image_width, options.image_height, "output.png");
-        export_image(&expected_image, options.image_width, options.image_height, "expected_output.png");
-        assert!(false, "Image does not match expected output.");
+#[test]
+fn test_precision_exhausted() {
+    let view = |size: f64| {
+        let step = size / IMAGE_DIM as f64;
+        MandelbrotCpu {
+            real_step: step,
+            i_step: step,
+            real_start: -0.75 - size / 2.0,
+            i_start: 0.1 + size / 2.0,
+            ..MandelbrotCpu::default()
+        }
+    };
+    // The default view is fine in any precision
+    assert!(!precision_exhausted(&MandelbrotCpu::default(), f32::EPSILON as f64));
+    assert!(!precision_exhausted(&view(1e-2), f32::EPSILON as f64));
+
+    // At a size of 1e-5, f32 pixels are around 1e-8 apart, which is the f32 epsilon at -0.75
+    assert!(precision_exhausted(&view(1e-5), f32::EPSILON as f64));
+    assert!(!precision_exhausted(&view(1e-5), f64::EPSILON));
+    assert!(precision_exhausted(&view(1e-14), f64::EPSILON));
+}
+
+#[test]
+fn test_digits_needed() {
+    let view = |size: f64| {
+        let step = size / IMAGE_DIM as f64;
+        MandelbrotCpu {
+            real_step: step,
+            i_step: step,
+            real_start: -0.75 - size / 2.0,
+            i_start: 0.1 + size / 2.0,
+            ..MandelbrotCpu::default()
+        }
+    };
+    // The default view reaches out to -2 with pixels 3 / 1024 apart
+    assert_eq!(digits_needed(&MandelbrotCpu::default()).ceil(), 4.0);
+    // Every factor of 10 deeper takes another digit
+    assert_eq!(digits_needed(&view(1e-5)).ceil(), 9.0);
+    assert_eq!(digits_needed(&view(1e-14)).ceil(), 18.0);
+
+    // Which agrees with where the precisions run out
+    let report = precision_report(&view(1e-5));
+    assert!(report.starts_with("Telling neighboring pixels apart takes 9 significant digits"), "{}", report);
+    assert!(report.contains("f32            ~7 digits, not enough"), "{}", report);
+    assert!(report.contains("f64            ~16 digits, enough"), "{}", report);
+    let report = precision_report(&view(1e-14));
+    assert!(report.contains("f64            ~16 digits, not enough"), "{}", report);
+    assert!(report.contains("double-double  ~31 digits, enough"), "{}", report);
+}
+
+#[test]
+fn test_interior_modulus() {
+    let modulus = |real: f64, imaginary: f64| Complex::new(real, imaginary).interior_max_modulus(None, 1000, BAILOUT_SQ);
+    // 0 never leaves the origin, -1 swings out to 1 and -2 right to the escape radius
+    assert_eq!(modulus(0.0, 0.0), Some(0.0));
+    assert_eq!(modulus(-1.0, 0.0), Some(1.0));
+    assert_eq!(modulus(1.0, 0.0), None);
+
+    let options = MandelbrotCpu {
+        iterations: 1000,
+        interior_modulus: true,
+        ..MandelbrotCpu::default()
+    };
+    let shade = |real: f64, imaginary: f64| options.pixel_color(&Complex::new(real, imaginary))[0];
+    // The deep interior is darkest, the interior near the edge lighter and the tip lightest
+    let deep = shade(-0.1, 0.0);
+    let edge = shade(0.24, 0.0);
+    let tip = shade(-1.99, 0.0);
+    assert!(deep < 20, "{}", deep);
+    assert!(deep < edge && edge < tip, "{} {} {}", deep, edge, tip);
+    assert!(tip > 240, "{}", tip);
+    // The outside is shaded as usual
+    assert_eq!(shade(1.0, 0.0), u8::MAX);
+}
+
+#[test]
+fn test_invert_plane() {
+    let inverse = |real: f64, imaginary: f64| {
+        let inverse = Complex::new(real, imaginary).inverse();
+        (inverse.real, inverse.imaginary)
+    };
+    assert_eq!(inverse(2.0, 0.0), (0.5, 0.0));
+    assert_eq!(inverse(0.0, 1.0), (0.0, -1.0));
+    assert_eq!(inverse(0.0, 0.0), (f64::INFINITY, 0.0));
+    // Inverting twice gives the number back
+    for (real, imaginary) in [(-0.75, 0.1), (3.0, -4.0), (1e-3, 2e3)] {
+        let (inverse_real, inverse_imaginary) = inverse(real, imaginary);
+        let (back_real, back_imaginary) = inverse(inverse_real, inverse_imaginary);
+        assert!((back_real - real).abs() <= 1e-12 * real.abs(), "{} {}", back_real, real);
+        assert!((back_imaginary - imaginary).abs() <= 1e-12 * imaginary.abs(), "{} {}", back_imaginary, imaginary);
+    }
+
+    // Every pixel shows the inverse of its coordinate, and the origin is outside of the set
+    let options = MandelbrotCpu {
+        shading: Shading::Hybrid,
+        ..MandelbrotCpu::default()
+    };
+    let inverted = MandelbrotCpu {
+        invert_plane: true,
+        ..options.clone()
+    };
+    for (real, imaginary) in [(-4.0, 0.0), (-1.0, 0.5), (2.0, 2.0), (-0.3, -0.1)] {
+        let point = Complex::new(real, imaginary);
+        assert_eq!(inverted.pixel_color(&point), options.pixel_color(&point.inverse()));
+    }
+    let flat = MandelbrotCpu {
+        shading: Shading::Flat,
+        ..inverted
+    };
+    assert_eq!(flat.pixel_color(&Complex::new(-4.0, 0.0)), [0; 3]);
+    assert_eq!(flat.pixel_color(&Complex::new(0.0, 0.0)), [u8::MAX; 3]);
+}
+
+#[test]
+fn test_transform() {
+    // A 4 x 4 view around -0.5, sampled at the pixel centers, so every coordinate is exact
+    let args = Args::try_parse_from(["gendelbrot", "--center", "-0.5", "0", "--size", "4", "4", "--image-size", "64", "64"]).unwrap();
+    let options = sample_centers(&options_from_args(&args));
+    let plain = build_mandelbrot_cpu(&options);
+
+    // The identity matrix leaves the render as it is
+    let args = Args::try_parse_from([
+        "gendelbrot", "--center", "-0.5", "0", "--size", "4", "4", "--image-size", "64", "64", "--transform", "1", "0", "0", "1", "0", "0",
+    ])
+    .unwrap();
+    let identity = sample_centers(&options_from_args(&args));
+    assert_eq!(identity.transform.as_ref().unwrap().center.real, -0.5);
+    assert_eq!(build_mandelbrot_cpu(&identity), plain);
+
+    // Rotating the grid a quarter turn counterclockwise around the center turns the image a
+    // quarter turn clockwise
+    let rotated = MandelbrotCpu {
+        transform: Some(transform::Affine {
+            matrix: [0.0, -1.0, 1.0, 0.0, 0.0, 0.0],
+            center: Complex::new(-0.5, 0.0),
+        }),
+        ..options.clone()
+    };
+    let image = build_mandelbrot_cpu(&rotated);
+    for row in 0..64 {
+        for column in 0..64 {
+            assert_eq!(image[row * 64 + column], plain[(63 - column) * 64 + row], "{} {}", row, column);
+        }
+    }
+
+    // Any rotation shows the rotated point, and e and f move the view
+    let (sin, cos) = 0.3f64.sin_cos();
+    let turned = MandelbrotCpu {
+        shading: Shading::Hybrid,
+        transform: Some(transform::Affine {
+            matrix: [cos, -sin, sin, cos, 0.25, -0.5],
+            center: Complex::new(-0.5, 0.0),
+        }),
+        ..options.clone()
+    };
+    let untransformed = MandelbrotCpu {
+        transform: None,
+        ..turned.clone()
+    };
+    for (real, imaginary) in [(-1.0, 0.5), (0.2, -0.3), (-0.5, 0.0)] {
+        let (x, y) = (real + 0.5, imaginary);
+        let point = Complex::new(-0.5 + cos * x - sin * y + 0.25, sin * x + cos * y - 0.5);
+        assert_eq!(turned.pixel_color(&Complex::new(real, imaginary)), untransformed.pixel_color(&point));
     }
 }
 
+#[test]
+fn test_log_polar() {
+    let options = MandelbrotCpu {
+        image_width: 64,
+        image_height: 32,
+        ..MandelbrotCpu::default()
+    };
+    let center = Complex::new(-0.75, 0.1);
+    let strip = log_polar_view(&options, center.clone(), 2.0);
+    let plane = |row: usize, column: usize| {
+        let point = strip.plane_point(&strip.pixel_point(row, column));
+        (point.real, point.imaginary)
+    };
+    let distance = |(a_real, a_imaginary): (f64, f64), (b_real, b_imaginary): (f64, f64)| {
+        ((a_real - b_real).powi(2) + (a_imaginary - b_imaginary).powi(2)).sqrt()
+    };
+    // The right edge of the strip is at the radius, straight to the left of the center at the top
+    let (real, imaginary) = plane(0, 64);
+    assert!((real - (-2.75)).abs() < 1e-12 && (imaginary - 0.1).abs() < 1e-12, "{} {}", real, imaginary);
+
+    // The angle wraps around from the last row to the first, just like between any other rows
+    for column in [0, 20, 63] {
+        let step = distance(plane(0, column), plane(1, column));
+        assert!(step > 0.0);
+        assert!((distance(plane(31, column), plane(0, column)) - step).abs() < 1e-9 * step);
+        assert!((distance(plane(15, column), plane(16, column)) - step).abs() < 1e-9 * step);
+    }
+    // Going left zooms in on the center
+    assert!(distance(plane(5, 10), (-0.75, 0.1)) < distance(plane(5, 11), (-0.75, 0.1)));
 
+    // The pixels are colored by the point in the plane they show
+    for (row, column) in [(0, 0), (10, 40), (31, 63)] {
+        let point = strip.pixel_point(row, column);
+        let (real, imaginary) = plane(row, column);
+        assert_eq!(strip.pixel_color(&point), options.pixel_color(&Complex::new(real, imaginary)));
+    }
+}
 
 #[test]
-fn test_mandelbrot_cpu_broad() {
-    for real_step in [0.01, 0.02, 0.04, 0.08] {
-        for i_step in [0.01, 0.02, 0.04, 0.08] {
-            let options = MandelbrotCpu {
-                threads: 6,
-                image_width: 100,
-                image_height: 100,
-                real_start: -2.0,
-                real_step,
-                i_start: 1.0,
-                i_step,
-                iterations: 1000,
-            };
-            let image = build_mandelbrot_cpu(&options);
-            assert_eq!(image.len(), options.image_width * options.image_height);
+fn test_escape_angle() {
+    assert_eq!(color::escape_angle(1.0, 0.0), 0.0);
+    assert_eq!(color::escape_angle(0.0, 1.0), 90.0);
+    assert_eq!(color::escape_angle(-1.0, 0.0), 180.0);
+    assert_eq!(color::escape_angle(0.0, -1.0), 270.0);
+    assert_eq!(color::escape_angle(1.0, -1e-20), 0.0);
 
-            let expected_image = build_mandelbrot_cpu_simple(&options);
-            //assert_eq!(image, expected_image);
-            if image != expected_image {
-                println!("Image does not match expected output for real_step: {}, i_step: {}", real_step, i_step);
-                export_image(&image, options.image_width, options.image_height, "images/output.png");
-                export_image(&expected_image, options.image_width, options.image_height, "images/expected_output.png");
-                assert!(false, "Image does not match expected output for real_step: {}, i_step: {}", real_step, i_step);
+    // Opposite endpoints are half the hue circle apart
+    for (real, imaginary) in [(3.0, 1.0), (-0.5, 2.5), (1.5, -4.0), (-2.0, -1e-3)] {
+        let angle = color::escape_angle(real, imaginary);
+        let opposite = color::escape_angle(-real, -imaginary);
+        assert!((0.0..360.0).contains(&angle), "{}", angle);
+        assert!(((angle - opposite).abs() - 180.0).abs() < 1e-9, "{} {}", angle, opposite);
+    }
+
+    // Points inside the set stay black
+    let options = MandelbrotCpu {
+        shading: Shading::Angle,
+        ..MandelbrotCpu::default()
+    };
+    assert_eq!(options.pixel_color(&Complex::new(0.0, 0.0)), [0; 3]);
+    assert_ne!(options.pixel_color(&Complex::new(1.0, 1.0)), [0; 3]);
+
+    let args = Args::try_parse_from(["gendelbrot", "--color-mode", "angle"]).unwrap();
+    assert_eq!(args.shading, Shading::Angle);
+}
+
+#[test]
+fn test_hsv_cycle_wraps() {
+    // Colors that only differ by rounding
+    let close = |a: [u8; 3], b: [u8; 3]| (0..3).all(|c| (a[c] as i32 - b[c] as i32).abs() <= 1);
+    for repeat in [1.0, 3.0, 7.5] {
+        for t in [0.0, 0.1, 0.25, 0.5, 0.9] {
+            // A whole cycle further along the rainbow is the same color
+            let color = color::hsv_cycle(t, repeat, 1.0, 1.0);
+            let next_cycle = color::hsv_cycle(t + 1.0 / repeat, repeat, 1.0, 1.0);
+            assert!(close(color, next_cycle), "{:?} != {:?}", color, next_cycle);
+        }
+    }
+    // There is no jump where the hue wraps around from 360 back to 0
+    let before = color::hsv_cycle(0.9999, 1.0, 1.0, 1.0);
+    let after = color::hsv_cycle(1.0001, 1.0, 1.0, 1.0);
+    assert!(close(before, after), "{:?} != {:?}", before, after);
+}
+
+#[test]
+fn test_mandelbrot_cpu_hsv_cycle() {
+    let options = MandelbrotCpu {
+        threads: 2,
+        shading: Shading::HsvCycle,
+        ..small_options(32)
+    };
+    let image = build_mandelbrot_cpu(&options);
+    assert_eq!(options.color_type(), ColorType::Rgb8);
+    assert_eq!(image.len(), 32 * 32 * 3);
+    assert_eq!(image, build_mandelbrot_cpu_simple(&options));
+}
+
+
+#[test]
+fn test_binary_decomposition() {
+    // 1 + 0.1i escapes into the upper half plane for all of these escape radii, and its mirror
+    // image into the lower half, so the two regions stay split the same way
+    let upper = Complex::new(1.0, 0.1);
+    let lower = Complex::new(1.0, -0.1);
+    for bailout_sq in [4.0, 16.0, 100.0, 1e6] {
+        let options = MandelbrotCpu {
+            bailout_sq,
+            shading: Shading::BinaryDecomp,
+            ..MandelbrotCpu::default()
+        };
+        assert_eq!(options.pixel_color(&upper), [color::BINARY_DECOMP_SHADES[0]; 3]);
+        assert_eq!(options.pixel_color(&lower), [color::BINARY_DECOMP_SHADES[1]; 3]);
+        assert_eq!(options.pixel_color(&Complex::new(-0.5, 0.0)), [0; 3]);
+    }
+}
+
+#[test]
+fn test_orbit_average() {
+    let radius = BAILOUT_SQ.sqrt();
+    // The average stays within the bailout radius, inside and outside of the set
+    for row in 0..32 {
+        for column in 0..32 {
+            let point = Complex::new(-2.5 + column as f64 * 0.125, 2.0 - row as f64 * 0.125);
+            let average = point.orbit_average(None, STABLE_ITERATIONS, BAILOUT_SQ);
+            assert!((0.0..=radius).contains(&average), "{:?} {}", point, average);
+        }
+    }
+    // Numbers that escape right away get the bailout radius
+    assert_eq!(Complex::new(3.0, 0.0).orbit_average(None, STABLE_ITERATIONS, BAILOUT_SQ), radius);
+
+    // Scanning away from the cusp of the cardioid along the real axis, the average mostly grows,
+    // up to the bailout radius
+    let averages: Vec<f64> = (0..175)
+        .map(|step| Complex::new(0.26 + step as f64 * 0.01, 0.0).orbit_average(None, STABLE_ITERATIONS, BAILOUT_SQ))
+        .collect();
+    let growing = averages.windows(2).filter(|pair| pair[1] >= pair[0]).count();
+    assert!(growing * 10 >= (averages.len() - 1) * 9, "{} of {}", growing, averages.len() - 1);
+    assert!(averages[0] < 0.7 && averages[174] > 0.99 * radius, "{:?}", averages);
+
+    // The set isn't black, its pixels are shaded too
+    let options = MandelbrotCpu {
+        shading: Shading::OrbitAverage,
+        ..MandelbrotCpu::default()
+    };
+    assert!(options.contains(&Complex::new(0.0, 0.0)));
+    assert_eq!(options.pixel_color(&Complex::new(0.0, 0.0)), [0; 3]);
+    assert_ne!(options.pixel_color(&Complex::new(-0.5, 0.0)), [0; 3]);
+    assert_eq!(options.pixel_color(&Complex::new(3.0, 0.0)), [u8::MAX; 3]);
+}
+
+#[test]
+fn test_builtin_palettes() {
+    for palette in BuiltinPalette::ALL {
+        assert_eq!(BuiltinPalette::from_name(palette.name()), Some(palette));
+        assert_eq!(BuiltinPalette::from_name(&palette.name().to_uppercase()), Some(palette));
+        assert_eq!(palette.name().parse::<BuiltinPalette>(), Ok(palette));
+
+        // The ends of the palette are its first and last stop, and t is clamped
+        let stops = palette.stops();
+        assert_eq!(palette.sample_in(0.0, ColorSpace::Srgb), stops[0]);
+        assert_eq!(palette.sample_in(1.0, ColorSpace::Srgb), stops[stops.len() - 1]);
+        assert_eq!(palette.sample_in(-1.0, ColorSpace::Srgb), stops[0]);
+        assert_eq!(palette.sample_in(2.0, ColorSpace::Srgb), stops[stops.len() - 1]);
+    }
+    assert_eq!(BuiltinPalette::from_name("plaid"), None);
+    assert!("plaid".parse::<BuiltinPalette>().unwrap_err().contains("grayscale, fire"));
+
+    // Colors in between stops are interpolated
+    assert_eq!(BuiltinPalette::Grayscale.sample_in(0.5, ColorSpace::Srgb), [128, 128, 128]);
+    assert_eq!(BuiltinPalette::Rainbow.sample_in(0.2, ColorSpace::Srgb), [255, 255, 0]);
+    assert_eq!(BuiltinPalette::Rainbow.sample_in(0.1, ColorSpace::Srgb), [255, 128, 0]);
+}
+
+#[test]
+fn test_mandelbrot_cpu_palette() {
+    let options = MandelbrotCpu {
+        image_width: 64,
+        image_height: 64,
+        shading: Shading::Palette,
+        palette: BuiltinPalette::Fire,
+        ..MandelbrotCpu::default()
+    };
+    assert_eq!(options.bytes_per_pixel(), 3);
+    assert_eq!(options.pixel_color(&Complex::new(-0.5, 0.0)), [0; 3]);
+    // Far outside of the set the gradient starts at the first stop
+    assert_eq!(options.pixel_color(&Complex::new(100.0, 100.0)), BuiltinPalette::Fire.sample_in(0.0, ColorSpace::Srgb));
+    let image = build_mandelbrot_cpu(&options);
+    assert_eq!(image.len(), 64 * 64 * 3);
+
+    let args = Args::try_parse_from(["gendelbrot", "--color", "ocean"]).unwrap();
+    assert_eq!(args.palette, Some(BuiltinPalette::Ocean));
+}
+
+#[test]
+fn test_palette_gamma() {
+    // Gamma 1 samples the palette as it is
+    for i in 0..=100 {
+        let t = i as f64 / 100.0;
+        assert_eq!(BuiltinPalette::Sunset.sample_gamma(t, 1.0, ColorSpace::Srgb), BuiltinPalette::Sunset.sample_in(t, ColorSpace::Srgb));
+    }
+    // Any positive gamma keeps the order of the colors, so the grayscale palette stays monotonic,
+    // and keeps both ends of the palette where they are
+    for gamma in [0.25, 0.5, 2.0, 4.0] {
+        let shades: Vec<u8> = (0..=100).map(|i| BuiltinPalette::Grayscale.sample_gamma(i as f64 / 100.0, gamma, ColorSpace::Srgb)[0]).collect();
+        assert!(shades.windows(2).all(|pair| pair[0] <= pair[1]), "{}", gamma);
+        assert_eq!((shades[0], shades[100]), (0, u8::MAX));
+    }
+    // Below 1 the middle of the range gets brighter colors, above 1 darker ones
+    assert!(BuiltinPalette::Grayscale.sample_gamma(0.5, 0.5, ColorSpace::Srgb)[0] > 128);
+    assert!(BuiltinPalette::Grayscale.sample_gamma(0.5, 2.0, ColorSpace::Srgb)[0] < 128);
+
+    let options = MandelbrotCpu {
+        shading: Shading::Palette,
+        palette: BuiltinPalette::Ocean,
+        ..small_options(32)
+    };
+    let gamma = MandelbrotCpu { palette_gamma: 0.5, ..options.clone() };
+    assert_ne!(build_mandelbrot_cpu(&gamma), build_mandelbrot_cpu(&options));
+    assert!(Args::try_parse_from(["gendelbrot", "--palette-gamma", "0"]).is_err());
+}
+
+#[test]
+fn test_color_space() {
+    // OKLab spaces the lightness evenly, so the middle of black to white is darker than the
+    // middle of the encoded values
+    let srgb = BuiltinPalette::Grayscale.sample_in(0.5, ColorSpace::Srgb);
+    let oklab = BuiltinPalette::Grayscale.sample_in(0.5, ColorSpace::Oklab);
+    assert_eq!(srgb, [128, 128, 128]);
+    assert_ne!(oklab, srgb);
+    assert!(oklab[0] < 128 && oklab[0] == oklab[1] && oklab[1] == oklab[2], "{:?}", oklab);
+    // The stops themselves stay where they are
+    for palette in BuiltinPalette::ALL {
+        let stops = palette.stops();
+        assert_eq!(palette.sample_in(0.0, ColorSpace::Oklab), stops[0]);
+        assert_eq!(palette.sample_in(1.0, ColorSpace::Oklab), stops[stops.len() - 1]);
+    }
+    for color in [[255, 128, 0], [10, 200, 90], [32, 0, 64]] {
+        let back = color::oklab_to_srgb(color::srgb_to_oklab(color));
+        assert!((0..3).all(|channel| back[channel].abs_diff(color[channel]) <= 1), "{:?}", back);
+    }
+
+    let args = Args::try_parse_from(["gendelbrot", "--color-space", "oklab"]).unwrap();
+    assert_eq!(args.color_space, ColorSpace::Oklab);
+    let options = MandelbrotCpu {
+        shading: Shading::Palette,
+        palette: BuiltinPalette::Fire,
+        ..small_options(32)
+    };
+    let oklab = MandelbrotCpu { color_space: ColorSpace::Oklab, ..options.clone() };
+    assert_ne!(build_mandelbrot_cpu(&oklab), build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_rgb_parse() {
+    assert_eq!("ff8000".parse::<Rgb>(), Ok(Rgb([255, 128, 0])));
+    assert_eq!("#0A0b0c".parse::<Rgb>(), Ok(Rgb([10, 11, 12])));
+    assert_eq!(Rgb([255, 128, 0]).to_string(), "ff8000");
+    assert!("ff80".parse::<Rgb>().is_err());
+    assert!("gg8000".parse::<Rgb>().is_err());
+}
+
+// ==================================================
+// Overlay tests
+// ==================================================
+
+#[test]
+fn test_overlay_crosshair() {
+    let color = Rgb([255, 0, 0]);
+    let (width, height) = (33, 20);
+    let mut image = vec![0u8; width * height * 3];
+    overlay::draw_crosshair(&mut image, width, height, 3, color);
+
+    let (x, y) = overlay::center_pixel(width, height);
+    assert_eq!((x, y), (16, 10));
+    let pixel = |x: usize, y: usize| &image[(y * width + x) * 3..(y * width + x) * 3 + 3];
+    for (x, y) in [(x, y), (x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+        assert_eq!(pixel(x, y), color.0);
+    }
+    // The corners are left alone
+    assert_eq!(pixel(x - 1, y - 1), [0, 0, 0]);
+    assert_eq!(pixel(0, 0), [0, 0, 0]);
+
+    // Grayscale images get the brightness of the color
+    let mut image = vec![0u8; width * height];
+    overlay::draw_crosshair(&mut image, width, height, 1, color);
+    assert_eq!(image[y * width + x], color.luma());
+    assert_eq!(image[y * width + x + 1], color.luma());
+}
+
+#[test]
+fn test_overlay_thirds() {
+    let mut image = vec![0u8; 9 * 6];
+    overlay::draw_thirds(&mut image, 9, 6, 1, Rgb([255, 255, 255]));
+    let lit: Vec<usize> = (0..9).filter(|&x| image[x] != 0).collect();
+    assert_eq!(lit, [3, 6]);
+    let lit: Vec<usize> = (0..6).filter(|&y| image[y * 9] != 0).collect();
+    assert_eq!(lit, [2, 4]);
+}
+
+#[test]
+fn test_budget_ring() {
+    // Next to the seahorse valley, where the pixels keep escaping later and later
+    let ring = |iterations| {
+        let options = MandelbrotCpu {
+            image_width: 64,
+            image_height: 64,
+            real_start: -0.76,
+            real_step: 1e-3,
+            i_start: 0.12,
+            i_step: 1e-3,
+            iterations,
+            ..MandelbrotCpu::default()
+        };
+        let ring = detail::budget_ring(&options);
+        // Only escaped pixels are in the ring
+        for (index, &escaped) in ring.iter().enumerate() {
+            assert!(!escaped || !options.contains(&options.pixel_point(index / 64, index % 64)), "{}", index);
+        }
+        ring.iter().filter(|&&escaped| escaped).count()
+    };
+    // A lower budget cuts off more pixels
+    let (low, high) = (ring(50), ring(1000));
+    assert!(low > high, "{} <= {}", low, high);
+
+    let mut image = vec![0u8; 3 * 2 * 3];
+    overlay::draw_mask(&mut image, 3, 3, &[false, true, false, false, false, true], Rgb([1, 2, 3]));
+    assert_eq!(image, [0, 0, 0, 1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+}
+
+// ==================================================
+// Double-double tests
+// ==================================================
+
+#[test]
+fn test_double_double_arithmetic() {
+    // 1e-20 is far too small to survive being added to 1 in f64
+    let one = DoubleDouble::from(1.0);
+    let tiny = DoubleDouble::from(1e-20);
+    assert_eq!((one + tiny - one).hi, 1e-20);
+    assert_eq!(((one + tiny) * (one + tiny) - one).hi, 2e-20);
+    assert_eq!((DoubleDouble::from(1.0) / DoubleDouble::from(3.0)).mul_f64(3.0).hi, 1.0);
+}
+
+#[test]
+fn test_double_double_parse() {
+    let tenth: DoubleDouble = "0.1".parse().unwrap();
+    assert_eq!(tenth.hi, 0.1);
+    // 0.1 isn't exactly representable in f64, the low part holds the difference
+    assert_ne!(tenth.lo, 0.0);
+    assert_eq!(tenth.mul_f64(10.0), DoubleDouble::from(1.0));
+
+    let deep: DoubleDouble = "-1.25000000000000000000001".parse().unwrap();
+    assert_eq!(deep.hi, -1.25);
+    assert!((deep.lo + 1e-23).abs() < 1e-31);
+    assert_eq!("-15e-1".parse::<DoubleDouble>().unwrap(), DoubleDouble::from(-1.5));
+    assert!("1.2.3".parse::<DoubleDouble>().is_err());
+    assert!("abc".parse::<DoubleDouble>().is_err());
+}
+
+#[test]
+fn test_mandelbrot_double_double_shallow() {
+    // At a normal zoom level double-double gives exactly the same image as f64
+    let options = MandelbrotCpu {
+        threads: 4,
+        precision: Precision::DoubleDouble,
+        ..small_options(64)
+    };
+    let image = double_double::build_mandelbrot_double_double(&options);
+    assert_eq!(image, build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_mandelbrot_double_double_deep() {
+    // A 64x64 pixel view around -2, zoomed in so far that every pixel is less than an f64
+    // ulp apart. Row 32 lies on the real axis, where everything from -2 up is in the set.
+    let options = MandelbrotCpu {
+        threads: 2,
+        image_width: 64,
+        image_height: 64,
+        real_start: -2.0,
+        real_start_lo: -32e-19,
+        real_step: 1e-19,
+        i_start: 32e-19,
+        i_step: 1e-19,
+        iterations: 100,
+        precision: Precision::DoubleDouble,
+        ..MandelbrotCpu::default()
+    };
+    let row = 32 * options.image_width..33 * options.image_width;
+
+    // f64 can't tell the pixels apart from -2, which just escapes
+    let image = build_mandelbrot_cpu(&options);
+    assert!(image[row.clone()].iter().all(|&pixel| pixel == u8::MAX));
+
+    // double-double resolves the real axis, -2 itself still escapes but the rest doesn't
+    let image = double_double::build_mandelbrot_double_double(&options);
+    let black: Vec<usize> = (0..options.image_width).filter(|&j| image[row.start + j] == 0).collect();
+    assert_eq!(black, (33..64).collect::<Vec<usize>>());
+}
+
+#[test]
+fn test_mandelbrot_f32() {
+    // At a normal zoom level with exact coordinates f32 gives exactly the same image as f64
+    let options = MandelbrotCpu {
+        threads: 4,
+        precision: Precision::F32,
+        ..small_options(64)
+    };
+    assert_eq!(single::build_mandelbrot_f32(&options), build_mandelbrot_cpu(&options));
+
+    // Zoomed in on the boundary f32 rounds some pixels the other way, but only ones on the
+    // boundary, next to a pixel on the other side of it in f64
+    let options = MandelbrotCpu {
+        image_width: 64,
+        image_height: 64,
+        real_start: -1.25,
+        real_step: 1e-5,
+        i_start: 0.03,
+        i_step: 1e-5,
+        iterations: 200,
+        ..options
+    };
+    let reference = build_mandelbrot_cpu(&options);
+    let image = single::build_mandelbrot_f32(&options);
+    let mut differing = 0;
+    for row in 0..64 {
+        for column in 0..64 {
+            if image[row * 64 + column] == reference[row * 64 + column] {
+                continue;
             }
+            differing += 1;
+            let mut neighbors = (row.saturating_sub(1)..(row + 2).min(64))
+                .flat_map(|y| (column.saturating_sub(1)..(column + 2).min(64)).map(move |x| (y, x)));
+            assert!(
+                neighbors.any(|(y, x)| reference[y * 64 + x] != reference[row * 64 + column]),
+                "{} {}",
+                row,
+                column
+            );
         }
     }
+    assert!(differing > 0);
 }
 
+#[test]
+fn test_timings_json() {
+    let phases = ["arguments", "setup", "compute", "postprocess", "write"];
+    let mut timings = timings::Timings::start();
+    for phase in phases {
+        if phase == "compute" {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        timings.finish(phase);
+    }
+    let json: serde_json::Value = serde_json::from_str(&timings.to_json(Engine::Cpu)).unwrap();
+    assert_eq!(json["engine"], "cpu");
+    let recorded = json["phases"].as_object().unwrap();
+    assert_eq!(recorded.len(), phases.len());
+    let mut sum = 0.0;
+    for phase in phases {
+        let seconds = recorded[phase].as_f64().unwrap();
+        assert!(seconds >= 0.0, "{} took {}", phase, seconds);
+        sum += seconds;
+    }
+    assert!(recorded["compute"].as_f64().unwrap() >= 0.005);
+    // The phases add up to the total
+    assert!((json["total"].as_f64().unwrap() - sum).abs() < 1e-6);
+}
 
-fn export_image(image: &[u8], width: usize, height: usize, path: &str) {
-    use image::{ImageBuffer, RgbImage};
-    let mut img: RgbImage = ImageBuffer::new(width as u32, height as u32);
-    for (i, pixel) in img.pixels_mut().enumerate() {
-        let value = image[i];
-        *pixel = image::Rgb([value, value, value]);
+#[test]
+fn test_launch_config() {
+    for total in [1, 255, 256, 257, 7 * 3, 100 * 257, 333 * 211, 1024 * 1024, 4096 * 4096 + 1] {
+        let launch = launch::LaunchConfig::new(total, launch::THREADS_PER_BLOCK);
+        // Walk the launches like the GPU render does
+        let (mut offset, mut launches) = (0, 0);
+        while offset < total {
+            let blocks = launch.blocks_at(offset);
+            assert!(blocks > 0 && blocks <= launch.blocks_per_step);
+            offset += blocks * launch.threads_per_block;
+            launches += 1;
+        }
+        assert_eq!(launches, launch.launches, "{} pixels", total);
+        assert_eq!(offset, launch.threads(), "{} pixels", total);
+        assert!(launch.threads() >= total && launch.threads() - total < launch.threads_per_block);
+        assert!(launch.launches <= 100);
     }
-    img.save(path).unwrap();
+    assert_eq!(launch::LaunchConfig::new(0, 256).launches, 0);
+
+    let dump = launch::LaunchConfig::new(1024 * 1024, 256).to_string();
+    assert!(dump.contains("blocks_per_step:   41"), "{}", dump);
+    assert!(dump.contains("total_launches:    100"), "{}", dump);
+    assert!(dump.contains("grid coverage:     1048576 threads, 100.00%"), "{}", dump);
 }
 
+#[test]
+fn test_mandelbrot_hybrid() {
+    assert_eq!(hybrid::gpu_rows(100, 0.5), 50);
+    assert_eq!(hybrid::gpu_rows(7, 0.3), 2);
+    assert_eq!((hybrid::gpu_rows(7, 0.0), hybrid::gpu_rows(7, 1.0)), (0, 7));
+    assert!(Args::try_parse_from(["gendelbrot", "--gpu-fraction", "1.5"]).is_err());
+    assert_eq!(Args::try_parse_from(["gendelbrot", "--engine", "hybrid"]).unwrap().engine, Engine::Hybrid);
+
+    // Every row is rendered by exactly one of the two, at the position it has in the full image
+    let options = MandelbrotCpu {
+        threads: 2,
+        ..small_options(64)
+    };
+    let expected = build_mandelbrot_cpu(&options);
+    for gpu_fraction in [0.0, 0.3, 0.5, 1.0] {
+        let options = MandelbrotCpu {
+            gpu_fraction,
+            ..options.clone()
+        };
+        let split = hybrid::gpu_rows(64, gpu_fraction);
+        let image = hybrid::build_mandelbrot_hybrid(
+            &options,
+            |top| {
+                assert_eq!(top.image_height, split);
+                build_mandelbrot_cpu_simple(top)
+            },
+            |bottom| {
+                assert_eq!(bottom.image_height, 64 - split);
+                build_mandelbrot_cpu(bottom)
+            },
+        );
+        assert_images_equal(&image, &expected, 64, 64, &format!("hybrid {}", gpu_fraction));
+    }
+}
 
 // ==================================================
 // GPU tests
 // ==================================================
 
+// How far the GPU images may be from the CPU ones, see compare_images_with_tolerance
+#[cfg(feature = "gpu")]
+fn assert_gpu_image(image: &[u8], expected: &[u8], options: &MandelbrotCpu, label: &str) {
+    let (width, height) = (options.image_width, options.image_height);
+    assert_images_similar(image, expected, width, height, label, (width * height / 1000).max(1), 1);
+}
+
+#[cfg(feature = "gpu")]
 #[test]
 fn test_mandelbrot_gpu_simple_default() {
     let options = MandelbrotCpu::default();
-    let image = build_mandelbrot_gpu_simple(&options);
+    let image = build_mandelbrot_gpu_simple(&options).unwrap();
     assert_eq!(image.len(), options.image_width * options.image_height);    
     let expected_image = build_mandelbrot_cpu_simple(&options);
 
-    if image != expected_image {
-        println!("GPU image does not match expected output.");
-        export_image(&image, options.image_width, options.image_height, "gpu_output.png");
-        export_image(&expected_image, options.image_width, options.image_height, "expected_output.png");
-        assert!(false, "GPU image does not match expected output.");
-    }
+    assert_gpu_image(&image, &expected_image, &options, "gpu simple default");
 }
 
 
+#[cfg(feature = "gpu")]
 #[test]
 fn test_mandelbrot_gpu_simple_broad() {
     for real_step in [0.01, 0.02, 0.04, 0.08] {
@@ -119,39 +2996,36 @@ fn test_mandelbrot_gpu_simple_broad() {
                 i_start: 1.0,
                 i_step,
                 iterations: 1000,
+                ..MandelbrotCpu::default()
             };
-            let image = build_mandelbrot_gpu_simple(&options);
+            let image = build_mandelbrot_gpu_simple(&options).unwrap();
             assert_eq!(image.len(), options.image_width * options.image_height);
 
             let expected_image = build_mandelbrot_cpu_simple(&options);
-            //assert_eq!(image, expected_image);
-            if image != expected_image {
-                println!("Image does not match expected output for real_step: {}, i_step: {}", real_step, i_step);
-                export_image(&image, options.image_width, options.image_height, "images/gpu_output.png");
-                export_image(&expected_image, options.image_width, options.image_height, "images/expected_output.png");
-                assert!(false, "Image does not match expected output for real_step: {}, i_step: {}", real_step, i_step);
-            }
+            assert_gpu_image(
+                &image,
+                &expected_image,
+                &options,
+                &format!("gpu simple broad real_step {} i_step {}", real_step, i_step),
+            );
         }
     }
 }
 
 
+#[cfg(feature = "gpu")]
 #[test]
 fn test_mandelbrot_gpu_default() {
     let options = MandelbrotCpu::default();
-    let image = build_mandelbrot_gpu(&options);
+    let image = build_mandelbrot_gpu(&options).unwrap();
     assert_eq!(image.len(), options.image_width * options.image_height);    
     let expected_image = build_mandelbrot_cpu_simple(&options);
 
-    if image != expected_image {
-        println!("GPU image does not match expected output.");
-        export_image(&image, options.image_width, options.image_height, "gpu_output.png");
-        export_image(&expected_image, options.image_width, options.image_height, "expected_output.png");
-        assert!(false, "GPU image does not match expected output.");
-    }
+    assert_gpu_image(&image, &expected_image, &options, "gpu default");
 }
 
 
+#[cfg(feature = "gpu")]
 #[test]
 fn test_mandelbrot_gpu_broad() {
     for real_step in [0.01, 0.02, 0.04, 0.08] {
@@ -165,18 +3039,440 @@ fn test_mandelbrot_gpu_broad() {
                 i_start: 1.0,
                 i_step,
                 iterations: 1000,
+                ..MandelbrotCpu::default()
             };
-            let image = build_mandelbrot_gpu(&options);
+            let image = build_mandelbrot_gpu(&options).unwrap();
             assert_eq!(image.len(), options.image_width * options.image_height);
 
             let expected_image = build_mandelbrot_cpu_simple(&options);
-            //assert_eq!(image, expected_image);
-            if image != expected_image {
-                println!("Image does not match expected output for real_step: {}, i_step: {}", real_step, i_step);
-                export_image(&image, options.image_width, options.image_height, "images/gpu_output.png");
-                export_image(&expected_image, options.image_width, options.image_height, "images/expected_output.png");
-                assert!(false, "Image does not match expected output for real_step: {}, i_step: {}", real_step, i_step);
-            }
+            assert_gpu_image(
+                &image,
+                &expected_image,
+                &options,
+                &format!("gpu broad real_step {} i_step {}", real_step, i_step),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn test_mandelbrot_gpu_covers_every_pixel() {
+    // Everything in the view escapes, so every pixel the kernel wrote is white. Sizes that aren't
+    // a multiple of the threads per block or of the pixels per launch leave a partial last launch.
+    for (image_width, image_height) in [(1, 1), (7, 3), (255, 1), (257, 1), (100, 257), (333, 211), (1000, 77)] {
+        let options = MandelbrotCpu {
+            image_width,
+            image_height,
+            real_start: 2.0,
+            real_step: 1.0 / image_width as f64,
+            i_start: 2.0,
+            i_step: 1.0 / image_height as f64,
+            ..MandelbrotCpu::default()
+        };
+        let image = build_mandelbrot_gpu(&options).unwrap();
+        assert_eq!(image.len(), image_width * image_height);
+        let missed = image.iter().filter(|&&pixel| pixel != u8::MAX).count();
+        assert_eq!(missed, 0, "{} x {}", image_width, image_height);
+    }
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn test_mandelbrot_gpu_cpu_fallback() {
+    let options = MandelbrotCpu {
+        image_width: 300,
+        image_height: 200,
+        real_step: RADIUS / 300.0,
+        i_step: RADIUS / 200.0,
+        gpu_cpu_fallback: true,
+        ..MandelbrotCpu::default()
+    };
+    let expected_image = build_mandelbrot_gpu(&options).unwrap();
+
+    // The first chunk starting past the middle of the image fails
+    let failing = std::sync::atomic::AtomicUsize::new(usize::MAX);
+    let inject = |offset: usize| {
+        if offset >= 300 * 100 && failing.compare_exchange(usize::MAX, offset, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            return Err(GpuError::Launch { cause: "injected".to_string() });
+        }
+        Ok(())
+    };
+    let image = build_mandelbrot_gpu_injecting(&options, inject).unwrap();
+    assert_ne!(failing.load(Ordering::Relaxed), usize::MAX);
+    assert_eq!(image, expected_image);
+
+    // Without the fallback the render fails
+    let options = MandelbrotCpu {
+        gpu_cpu_fallback: false,
+        ..options
+    };
+    let error = build_mandelbrot_gpu_injecting(&options, |offset| {
+        if offset > 0 {
+            return Err(GpuError::Launch { cause: "injected".to_string() });
+        }
+        Ok(())
+    });
+    assert_eq!(error, Err(GpuError::Launch { cause: "injected".to_string() }));
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn test_mandelbrot_gpu_hybrid() {
+    let options = MandelbrotCpu {
+        image_width: 300,
+        image_height: 200,
+        real_step: RADIUS / 300.0,
+        i_step: RADIUS / 200.0,
+        gpu_fraction: 0.3,
+        ..MandelbrotCpu::default()
+    };
+    let image = build_mandelbrot_hybrid_or_exit(&options);
+    assert_eq!(image.len(), 300 * 200);
+    // The top 60 rows match the GPU and the rest the CPU
+    let gpu_image = build_mandelbrot_gpu(&options).unwrap();
+    let cpu_image = build_mandelbrot_cpu(&options);
+    let top = row_range(&options, 0, 60);
+    assert_gpu_image(&image[..300 * 60], &gpu_image[..300 * 60], &top, "hybrid gpu rows");
+    assert_images_equal(&image[300 * 60..], &cpu_image[300 * 60..], 300, 140, "hybrid cpu rows");
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn test_mandelbrot_gpu_alloc_failure() {
+    // No GPU has a terabyte of memory, so allocating the image fails
+    let options = MandelbrotCpu {
+        image_width: 1 << 20,
+        image_height: 1 << 20,
+        ..MandelbrotCpu::default()
+    };
+    for result in [build_mandelbrot_gpu_simple(&options), build_mandelbrot_gpu(&options)] {
+        match result {
+            Err(GpuError::Alloc { buffer, .. }) => assert_eq!(buffer, "image"),
+            other => panic!("expected an allocation error, got {:?}", other.map(|image| image.len())),
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn test_report_device_info() {
+    let info = device_info().unwrap();
+    assert!(!info.name.is_empty());
+    assert!(info.memory_bytes.is_some_and(|bytes| bytes > 0));
+    assert!(info.max_threads_per_block.is_some_and(|max| max as usize >= launch::THREADS_PER_BLOCK));
+    assert!(info.to_string().contains(&format!("CUDA device: {}", info.name)), "{}", info);
+}
+
+#[cfg(feature = "wgpu")]
+#[test]
+fn test_mandelbrot_wgpu() {
+    let options = MandelbrotCpu::default();
+    let image = match wgpu_engine::build_mandelbrot_wgpu(&options) {
+        Err(wgpu_engine::WgpuError::NoAdapter) => {
+            println!("There is no wgpu adapter, skipping");
+            return;
+        }
+        image => image.unwrap(),
+    };
+    let expected = build_mandelbrot_cpu(&options);
+    assert_eq!(image.len(), expected.len());
+    // The GPU calculates in f32, which can round a few points on the edge of the set differently
+    let mismatches = image.iter().zip(&expected).filter(|(a, b)| a != b).count();
+    assert!(mismatches * 1000 < expected.len(), "{} pixels differ", mismatches);
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn test_gpu_launch_retries() {
+    // Launches every chunk of a 3 chunk image with a launcher that fails the first {failures}
+    // times for every chunk
+    let render = |retries: u32, failures: u32| {
+        let mut image = vec![];
+        for _chunk in 0..3 {
+            let mut attempts = 0;
+            launch_with_retries(retries, std::time::Duration::ZERO, || {
+                attempts += 1;
+                if attempts <= failures {
+                    return Err("device busy");
+                }
+                image.extend_from_slice(&[0, u8::MAX]);
+                Ok(())
+            })?;
         }
+        Ok(image)
+    };
+
+    // With N - 1 transient failures and N - 1 retries the whole image is still rendered
+    assert_eq!(render(4, 4), Ok(vec![0, u8::MAX, 0, u8::MAX, 0, u8::MAX]));
+    assert_eq!(render(0, 0), Ok(vec![0, u8::MAX, 0, u8::MAX, 0, u8::MAX]));
+
+    // One failure too many gives up with the error of the last launch
+    assert_eq!(render(3, 4), Err(GpuError::Launch { cause: "\"device busy\"".to_string() }));
+}
+
+#[test]
+fn test_start_z() {
+    let args = Args::try_parse_from(["gendelbrot", "--image-size", "64", "64", "--start-z", "0", "0"]).unwrap();
+    let options = options_from_args(&args);
+    assert_eq!(options.start_z.as_ref().map(|start| (start.real, start.imaginary)), Some((0.0, 0.0)));
+
+    // Starting at 0 is the usual set
+    let plain = build_mandelbrot_cpu(&MandelbrotCpu { start_z: None, ..options.clone() });
+    assert_eq!(build_mandelbrot_cpu(&options), plain);
+
+    // Starting elsewhere changes the shape of the set
+    let args = Args::try_parse_from(["gendelbrot", "--image-size", "64", "64", "--start-z", "0.3", "-0.2"]).unwrap();
+    let shifted = build_mandelbrot_cpu(&options_from_args(&args));
+    let changed = shifted.iter().zip(&plain).filter(|(a, b)| a != b).count();
+    assert!(changed > plain.len() / 100, "only {} bytes changed", changed);
+}
+
+#[test]
+fn test_fractal_formula() {
+    // z^2 + c gives exactly the same image as the built-in iteration, for the mandelbrot set and
+    // julia sets
+    let options = MandelbrotCpu {
+        threads: 4,
+        ..small_options(64)
+    };
+    for source in ["z^2 + c", "z*z + c", "sqr(z) + c"] {
+        let formula = Some(Arc::new(formula::Formula::parse(source).unwrap()));
+        let with_formula = MandelbrotCpu { formula, ..options.clone() };
+        assert_eq!(formula::build_mandelbrot_formula(&with_formula), build_mandelbrot_cpu(&options), "{}", source);
+    }
+    let julia = MandelbrotCpu {
+        fractal: FractalKind::Julia { real: -0.8, imaginary: 0.156 },
+        ..options.clone()
+    };
+    let formula = Some(Arc::new(formula::Formula::parse("z^2+c").unwrap()));
+    let with_formula = MandelbrotCpu { formula, ..julia.clone() };
+    assert_eq!(formula::build_mandelbrot_formula(&with_formula), build_mandelbrot_cpu(&julia));
+
+    // Other formulas are evaluated with the usual precedence
+    let apply = |source: &str, z: Complex, c: Complex| {
+        let next = formula::Formula::parse(source).unwrap().apply(&z, &c);
+        (next.real, next.imaginary)
+    };
+    assert_eq!(apply("z^3 + c", Complex::new(1.0, 1.0), Complex::new(0.5, 0.0)), (-1.5, 2.0));
+    assert_eq!(apply("z*z + sin(c)", Complex::new(0.0, 1.0), Complex::new(0.0, 0.0)), (-1.0, 0.0));
+    assert_eq!(apply("-z^2 + 2*c - i", Complex::new(3.0, 0.0), Complex::new(1.0, 0.0)), (-7.0, -1.0));
+    assert_eq!(apply("(z + 1) / (z - 1)", Complex::new(0.0, 1.0), Complex::new(0.0, 0.0)), (0.0, -1.0));
+    assert_eq!(apply("conj(z)^2 + c", Complex::new(1.0, 2.0), Complex::new(0.0, 0.0)), (-3.0, -4.0));
+    assert_eq!(apply("z^-2", Complex::new(0.0, 2.0), Complex::new(0.0, 0.0)), (-0.25, 0.0));
+    let (real, _) = apply("2^3^2", Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+    assert!((real - 512.0).abs() < 1e-9, "{}", real);
+    let (real, imaginary) = apply("z^0.5", Complex::new(-4.0, 0.0), Complex::new(0.0, 0.0));
+    assert!(real.abs() < 1e-12 && (imaginary - 2.0).abs() < 1e-12, "{} {}", real, imaginary);
+
+    // Orbits that turn into NaN escape instead of counting as in the set
+    let formula = formula::Formula::parse("z / 0 + c").unwrap();
+    assert_eq!(formula.escape(&Complex::new(0.0, 0.0), None, 100, BAILOUT_SQ), Some(0));
+
+    // Everything that only looks at where the orbits escape iterates the formula too
+    let cubic = MandelbrotCpu {
+        formula: Some(Arc::new(formula::Formula::parse("z^3 + c").unwrap())),
+        iterations: 100,
+        ..options.clone()
+    };
+    let image = formula::build_mandelbrot_formula(&cubic);
+    assert_ne!(image, build_mandelbrot_cpu(&options));
+    let inside: Vec<bool> = image.iter().map(|&pixel| pixel == 0).collect();
+    assert_eq!(minibrots::membership(&cubic), inside);
+    assert!(render_iterations(&cubic).iter().zip(&inside).all(|(&count, &inside)| (count == 100) == inside));
+    assert_eq!(validate::validate_image(&image, &cubic, 1000), Ok(0));
+
+    // Mistakes are reported when the arguments are parsed
+    for source in ["z^^2", "foo(z)", "x", "(z + c", "z +", "z c", "sin z", "z # 2", "1.2.3"] {
+        assert!(formula::Formula::parse(source).is_err(), "{}", source);
+        assert!(Args::try_parse_from(["gendelbrot", "--fractal-formula", source]).is_err(), "{}", source);
+    }
+    let args = Args::try_parse_from(["gendelbrot", "--image-size", "64", "64", "--fractal-formula", "z^3 + c"]).unwrap();
+    assert_eq!(options_from_args(&args).formula.map(|formula| formula.source().to_string()), Some("z^3 + c".to_string()));
+}
+
+#[test]
+fn test_device_info_report() {
+    let info = device::DeviceInfo {
+        name: "NVIDIA GeForce RTX 3080".to_string(),
+        compute_capability: Some((8, 6)),
+        memory_bytes: Some(10 << 30),
+        max_threads_per_block: Some(1024),
+    };
+    assert_eq!(
+        info.to_string(),
+        format!(
+            "CUDA device: NVIDIA GeForce RTX 3080\nCompute capability: 8.6\nMemory: 10240 MiB\nMax threads per block: 1024 (rendering with {})",
+            launch::THREADS_PER_BLOCK
+        )
+    );
+
+    // What the driver couldn't report is left out instead of guessed
+    let info = device::DeviceInfo {
+        compute_capability: None,
+        memory_bytes: None,
+        max_threads_per_block: None,
+        ..info
+    };
+    assert_eq!(
+        info.to_string(),
+        format!("CUDA device: NVIDIA GeForce RTX 3080\nRendering with {} threads per block", launch::THREADS_PER_BLOCK)
+    );
+}
+
+#[test]
+fn test_strip_metadata() {
+    // Renders with the extra {arguments} and returns the bytes of the written PNG
+    let write = |arguments: &[&str]| {
+        let mut command_line = vec!["gendelbrot", "--image-size", "32", "32"];
+        command_line.extend_from_slice(arguments);
+        let args = Args::try_parse_from(command_line).unwrap();
+        let options = options_from_args(&args);
+        let image = RenderOutput::new(build_mandelbrot_cpu(&options), options.color_type()).unwrap();
+        let path = std::env::temp_dir().join("gendelbrot_test_strip_metadata.png");
+        let params = embedded_params(&args, true);
+        write_output(&path, &image, &options, params.as_deref(), &EncoderRegistry::default()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bytes
+    };
+
+    // The embedded parameters hold arguments that don't change the image
+    let one_thread = write(&["--threads", "1", "--save-params-with-image"]);
+    let four_threads = write(&["--threads", "4", "--save-params-with-image"]);
+    assert_ne!(one_thread, four_threads);
+
+    // Without them the same image gives the same file
+    let stripped = write(&["--threads", "1", "--save-params-with-image", "--strip-metadata"]);
+    assert_eq!(stripped, write(&["--threads", "4", "--save-params-with-image", "--strip-metadata"]));
+    assert_eq!(stripped, write(&["--strip-metadata"]));
+    assert!(stripped.len() < one_thread.len());
+}
+
+#[test]
+fn test_scanline() {
+    let options = MandelbrotCpu {
+        threads: 4,
+        image_width: 64,
+        image_height: 48,
+        real_step: RADIUS / 64.0,
+        i_step: RADIUS / 48.0,
+        iterations: 100,
+        ..MandelbrotCpu::default()
+    };
+    let full = render_iterations(&options);
+
+    // A row and a column through the set give the same counts as in the full render
+    let pixels = scanline::Scanline::Row(24).pixels(&options).unwrap();
+    assert_eq!(scanline::scanline_counts(&options, &pixels), full[24 * 64..25 * 64]);
+    let pixels = scanline::Scanline::Column(40).pixels(&options).unwrap();
+    let column: Vec<u32> = (0..48).map(|row| full[row * 64 + 40]).collect();
+    let counts = scanline::scanline_counts(&options, &pixels);
+    assert_eq!(counts, column);
+
+    let mut out = vec![];
+    scanline::write_scanline(&options, &pixels, &counts, &mut out).unwrap();
+    let csv = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 1 + 48);
+    assert_eq!(lines[0], "column,row,re,im,count");
+    let point = options.pixel_point(5, 40);
+    assert_eq!(lines[6], format!("40,5,{},{},{}", point.real, point.imaginary, column[5]));
+
+    // The pixels are mapped through the inversion of the plane like in a full render
+    let inverted = MandelbrotCpu {
+        invert_plane: true,
+        ..options.clone()
+    };
+    let pixels = scanline::Scanline::Row(24).pixels(&inverted).unwrap();
+    let counts = scanline::scanline_counts(&inverted, &pixels);
+    assert_ne!(counts, full[24 * 64..25 * 64]);
+    let image = build_mandelbrot_cpu(&inverted);
+    for (column, &count) in counts.iter().enumerate() {
+        assert_eq!(count == 100, image[24 * 64 + column] == 0, "column {}", column);
+    }
+
+    // Lines outside of the image are reported
+    assert!(scanline::Scanline::Row(48).pixels(&options).is_err());
+    assert!(scanline::Scanline::Column(64).pixels(&options).is_err());
+    assert!(Args::try_parse_from(["gendelbrot", "--scanline", "1", "--scanline-col", "1"]).is_err());
+}
+
+// ==================================================
+// Golden images
+// ==================================================
+
+// Compares {image}, rendered with {options}, with the golden image tests/golden/{name}.png,
+// allowing the same differences on the edge of the set as the GPU tests. Comparing engines with
+// each other only catches them disagreeing, the golden images also catch all of them changing.
+// With UPDATE_GOLDENS=1 the golden image is overwritten with {image} instead, for changes that
+// are meant to change the renders.
+fn check_golden(name: &str, options: &MandelbrotCpu, image: &[u8]) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name));
+    let (width, height) = (options.image_width, options.image_height);
+    if std::env::var("UPDATE_GOLDENS").as_deref() == Ok("1") {
+        image::save_buffer(&path, image, width as u32, height as u32, options.color_type()).unwrap();
+        println!("Updated {:?}", path);
+        return;
     }
-}
\ No newline at end of file
+    let golden = image::open(&path).unwrap_or_else(|error| {
+        panic!("couldn't read {:?}: {}, run the tests with UPDATE_GOLDENS=1 to create it", path, error)
+    });
+    assert_eq!(
+        (golden.width() as usize, golden.height() as usize),
+        (width, height),
+        "{}: the golden image has a different size",
+        name
+    );
+    let golden = if options.bytes_per_pixel() == 1 { golden.into_luma8().into_raw() } else { golden.into_rgb8().into_raw() };
+    assert_images_similar(image, &golden, width, height, &format!("golden {}", name), (width * height / 1000).max(1), 1);
+}
+
+// The default view at {size} x {size}
+fn small_options(size: usize) -> MandelbrotCpu {
+    MandelbrotCpu {
+        image_width: size,
+        image_height: size,
+        real_step: RADIUS / size as f64,
+        i_step: RADIUS / size as f64,
+        ..MandelbrotCpu::default()
+    }
+}
+
+// The default view at 128 x 128
+fn golden_default_options() -> MandelbrotCpu {
+    MandelbrotCpu {
+        threads: 4,
+        ..small_options(128)
+    }
+}
+
+#[test]
+fn test_golden_default() {
+    let options = golden_default_options();
+    check_golden("default", &options, &build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_golden_seahorse_valley() {
+    // The spirals between the main cardioid and the period 2 bulb, around -0.7461 + 0.1133i
+    let options = MandelbrotCpu {
+        real_start: -0.75390625,
+        i_start: 0.12109375,
+        real_step: 1.0 / 8192.0,
+        i_step: 1.0 / 8192.0,
+        iterations: 200,
+        ..golden_default_options()
+    };
+    check_golden("seahorse_valley", &options, &build_mandelbrot_cpu(&options));
+}
+
+#[test]
+fn test_golden_julia() {
+    let options = MandelbrotCpu {
+        fractal: FractalKind::Julia { real: -0.8, imaginary: 0.156 },
+        iterations: 100,
+        ..golden_default_options()
+    };
+    check_golden("julia", &options, &build_mandelbrot_cpu(&options));
+}
@@ -0,0 +1,157 @@
+// An index of the partial images of a large render, for finding your way around a render too
+// large to open in one piece. Large renders are split into bands of rows with --row-range, so the
+// index is one column of cells from top to bottom, in the order the parts are given to --stitch.
+// Every cell is the part downsampled by the same factor, so the cells keep the proportions of the
+// parts, and is labeled with the number of the part. Only one part is loaded at a time.
+
+use crate::color::{self, Rgb};
+use crate::overlay::set_pixel;
+use image::ColorType;
+use std::ops::Range;
+
+// Width of the index in pixels, narrower parts aren't scaled up
+pub const INDEX_WIDTH: usize = 256;
+
+// Height in pixels of the divider between two cells
+pub const DIVIDER_HEIGHT: usize = 2;
+
+// Color of the divider between two cells
+const DIVIDER_COLOR: Rgb = Rgb([0x80, 0x80, 0x80]);
+
+// Every pixel of the font is drawn as LABEL_SCALE x LABEL_SCALE pixels
+const LABEL_SCALE: usize = 2;
+
+// Space in pixels around the digits of a label
+const LABEL_MARGIN: usize = 1;
+
+// The digits 0 to 9 in a 3 x 5 font, one row per byte with the leftmost pixel as the highest bit
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+// The index image along with where every part ended up in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileIndex {
+    pub image: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    pub color_type: ColorType,
+    // The rows of the index showing every part, in the order of the parts
+    pub cells: Vec<Range<usize>>,
+}
+
+// Returns the factor a part {width} pixels wide is downsampled by to fit in INDEX_WIDTH
+pub fn index_factor(width: usize) -> usize {
+    width.div_ceil(INDEX_WIDTH).max(1)
+}
+
+// Averages every {factor} x {factor} block of pixels of an {image} {width} x {height} pixels
+// large into one pixel in linear light. The blocks at the right and bottom edges can be smaller.
+// Returns the downsampled image along with its width and height.
+pub fn thumbnail(image: &[u8], width: usize, height: usize, bytes_per_pixel: usize, factor: usize) -> (Vec<u8>, usize, usize) {
+    let (thumbnail_width, thumbnail_height) = (width.div_ceil(factor), height.div_ceil(factor));
+    let mut thumbnail = Vec::with_capacity(thumbnail_width * thumbnail_height * bytes_per_pixel);
+    let mut samples = Vec::with_capacity(factor * factor);
+    for row in 0..thumbnail_height {
+        for column in 0..thumbnail_width {
+            for channel in 0..bytes_per_pixel {
+                samples.clear();
+                for y in row * factor..((row + 1) * factor).min(height) {
+                    for x in column * factor..((column + 1) * factor).min(width) {
+                        samples.push(image[(y * width + x) * bytes_per_pixel + channel]);
+                    }
+                }
+                thumbnail.push(color::average_linear(&samples));
+            }
+        }
+    }
+    (thumbnail, thumbnail_width, thumbnail_height)
+}
+
+// Draws {number} in white on a black box at the top left corner of the rows {rows} of an {image}
+// {width} pixels wide. Whatever doesn't fit in the rows is left out.
+fn draw_label(image: &mut [u8], width: usize, bytes_per_pixel: usize, rows: &Range<usize>, number: usize) {
+    let digits: Vec<usize> = number.to_string().bytes().map(|digit| (digit - b'0') as usize).collect();
+    let box_width = 2 * LABEL_MARGIN + (digits.len() * 4 - 1) * LABEL_SCALE;
+    let box_height = 2 * LABEL_MARGIN + 5 * LABEL_SCALE;
+    for y in 0..box_height.min(rows.len()) {
+        for x in 0..box_width.min(width) {
+            let lit = match (x.checked_sub(LABEL_MARGIN), y.checked_sub(LABEL_MARGIN)) {
+                (Some(x), Some(y)) if x < box_width - 2 * LABEL_MARGIN && y < box_height - 2 * LABEL_MARGIN => {
+                    let (column, font_row) = (x / LABEL_SCALE, y / LABEL_SCALE);
+                    // Every digit is 3 pixels wide with a pixel of space after it
+                    column % 4 < 3 && DIGITS[digits[column / 4]][font_row] & (0b100 >> (column % 4)) != 0
+                }
+                _ => false,
+            };
+            let color = if lit { Rgb([u8::MAX; 3]) } else { Rgb([0; 3]) };
+            set_pixel(image, width, bytes_per_pixel, x, rows.start + y, color);
+        }
+    }
+}
+
+// Builds the index of the partial images at {paths}, which have to be equally wide and of the
+// same color type like for --stitch
+pub fn build_tile_index(paths: &[String]) -> Result<TileIndex, String> {
+    let mut index = TileIndex {
+        image: vec![],
+        width: 0,
+        height: 0,
+        color_type: ColorType::L8,
+        cells: vec![],
+    };
+    let mut format = None;
+    for (number, path) in paths.iter().enumerate() {
+        let part = image::open(path)
+            .map_err(|error| format!("Couldn't open partial image {}: {}", path, error))?;
+        let color_type = if part.color().has_color() {
+            ColorType::Rgb8
+        } else {
+            ColorType::L8
+        };
+        let (width, height) = (part.width() as usize, part.height() as usize);
+        match format {
+            None => format = Some((width, color_type)),
+            Some(expected) if expected != (width, color_type) => {
+                return Err(format!(
+                    "Partial image {} is a {} pixels wide {:?} image, expected a {} pixels wide {:?} image",
+                    path, width, color_type, expected.0, expected.1
+                ));
+            }
+            _ => {}
+        }
+        let (pixels, bytes_per_pixel) = if color_type == ColorType::Rgb8 {
+            (part.into_rgb8().into_raw(), 3)
+        } else {
+            (part.into_luma8().into_raw(), 1)
+        };
+        let (cell, cell_width, cell_height) = thumbnail(&pixels, width, height, bytes_per_pixel, index_factor(width));
+        if number > 0 {
+            let start = index.image.len();
+            index.image.resize(start + cell_width * DIVIDER_HEIGHT * bytes_per_pixel, 0);
+            for y in 0..DIVIDER_HEIGHT {
+                for x in 0..cell_width {
+                    set_pixel(&mut index.image[start..], cell_width, bytes_per_pixel, x, y, DIVIDER_COLOR);
+                }
+            }
+            index.height += DIVIDER_HEIGHT;
+        }
+        let rows = index.height..index.height + cell_height;
+        index.image.extend_from_slice(&cell);
+        draw_label(&mut index.image, cell_width, bytes_per_pixel, &rows, number);
+        index.height += cell_height;
+        index.width = cell_width;
+        index.color_type = color_type;
+        index.cells.push(rows);
+    }
+    Ok(index)
+}
@@ -0,0 +1,47 @@
+// How long every phase of a run took, for --dump-timings-json, so performance regressions show up
+// in CI. Every phase starts where the one before it finished, so the phases add up to the whole
+// run.
+
+use crate::Engine;
+use std::time::{Duration, Instant};
+
+pub struct Timings {
+    started: Instant,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    // Starts timing the first phase
+    pub fn start() -> Timings {
+        let now = Instant::now();
+        Timings {
+            started: now,
+            last: now,
+            phases: vec![],
+        }
+    }
+
+    // Ends {phase}, which took the time since the previous phase ended
+    pub fn finish(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.phases.push((phase, now - self.last));
+        self.last = now;
+    }
+
+    // Returns the phases, rendered on {engine}, as a JSON object with the seconds every phase
+    // took and the total
+    pub fn to_json(&self, engine: Engine) -> String {
+        let phases: serde_json::Map<String, serde_json::Value> = self
+            .phases
+            .iter()
+            .map(|(phase, duration)| (phase.to_string(), duration.as_secs_f64().into()))
+            .collect();
+        let timings = serde_json::json!({
+            "engine": engine,
+            "phases": phases,
+            "total": (self.last - self.started).as_secs_f64(),
+        });
+        serde_json::to_string_pretty(&timings).unwrap()
+    }
+}
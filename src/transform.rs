@@ -0,0 +1,25 @@
+// Affine transforms of the sampling grid, for rotating, shearing, stretching and moving the view
+// in one go. The grid that --center and --size describe is laid out as usual, then every point is
+// moved by the transform around the center of the view before it's iterated.
+
+use crate::Complex;
+
+// A 2x3 affine matrix [a b e; c d f], applied around {center}: a point p of the view shows the
+// number center + [a b; c d] (p - center) + (e, f)
+#[derive(Debug, Clone)]
+pub struct Affine {
+    pub matrix: [f64; 6],
+    pub center: Complex,
+}
+
+impl Affine {
+    // Returns the number the point {point} of the view shows
+    pub fn apply(&self, point: &Complex) -> Complex {
+        let [a, b, c, d, e, f] = self.matrix;
+        let (x, y) = (point.real - self.center.real, point.imaginary - self.center.imaginary);
+        Complex::new(
+            self.center.real + a * x + b * y + e,
+            self.center.imaginary + c * x + d * y + f,
+        )
+    }
+}
@@ -0,0 +1,63 @@
+// Sanity checks for rendered images, to catch errors in how the image is put together
+// (threading, GPU chunks, row ranges) before the outputted file is trusted.
+
+use crate::{MandelbrotCpu, Shading};
+
+// Number of pixels recomputed by --validate-output
+pub const SPOT_CHECKS: usize = 64;
+
+// Checks that {image} is a complete render of {options}. Returns an error if the image has the
+// wrong size or contains values the renderer can't produce. Otherwise {spot_checks} pixels
+// spread over the image are recomputed, and the number of them that don't match is returned.
+// Pixels right on the edge of the set can legitimately differ because of rounding, so a few
+// mismatches are only worth a warning.
+pub fn validate_image(image: &[u8], options: &MandelbrotCpu, spot_checks: usize) -> Result<usize, String> {
+    let bytes_per_pixel = options.bytes_per_pixel();
+    let pixels = options.image_width * options.image_height;
+    let expected_len = pixels * bytes_per_pixel;
+    if image.len() != expected_len {
+        return Err(format!(
+            "expected {} bytes ({}x{} pixels, {} bytes per pixel) but got {}",
+            expected_len, options.image_width, options.image_height, bytes_per_pixel, image.len()
+        ));
+    }
+
+    // Without supersampling, flat shading only ever produces black and white pixels
+    if options.shading == Shading::Flat && options.samples <= 1 {
+        if let Some(index) = image.iter().position(|&value| value != 0 && value != u8::MAX) {
+            return Err(format!(
+                "pixel ({}, {}) has the value {}, which flat shading never produces",
+                index % options.image_width,
+                index / options.image_width,
+                image[index]
+            ));
+        }
+    }
+
+    if pixels == 0 {
+        return Ok(0);
+    }
+
+    // Recompute pixels picked by a simple xorshift generator, so the same pixels get checked
+    // every time
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut expected = vec![0; bytes_per_pixel];
+    let mut pixel_options = options.clone();
+    let mut mismatches = 0;
+    for _i in 0..spot_checks {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let index = (state % pixels as u64) as usize;
+        let j = index % options.image_width;
+        let i = index / options.image_width;
+
+        let point = options.pixel_point(i, j);
+        pixel_options.iterations = options.iterations_at(i, j);
+        pixel_options.render_pixel(point.real, point.imaginary, &mut expected);
+        if image[index * bytes_per_pixel..(index + 1) * bytes_per_pixel] != expected[..] {
+            mismatches += 1;
+        }
+    }
+    Ok(mismatches)
+}
@@ -0,0 +1,171 @@
+// Rendering through wgpu, for GPUs CUDA doesn't run on (AMD, Intel, Apple). The escape-time
+// loop runs as the compute shader in mandelbrot.wgsl, one invocation per pixel. WGSL has no
+// f64, so this calculates in f32, which runs out of precision at a zoom of around 1e-6.
+
+use crate::{progress, MandelbrotCpu};
+use wgpu::util::DeviceExt;
+
+// Number of pixels along each axis of a workgroup, has to match @workgroup_size in
+// mandelbrot.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+// Errors from rendering through wgpu
+#[derive(Debug, Clone, PartialEq)]
+pub enum WgpuError {
+    NoAdapter,
+    Device { cause: String },
+    TooLarge { width: usize, height: usize },
+    Retrieve { cause: String },
+}
+
+impl std::fmt::Display for WgpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WgpuError::NoAdapter => write!(f, "there is no GPU wgpu can render on"),
+            WgpuError::Device { cause } => write!(f, "couldn't open the GPU: {}", cause),
+            WgpuError::TooLarge { width, height } => {
+                write!(f, "a {}x{} image is too large for the GPU", width, height)
+            }
+            WgpuError::Retrieve { cause } => write!(f, "couldn't retrieve the image from the GPU: {}", cause),
+        }
+    }
+}
+
+impl std::error::Error for WgpuError {}
+
+async fn request_adapter() -> Option<wgpu::Adapter> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    instance.request_adapter(&wgpu::RequestAdapterOptions::default()).await
+}
+
+// Renders the image with flat shading on the GPU through wgpu
+pub fn build_mandelbrot_wgpu(options: &MandelbrotCpu) -> Result<Vec<u8>, WgpuError> {
+    pollster::block_on(render(options))
+}
+
+// Renders through wgpu, exiting with a clean message if the GPU fails
+pub fn build_mandelbrot_wgpu_or_exit(options: &MandelbrotCpu) -> Vec<u8> {
+    build_mandelbrot_wgpu(options).unwrap_or_else(|error| {
+        eprintln!("\nRendering through wgpu failed, {}", error);
+        std::process::exit(1);
+    })
+}
+
+async fn render(options: &MandelbrotCpu) -> Result<Vec<u8>, WgpuError> {
+    let adapter = request_adapter().await.ok_or(WgpuError::NoAdapter)?;
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                required_limits: adapter.limits(),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .map_err(|error| WgpuError::Device { cause: error.to_string() })?;
+
+    // Every pixel takes a u32, WGSL can't store single bytes
+    let size = (options.image_width * options.image_height * 4) as u64;
+    let limits = device.limits();
+    let groups_x = (options.image_width as u32).div_ceil(WORKGROUP_SIZE);
+    let groups_y = (options.image_height as u32).div_ceil(WORKGROUP_SIZE);
+    if size > (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+        || groups_x.max(groups_y) > limits.max_compute_workgroups_per_dimension
+        || options.image_width > u32::MAX as usize
+        || options.image_height > u32::MAX as usize
+    {
+        return Err(WgpuError::TooLarge {
+            width: options.image_width,
+            height: options.image_height,
+        });
+    }
+
+    // The fields of Params in mandelbrot.wgsl, in order
+    let mut params = vec![];
+    params.extend_from_slice(&(options.image_width as u32).to_le_bytes());
+    params.extend_from_slice(&(options.image_height as u32).to_le_bytes());
+    params.extend_from_slice(&options.iterations.to_le_bytes());
+    for value in [
+        options.bailout_sq,
+        options.real_start,
+        options.i_start,
+        options.real_step,
+        options.i_step,
+    ] {
+        params.extend_from_slice(&(value as f32).to_le_bytes());
+    }
+    let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("params"),
+        contents: &params,
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let image = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("image"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    // Storage buffers can't be read by the CPU, so the image is copied into this one
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("staging"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("mandelbrot.wgsl"));
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("mandelbrot"),
+        layout: None,
+        module: &shader,
+        entry_point: "main",
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: image.as_entire_binding(),
+            },
+        ],
+    });
+
+    progress::status(options.progress, "Generating Image...");
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(groups_x, groups_y, 1);
+    }
+    encoder.copy_buffer_to_buffer(&image, 0, &staging, 0, size);
+    queue.submit(Some(encoder.finish()));
+
+    progress::status(options.progress, "Waiting for GPU to finish...");
+    let slice = staging.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .map_err(|error| WgpuError::Retrieve { cause: error.to_string() })?
+        .map_err(|error| WgpuError::Retrieve { cause: error.to_string() })?;
+
+    let pixels = slice.get_mapped_range();
+    Ok(pixels
+        .chunks_exact(4)
+        .map(|pixel| u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]) as u8)
+        .collect())
+}